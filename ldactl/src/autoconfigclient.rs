@@ -1,23 +1,37 @@
-use crate::credential::{ClientSideId, LaunchDarklyCredential, RelayAutoConfigKey};
-use crate::message_event_source::MessageParseError;
+use crate::callbacks::Callbacks;
+use crate::coalesce::Coalescer;
+use crate::credential::{LaunchDarklyCredential, RelayAutoConfigKey};
+use crate::endpoint::Endpoint;
+use crate::filter::EnvironmentFilter;
+use crate::key_rotation::KeyRotation;
+use crate::message_event_source::{MessageParseError, MessageParser, ParseErrorPolicy};
 use crate::messages::{
-    DeleteEvent, EnvironmentConfig, EnvironmentKey, Message, PatchEvent, ProjectKey, PutData,
-    PutEvent,
+    DeleteEvent, EnvironmentConfig, EnvironmentField, EnvironmentId, EnvironmentKey,
+    FilterDeleteEvent, FilterId, FilterKey, FilterPatchEvent, Message, PatchEvent, PayloadFilter,
+    ProjectKey, PutData, PutEvent, Timestamp, Version,
 };
-use std::collections::hash_map::Entry;
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::stats::{AutoConfigStats, StatsRecorder};
+use crate::store::{Changed, EnvironmentStore, TrackedStore};
+use crate::tombstones::{Tombstones, DEFAULT_TOMBSTONE_RETENTION};
 use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
-use crate::eventsource::{EventSource, EventSourceBuilder, EventSourceError};
+use crate::eventsource::{
+    EventSource, EventSourceBuilder, EventSourceBuilderError, EventSourceError, ReconnectReason,
+    EXTRA_HEADERS_ENV,
+};
 
-use backoff::ExponentialBackoff;
-use futures::Stream;
+use futures::{Future, Stream};
 
 use miette::Diagnostic;
 use pin_project::pin_project;
-use reqwest::{Client, ClientBuilder, RequestBuilder, Url};
 use serde::Serialize;
 use thiserror::Error;
+use tokio_sse_codec::BytesStr;
 use tracing::{debug, debug_span, error, instrument, trace, warn, warn_span};
 
 #[derive(Debug, Error, Diagnostic)]
@@ -26,154 +40,901 @@ pub enum AutoConfigClientError {
     EventSourceError(#[from] EventSourceError),
     #[error("error parsing autoconfig event")]
     EventParseError(#[from] MessageParseError),
+    #[error("stream ended before the initial put was received")]
+    StreamEnded,
+    #[error("not initialized within {0:?}")]
+    #[diagnostic(help("check the credential and that the stream url is reachable"))]
+    InitializationTimeout(Duration),
 }
 
 #[pin_project]
 pub struct AutoConfigClient {
-    environments: HashMap<ClientSideId, EnvironmentConfig>,
-    #[pin]
-    event_source: Pin<Box<EventSource>>,
-    changes: VecDeque<ConfigChangeEvent>,
+    environments: TrackedStore,
+    // None when serving environments from a file
+    event_source: Option<Pin<Box<EventSource>>>,
+    // change events and the id of the message that caused them
+    changes: VecDeque<(Option<String>, ConfigChangeEvent)>,
+    // sequence number of the last change event yielded
+    sequence: u64,
     is_initialized: bool,
+    filter: EnvironmentFilter,
+    // fires when the next expiring sdk key expires, rescheduled whenever environments change
+    expiry_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+    reschedule_expiry: bool,
+    watch: tokio::sync::watch::Sender<Arc<HashMap<EnvironmentId, EnvironmentConfig>>>,
+    stats: StatsRecorder,
+    reconnect_delay_window: Duration,
+    min_reconnect_interval: Duration,
+    last_server_reconnect: Option<Instant>,
+    // fires when a reconnect requested by the server is due
+    reconnect_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+    conflicts: tokio::sync::broadcast::Sender<VersionConflict>,
+    created_at: Instant,
+    stale_after: Option<Duration>,
+    is_stale: bool,
+    // fires when the stream goes stale unless another frame arrives first
+    staleness_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+    // the next put replaces the environments even where its versions aren't newer
+    resync_pending: bool,
+    callbacks: Callbacks,
+    coalescer: Option<Coalescer>,
+    parse_error_policy: ParseErrorPolicy,
+    parser: MessageParser,
+    payload_filters: HashMap<FilterId, PayloadFilter>,
+    key_rotation: Option<KeyRotation>,
+    tombstones: Tombstones,
+    // ConfigChangeEvent::CredentialRotated was queued
+    rotation_reported: bool,
 }
 
 #[derive(Debug, Serialize, Clone)]
 #[serde(tag = "kind", content = "data", rename_all = "camelCase")]
+#[allow(clippy::large_enum_variant)]
 pub enum ConfigChangeEvent {
-    Initialized,
+    Initialized(InitializedSummary),
     Insert(EnvironmentConfig),
     // previous, current
     Update {
         previous: EnvironmentConfig,
         current: EnvironmentConfig,
+        /// Fields that differ between `previous` and `current`
+        changed: Vec<EnvironmentField>,
     },
     Delete(EnvironmentConfig),
+    /// Nothing, not even a heartbeat, was received for longer than
+    /// [`AutoConfigClient::stale_after`], the environments may be out of date
+    #[serde(rename_all = "camelCase")]
+    Stale {
+        #[serde(rename = "sinceLastFrameMs", serialize_with = "serialize_millis")]
+        since_last_frame: Duration,
+    },
+    /// A frame was received after [`ConfigChangeEvent::Stale`]
+    Fresh,
+    /// A payload filter was added to a project
+    FilterInsert(PayloadFilter),
+    FilterUpdate {
+        previous: PayloadFilter,
+        current: PayloadFilter,
+    },
+    FilterDelete(PayloadFilter),
+    /// The primary key was rejected and the client failed over to the secondary one, see
+    /// [`AutoConfigClient::key_rotation`]
+    CredentialRotated,
+}
+
+impl ConfigChangeEvent {
+    /// The `kind` it's serialized with, such as `insert` or `filterUpdate`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ConfigChangeEvent::Initialized(_) => "initialized",
+            ConfigChangeEvent::Insert(_) => "insert",
+            ConfigChangeEvent::Update { .. } => "update",
+            ConfigChangeEvent::Delete(_) => "delete",
+            ConfigChangeEvent::Stale { .. } => "stale",
+            ConfigChangeEvent::Fresh => "fresh",
+            ConfigChangeEvent::FilterInsert(_) => "filterInsert",
+            ConfigChangeEvent::FilterUpdate { .. } => "filterUpdate",
+            ConfigChangeEvent::FilterDelete(_) => "filterDelete",
+            ConfigChangeEvent::CredentialRotated => "credentialRotated",
+        }
+    }
+
+    /// Id of the environment that was inserted, updated or deleted, `None` for other events
+    pub fn env_id(&self) -> Option<&EnvironmentId> {
+        match self {
+            ConfigChangeEvent::Insert(env)
+            | ConfigChangeEvent::Update { current: env, .. }
+            | ConfigChangeEvent::Delete(env) => Some(&env.env_id),
+            _ => None,
+        }
+    }
+}
+
+/// A [`ConfigChangeEvent`] numbered in the order it was yielded, see
+/// [`AutoConfigClient::next_sequenced`]
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct SequencedChange {
+    /// Starts at 1 and increases by one for every change event, a gap means one was missed
+    pub sequence: u64,
+    /// Id of the stream message that caused the change, `None` for changes that didn't come
+    /// from a message, such as pruning expired sdk keys
+    pub message_id: Option<String>,
+    #[serde(flatten)]
+    pub change: ConfigChangeEvent,
 }
 
-static DEFAULT_ENDPOINT: &'static str = "https://stream.launchdarkly.com/relay_auto_config";
+/// What the client started out with, sent with [`ConfigChangeEvent::Initialized`]
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct InitializedSummary {
+    pub environment_count: usize,
+    /// Sorted and deduplicated
+    pub project_keys: Vec<String>,
+    /// Time from creating the client until the initial put was processed, `None` when
+    /// initialized from a file
+    #[serde(
+        rename = "connectLatencyMs",
+        serialize_with = "serialize_optional_millis"
+    )]
+    pub connect_latency: Option<Duration>,
+}
+
+impl InitializedSummary {
+    fn new(environments: &dyn EnvironmentStore, connect_latency: Option<Duration>) -> Self {
+        let mut project_keys: Vec<String> = environments
+            .iter()
+            .map(|(_, env)| env.proj_key.to_string())
+            .collect();
+        project_keys.sort_unstable();
+        project_keys.dedup();
+        Self {
+            environment_count: environments.len(),
+            project_keys,
+            connect_latency,
+        }
+    }
+}
+
+fn serialize_millis<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+fn serialize_optional_millis<S>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match duration {
+        Some(duration) => serializer.serialize_some(&(duration.as_millis() as u64)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A message that disagrees with the stored environments, see
+/// [`AutoConfigClient::subscribe_conflicts`]
+///
+/// These are ignored by the client, but a steady stream of them points to events arriving out
+/// of order or to the stream and the stored environments having diverged.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum VersionConflict {
+    /// A patch wasn't newer than the stored environment
+    #[serde(rename_all = "camelCase")]
+    StalePatch {
+        env_id: EnvironmentId,
+        stored_version: Version,
+        received_version: Version,
+    },
+    /// A delete wasn't newer than the stored environment
+    #[serde(rename_all = "camelCase")]
+    StaleDelete {
+        env_id: EnvironmentId,
+        stored_version: Version,
+        received_version: Version,
+    },
+    /// A delete referenced an environment that isn't stored
+    #[serde(rename_all = "camelCase")]
+    UnknownDelete {
+        env_id: EnvironmentId,
+        received_version: Version,
+    },
+    /// A patch wasn't newer than the delete of its environment, see
+    /// [`AutoConfigClient::tombstone_retention`]
+    #[serde(rename_all = "camelCase")]
+    PatchAfterDelete {
+        env_id: EnvironmentId,
+        deleted_version: Version,
+        received_version: Version,
+    },
+}
+
+impl VersionConflict {
+    fn report(self, conflicts: &tokio::sync::broadcast::Sender<VersionConflict>) {
+        warn!(conflict=?self, "received message that conflicts with stored environments");
+        // nobody subscribed, the log is all there is
+        let _ = conflicts.send(self);
+    }
+}
 
 impl AutoConfigClient {
-    #[instrument(skip(credential), fields(credential=%credential, endpoint=%DEFAULT_ENDPOINT))]
-    pub fn new(credential: RelayAutoConfigKey) -> Self {
-        let event_source = EventSourceBuilder::get(Url::parse(DEFAULT_ENDPOINT).unwrap())
-            .authorization(credential.as_str())
-            .build()
-            .unwrap();
-        Self::from_event_source(event_source)
+    #[instrument(skip(credential), fields(credential=%credential))]
+    pub fn new(credential: RelayAutoConfigKey) -> Result<Self, EventSourceBuilderError> {
+        Self::with_endpoint(credential, Endpoint::default())
+    }
+
+    /// Streams from another LaunchDarkly instance, such as [`Endpoint::Eu`]
+    ///
+    /// Fails if [`EXTRA_HEADERS_ENV`] is set but isn't a valid list of headers
+    #[instrument(skip(credential), fields(credential=%credential, %endpoint))]
+    pub fn with_endpoint(
+        credential: RelayAutoConfigKey,
+        endpoint: Endpoint,
+    ) -> Result<Self, EventSourceBuilderError> {
+        Ok(Self::from_event_source(
+            Self::event_source_builder(&credential, &endpoint).build()?,
+        ))
+    }
+
+    /// Picks up where a previous client left off, see [`AutoConfigClient::snapshot`]
+    ///
+    /// The stream resumes with the snapshot's last event id and the first put is merged into
+    /// the restored environments. [`ConfigChangeEvent::Initialized`] is still sent once it's
+    /// merged, but only environments that changed in the meantime produce change events.
+    #[instrument(skip(credential, snapshot), fields(credential=%credential))]
+    pub fn restore(
+        credential: RelayAutoConfigKey,
+        snapshot: Snapshot,
+    ) -> Result<Self, EventSourceBuilderError> {
+        Self::restore_from_builder(
+            Self::event_source_builder(&credential, &Endpoint::default()),
+            snapshot,
+        )
+    }
+
+    /// Connects with the primary key and fails over to the secondary one once it's rejected
+    #[instrument(skip(primary, secondary), fields(%primary, %secondary, %endpoint))]
+    pub fn with_secondary_key(
+        primary: RelayAutoConfigKey,
+        secondary: RelayAutoConfigKey,
+        endpoint: Endpoint,
+    ) -> Result<Self, EventSourceBuilderError> {
+        let rotation = KeyRotation::new(primary, secondary);
+        Ok(Self::from_event_source(
+            Self::rotating_event_source_builder(&rotation, &endpoint).build()?,
+        )
+        .key_rotation(rotation))
+    }
+
+    pub fn restore_from_builder(
+        builder: EventSourceBuilder,
+        snapshot: Snapshot,
+    ) -> Result<Self, EventSourceBuilderError> {
+        debug!(
+            environment_count = snapshot.environments.len(),
+            last_event_id = snapshot.last_event_id.as_deref(),
+            "restoring from snapshot"
+        );
+        let event_source = builder
+            .last_event(snapshot.last_event_id.map(BytesStr::from))
+            .build()?;
+        let mut client = Self::from_event_source(event_source);
+        client.replace_environments(snapshot.environments);
+        Ok(client)
+    }
+
+    /// Event source for the stream at `endpoint`, for [`AutoConfigClient::restore_from_builder`]
+    /// and [`AutoConfigClient::from_event_source`]
+    pub fn event_source_builder(
+        credential: &RelayAutoConfigKey,
+        endpoint: &Endpoint,
+    ) -> EventSourceBuilder {
+        EventSourceBuilder::get(endpoint.stream_url())
+            .authorization(credential.expose_secret())
+            .env_headers(EXTRA_HEADERS_ENV)
     }
 
-    pub fn from_request(request: reqwest::Request) {
-        Self::from_event_source(EventSourceBuilder::from_request(request).build().unwrap());
+    /// Like [`AutoConfigClient::event_source_builder`], but authorizes with `rotation`. Pass the
+    /// same rotation to [`AutoConfigClient::key_rotation`] to hear about the failover
+    pub fn rotating_event_source_builder(
+        rotation: &KeyRotation,
+        endpoint: &Endpoint,
+    ) -> EventSourceBuilder {
+        EventSourceBuilder::get(endpoint.stream_url())
+            .authenticator(rotation.clone())
+            .env_headers(EXTRA_HEADERS_ENV)
+    }
+
+    pub fn from_request(request: reqwest::Request) -> Result<Self, EventSourceBuilderError> {
+        Ok(Self::from_event_source(
+            EventSourceBuilder::from_request(request).build()?,
+        ))
     }
 
     pub fn from_event_source(event_source: EventSource) -> Self {
+        Self::with_event_source(Some(Box::pin(event_source)))
+    }
+
+    /// Serves the given environments without ever connecting to LaunchDarkly
+    ///
+    /// [`ConfigChangeEvent::Initialized`] and an insert for every environment are yielded like
+    /// after the initial put, after that the stream stays pending. Expiring sdk keys are still
+    /// pruned when they expire.
+    pub fn offline(environments: HashMap<EnvironmentId, EnvironmentConfig>) -> Self {
+        Self::with_event_source(None).bootstrap(environments)
+    }
+
+    /// Starts from the given environments instead of waiting for the stream to connect
+    ///
+    /// [`ConfigChangeEvent::Initialized`] and an insert for every environment are yielded right
+    /// away. The initial put from the stream is then merged on top by version, so only
+    /// environments that changed since produce change events, and environments missing from it
+    /// are deleted.
+    pub fn bootstrap(mut self, environments: HashMap<EnvironmentId, EnvironmentConfig>) -> Self {
+        self.replace_environments(environments);
+        self.changes.push_back((
+            None,
+            ConfigChangeEvent::Initialized(InitializedSummary::new(&self.environments, None)),
+        ));
+        self.generate_init_changes();
+        self.is_initialized = true;
+        self
+    }
+
+    /// Bootstraps from a file written by `ldactl --output-file`, see
+    /// [`AutoConfigClient::bootstrap`]
+    #[instrument(skip_all, fields(path=%path.as_ref().display()))]
+    pub fn bootstrap_from_file(
+        self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, SnapshotError> {
+        let environments: HashMap<EnvironmentId, EnvironmentConfig> =
+            crate::snapshot::read_json(path.as_ref())?;
+        debug!(
+            environment_count = environments.len(),
+            "loaded environments from file"
+        );
+        Ok(self.bootstrap(environments))
+    }
+
+    /// Serves the environments from a file written by `ldactl --output-file`, see
+    /// [`AutoConfigClient::offline`]
+    #[instrument(skip_all, fields(path=%path.as_ref().display()))]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, SnapshotError> {
+        Self::with_event_source(None).bootstrap_from_file(path)
+    }
+
+    fn with_event_source(event_source: Option<Pin<Box<EventSource>>>) -> Self {
         Self {
-            environments: HashMap::new(),
-            event_source: Box::pin(event_source),
+            environments: TrackedStore::new(Box::new(HashMap::new())),
+            event_source,
             changes: VecDeque::new(),
+            sequence: 0,
             is_initialized: false,
+            filter: EnvironmentFilter::default(),
+            expiry_timer: None,
+            reschedule_expiry: false,
+            watch: tokio::sync::watch::channel(Arc::new(HashMap::new())).0,
+            stats: StatsRecorder::default(),
+            reconnect_delay_window: Duration::ZERO,
+            min_reconnect_interval: Duration::ZERO,
+            last_server_reconnect: None,
+            reconnect_timer: None,
+            conflicts: tokio::sync::broadcast::channel(64).0,
+            created_at: Instant::now(),
+            stale_after: None,
+            is_stale: false,
+            staleness_timer: None,
+            resync_pending: false,
+            callbacks: Callbacks::default(),
+            coalescer: None,
+            parse_error_policy: ParseErrorPolicy::default(),
+            parser: MessageParser::default(),
+            payload_filters: HashMap::new(),
+            key_rotation: None,
+            tombstones: Tombstones::new(DEFAULT_TOMBSTONE_RETENTION),
+            rotation_reported: false,
+        }
+    }
+    /// Only keep environments matching `filter`, others never produce change events.
+    /// Environments already stored that don't match are dropped
+    pub fn filter(mut self, filter: EnvironmentFilter) -> Self {
+        self.filter = filter;
+        let filter = &self.filter;
+        self.environments.retain(&mut |env| filter.matches(env));
+        self.changes.retain(|(_, change)| match change {
+            ConfigChangeEvent::Insert(env) => self.filter.matches(env),
+            _ => true,
+        });
+        for (_, change) in self.changes.iter_mut() {
+            if let ConfigChangeEvent::Initialized(summary) = change {
+                *summary = InitializedSummary::new(&self.environments, summary.connect_latency);
+            }
         }
+        self.publish();
+        self
     }
+    /// Waits a random delay of up to `window` before following a `reconnect` message
+    ///
+    /// LaunchDarkly sends reconnects to every client at once, spreading them out keeps a fleet
+    /// of clients from reconnecting in lockstep. Reconnects received while one is already
+    /// scheduled are dropped. Defaults to reconnecting immediately.
+    pub fn reconnect_delay_window(mut self, window: Duration) -> Self {
+        self.reconnect_delay_window = window;
+        self
+    }
+    /// Follows at most one `reconnect` message per `interval`, later ones are delayed until
+    /// the interval has passed
+    pub fn min_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.min_reconnect_interval = interval;
+        self
+    }
+    /// Yields [`ConfigChangeEvent::Stale`] once nothing was received for `threshold`, and
+    /// [`ConfigChangeEvent::Fresh`] when the next frame arrives
+    ///
+    /// LaunchDarkly sends heartbeat comments while the stream is idle, so this should be a
+    /// few times the heartbeat interval.
+    pub fn stale_after(mut self, threshold: Duration) -> Self {
+        self.stale_after = Some(threshold);
+        self
+    }
+    /// What to do with events that can't be parsed, such as an event type added to the stream
+    /// after this version was released. Defaults to [`ParseErrorPolicy::Fail`]
+    pub fn on_parse_error(mut self, policy: ParseErrorPolicy) -> Self {
+        self.parse_error_policy = policy;
+        self
+    }
+    /// How long deleted environments are remembered, 5 minutes by default
+    ///
+    /// A patch for a deleted environment that isn't newer than the delete is ignored and
+    /// reported as [`VersionConflict::PatchAfterDelete`] instead of adding the environment
+    /// back. `Duration::ZERO` forgets deletes right away.
+    pub fn tombstone_retention(mut self, retention: Duration) -> Self {
+        self.tombstones.set_retention(retention);
+        self
+    }
+
+    /// Parses events with `parser`, such as to accept a newer protocol version
+    pub fn message_parser(mut self, parser: MessageParser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Yields [`ConfigChangeEvent::CredentialRotated`] once `rotation` fails over to its
+    /// secondary key, see [`AutoConfigClient::rotating_event_source_builder`]
+    pub fn key_rotation(mut self, rotation: KeyRotation) -> Self {
+        self.key_rotation = Some(rotation);
+        self
+    }
+
+    /// Whether [`ConfigChangeEvent::Stale`] was yielded without a [`ConfigChangeEvent::Fresh`]
+    /// since
+    pub fn is_stale(&self) -> bool {
+        self.is_stale
+    }
+    /// Latest environments, updated after every event that changes them
+    ///
+    /// Receivers share one copy of the map per update, so any number of tasks can watch it
+    /// without consuming the change events.
+    pub fn subscribe(
+        &self,
+    ) -> tokio::sync::watch::Receiver<Arc<HashMap<EnvironmentId, EnvironmentConfig>>> {
+        // nothing is published while nobody's watching, catch up first
+        if self.watch.receiver_count() == 0 {
+            self.watch
+                .send_replace(Arc::new(self.environments.iter().collect()));
+        }
+        self.watch.subscribe()
+    }
+    /// Shares the environments with subscribers after they changed
+    ///
+    /// Only the environments that changed are copied into the published map, and only if
+    /// there are subscribers.
+    fn publish(&mut self) {
+        self.reschedule_expiry = true;
+        let changed = self.environments.take_changed();
+        if self.watch.receiver_count() == 0 {
+            return;
+        }
+        let environments = &self.environments;
+        match changed {
+            Changed::All => {
+                self.watch
+                    .send_replace(Arc::new(environments.iter().collect()));
+            }
+            Changed::Some(env_ids) if env_ids.is_empty() => {}
+            Changed::Some(env_ids) => self.watch.send_modify(|published| {
+                // copies the map only if a receiver still holds on to the previous one
+                let published = Arc::make_mut(published);
+                for env_id in env_ids {
+                    match environments.get(&env_id) {
+                        Some(env) => published.insert(env_id, env),
+                        None => published.remove(&env_id),
+                    };
+                }
+            }),
+        }
+    }
+    /// Messages that were ignored because they conflict with the stored environments
+    ///
+    /// Conflicts are also logged as warnings. Slow receivers miss conflicts once more than 64
+    /// are queued, see [`tokio::sync::broadcast`].
+    pub fn subscribe_conflicts(&self) -> tokio::sync::broadcast::Receiver<VersionConflict> {
+        self.conflicts.subscribe()
+    }
+    /// Whether the initial put from the server has been processed
+    pub fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    /// Reads the stream until the initial put has been processed, or until `timeout` elapses
+    ///
+    /// Change events read in the meantime, including [`ConfigChangeEvent::Initialized`], are
+    /// kept and still yielded by the stream afterwards.
+    #[instrument(skip(self))]
+    pub async fn wait_until_initialized(
+        mut self: Pin<&mut Self>,
+        timeout: Option<Duration>,
+    ) -> Result<(), AutoConfigClientError> {
+        let initialized = futures::future::poll_fn(|cx| loop {
+            if self.is_initialized {
+                return Poll::Ready(Ok(()));
+            }
+            if self.as_mut().poll_reconnect(cx).is_ready() {
+                continue;
+            }
+            match futures::ready!(self.as_mut().poll_message(cx)) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                None => return Poll::Ready(Err(AutoConfigClientError::StreamEnded)),
+            }
+        });
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, initialized)
+                .await
+                .map_err(|_| AutoConfigClientError::InitializationTimeout(timeout))?,
+            None => initialized.await,
+        }
+    }
+
+    /// Message counters and processing time, including the event source's connection stats
+    pub fn stats(&self) -> AutoConfigStats {
+        self.stats.snapshot(
+            self.event_source
+                .as_ref()
+                .map(|event_source| event_source.stats()),
+        )
+    }
+
+    /// Reconnects without `Last-Event-ID` and replaces the environments with the next put
+    ///
+    /// Stored environments that differ from the put are updated even if their version isn't
+    /// older, and those missing from it are deleted, so the change events bring consumers back
+    /// in line with LaunchDarkly. Does nothing for offline clients.
+    #[instrument(skip(self))]
+    pub fn resync(mut self: Pin<&mut Self>) {
+        let this = self.as_mut().get_mut();
+        let event_source = match this.event_source.as_mut() {
+            Some(event_source) => event_source,
+            None => {
+                debug!("ignoring resync of offline client");
+                return;
+            }
+        };
+        debug!("resyncing with a full put");
+        event_source.as_mut().clear_last_event_id();
+        event_source
+            .as_mut()
+            .reconnect(ReconnectReason::OperatorRequested);
+        this.resync_pending = true;
+    }
+
+    /// Holds back change events until their environment saw no changes for `window`, and
+    /// merges the ones in between into a single event
+    ///
+    /// A burst of updates to one environment turns into one update from the first previous
+    /// version to the last, and an environment that's added and removed again produces no
+    /// events at all. The environments themselves are still updated right away, and the
+    /// initial put is never held back.
+    pub fn coalesce(mut self, window: Duration) -> Self {
+        self.coalescer = Some(Coalescer::new(window));
+        self
+    }
+    /// Calls `f` with every environment that's added
+    ///
+    /// Callbacks run as change events are yielded, either by consuming the stream or by
+    /// [`AutoConfigClient::run`], so they should return quickly.
+    pub fn on_insert(mut self, f: impl FnMut(&EnvironmentConfig) + Send + 'static) -> Self {
+        self.callbacks.on_insert(f);
+        self
+    }
+    /// Calls `f` with the previous and current version of every environment that's updated,
+    /// and the fields that changed, see [`AutoConfigClient::on_insert`]
+    pub fn on_update(
+        mut self,
+        f: impl FnMut(&EnvironmentConfig, &EnvironmentConfig, &[EnvironmentField]) + Send + 'static,
+    ) -> Self {
+        self.callbacks.on_update(f);
+        self
+    }
+    /// Calls `f` with every environment that's removed, see [`AutoConfigClient::on_insert`]
+    pub fn on_delete(mut self, f: impl FnMut(&EnvironmentConfig) + Send + 'static) -> Self {
+        self.callbacks.on_delete(f);
+        self
+    }
+    /// Consumes the stream until it ends, calling the registered callbacks along the way
+    ///
+    /// Returns the first error, such as a message that couldn't be parsed.
+    pub async fn run(self) -> Result<(), AutoConfigClientError> {
+        let client = self;
+        futures::pin_mut!(client);
+        while let Some(change) = futures::StreamExt::next(&mut client).await {
+            change?;
+        }
+        Ok(())
+    }
+
+    /// Like [`futures::StreamExt::next`], but with the change event's sequence number and
+    /// message id
+    ///
+    /// Both share one sequence, so mixing them leaves gaps in the numbers seen here.
+    pub async fn next_sequenced(
+        mut self: Pin<&mut Self>,
+    ) -> Option<Result<SequencedChange, AutoConfigClientError>> {
+        futures::future::poll_fn(|cx| self.as_mut().poll_next_sequenced(cx)).await
+    }
+
+    /// Polls for the next change event with its sequence number, see
+    /// [`AutoConfigClient::next_sequenced`]
+    pub fn poll_next_sequenced(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<SequencedChange, AutoConfigClientError>>> {
+        let span = debug_span!("event");
+        let _span = span.enter();
+        loop {
+            let this = self.as_mut().project();
+            if let Some((message_id, change)) = this.changes.pop_front() {
+                this.callbacks.dispatch(&change);
+                *this.sequence += 1;
+                return Poll::Ready(Some(Ok(SequencedChange {
+                    sequence: *this.sequence,
+                    message_id,
+                    change,
+                })));
+            }
+            if self.as_mut().poll_expired(cx).is_ready() {
+                continue;
+            }
+            if self.as_mut().poll_reconnect(cx).is_ready() {
+                continue;
+            }
+            if let Some(coalescer) = self.as_mut().get_mut().coalescer.as_mut() {
+                if let Poll::Ready(due) = coalescer.poll_due(cx) {
+                    self.as_mut().get_mut().changes.extend(due);
+                    continue;
+                }
+            }
+            match self.as_mut().poll_message(cx) {
+                Poll::Ready(Some(Ok(()))) => {}
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    let this = self.as_mut().get_mut();
+                    match this.coalescer.as_mut().map(Coalescer::drain) {
+                        Some(held_back) if !held_back.is_empty() => {
+                            this.changes.extend(held_back);
+                            continue;
+                        }
+                        _ => return Poll::Ready(None),
+                    }
+                }
+                // checked after reading so heartbeats that were just read count as frames
+                Poll::Pending => futures::ready!(self.as_mut().poll_staleness(cx)),
+            }
+        }
+    }
+
+    /// Current environments and last event id, for [`AutoConfigClient::restore`]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            environments: self.environments.iter().collect(),
+            last_event_id: self
+                .event_source
+                .as_ref()
+                .and_then(|event_source| event_source.last_event_id())
+                .map(|id| id.to_string()),
+        }
+    }
+    /// Copy of the environments in the store, shared with [`AutoConfigClient::subscribe`]
     #[instrument(skip(self), fields(environment_count=self.environments.len()))]
-    pub fn environments(&self) -> &HashMap<ClientSideId, EnvironmentConfig> {
-        &self.environments
+    pub fn environments(&self) -> Arc<HashMap<EnvironmentId, EnvironmentConfig>> {
+        if self.watch.receiver_count() == 0 {
+            return Arc::new(self.environments.iter().collect());
+        }
+        self.watch.borrow().clone()
     }
+    /// Replaces the in-memory `HashMap` the environments are kept in
+    ///
+    /// Environments already in `store` are treated like a restored snapshot: the first put
+    /// is merged into them, so only environments that changed produce change events.
+    pub fn with_store(mut self, store: impl EnvironmentStore + 'static) -> Self {
+        self.environments = TrackedStore::new(Box::new(store));
+        let filter = &self.filter;
+        self.environments.retain(&mut |env| filter.matches(env));
+        self.publish();
+        self
+    }
+    /// Environments in `project_key`, matched ignoring ASCII case
     #[instrument(skip(self))]
     pub fn by_project_key(
         &self,
         project_key: ProjectKey,
-    ) -> impl Iterator<Item = &EnvironmentConfig> + '_ {
-        self.environments()
+    ) -> impl Iterator<Item = EnvironmentConfig> + '_ {
+        self.environments
+            .iter()
+            .map(|(_, env)| env)
+            .filter(move |env| env.proj_key.eq_ignore_case(&project_key))
+    }
+
+    /// Payload filters in the projects that match [`AutoConfigClient::filter`]
+    pub fn payload_filters(&self) -> impl Iterator<Item = &PayloadFilter> + '_ {
+        self.payload_filters.values()
+    }
+
+    /// Keys of the payload filters defined in `project_key`, sorted
+    pub fn filter_keys(&self, project_key: &ProjectKey) -> Vec<FilterKey> {
+        let mut keys: Vec<FilterKey> = self
+            .payload_filters
             .values()
-            .filter(move |env| env.proj_key == project_key)
+            .filter(|filter| filter.proj_key.eq_ignore_case(project_key))
+            .map(|filter| filter.key.clone())
+            .collect();
+        keys.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        keys
     }
 
+    /// Looks up an environment by its keys, matched ignoring ASCII case
     #[instrument(skip(self))]
     pub fn get_environment(
         &self,
         project_key: ProjectKey,
         env_key: EnvironmentKey,
-    ) -> Option<&EnvironmentConfig> {
+    ) -> Option<EnvironmentConfig> {
         self.by_project_key(project_key)
-            .find(move |env| env.env_key == env_key)
+            .find(move |env| env.env_key.eq_ignore_case(&env_key))
     }
 
     #[instrument(skip(self, environments))]
-    pub fn replace_environments(&mut self, environments: HashMap<ClientSideId, EnvironmentConfig>) {
+    pub fn replace_environments(
+        &mut self,
+        environments: HashMap<EnvironmentId, EnvironmentConfig>,
+    ) {
         debug!(
             environment_count = environments.len(),
             "replacing environments"
         );
-        self.environments = environments;
+        self.environments.clear();
+        for (env_id, env) in environments {
+            if self.filter.matches(&env) {
+                self.environments.upsert(env_id, env);
+            }
+        }
+        self.publish();
     }
     fn generate_init_changes(&mut self) {
-        for env in self.environments.values() {
+        for (_, env) in self.environments.iter() {
             self.changes
-                .push_back(ConfigChangeEvent::Insert(env.clone()));
+                .push_back((None, ConfigChangeEvent::Insert(env)));
         }
     }
     #[instrument(skip(self, environments))]
-    pub fn load_environments(&mut self, environments: HashMap<ClientSideId, EnvironmentConfig>) {
+    pub fn load_environments(&mut self, environments: HashMap<EnvironmentId, EnvironmentConfig>) {
         debug!(
             environment_count = environments.len(),
             "loading environments"
         );
         if self.environments.is_empty() {
             debug!("initialized in-memory-cache");
-            self.environments = environments;
+            self.replace_environments(environments);
             return;
         }
 
         for (key, value) in environments {
-            match self.environments.entry(key) {
-                Entry::Occupied(mut entry) => {
+            if !self.filter.matches(&value) {
+                continue;
+            }
+            match self.environments.get(&key) {
+                Some(existing) => {
                     let span = debug_span!("merge", env_id = %value.env_id, proj_key=%value.proj_key, env_key=%value.env_key, received_version=%value.version);
                     let _enter = span.enter();
-                    let existing = entry.get_mut();
-                    if existing.version < value.version {
+                    if value.version.is_newer_than(existing.version) {
                         debug!("updating environment");
-                        *existing = value;
+                        self.environments.upsert(key, value);
                     } else {
                         debug!("ignoring environment update");
                     }
                 }
-                Entry::Vacant(entry) => {
+                None => {
                     debug!("adding environment");
-                    entry.insert(value);
+                    self.environments.upsert(key, value);
                 }
             }
         }
+        self.publish();
     }
 
     #[instrument(level= "debug", skip(source, value), fields(proj_key=%value.proj_key, env_key=%value.env_key, received_version=%value.version))]
     fn update_environment(
-        source: &mut HashMap<ClientSideId, EnvironmentConfig>,
-        env_id: ClientSideId,
+        source: &mut dyn EnvironmentStore,
+        env_id: EnvironmentId,
         value: EnvironmentConfig,
-    ) -> Option<ConfigChangeEvent> {
+        force: bool,
+    ) -> Result<ConfigChangeEvent, Version> {
         debug_assert!(env_id == value.env_id);
-        match source.entry(env_id) {
-            Entry::Occupied(mut entry) => {
-                let existing = entry.get_mut();
-                if existing.version < value.version {
+        match source.get(&env_id) {
+            Some(existing) => {
+                if value.version.is_newer_than(existing.version) || (force && existing != value) {
                     debug!("updating environment");
-                    let previous_value = entry.insert(value.clone());
-                    Some(ConfigChangeEvent::Update {
-                        previous: previous_value,
+                    source.upsert(env_id, value.clone());
+                    Ok(ConfigChangeEvent::Update {
+                        changed: value.diff(&existing),
+                        previous: existing,
                         current: value,
                     })
                 } else {
                     debug!("ignoring environment update");
-                    None
+                    Err(existing.version)
                 }
             }
-            Entry::Vacant(entry) => {
+            None => {
                 debug!("adding environment");
-                entry.insert(value.clone());
-                Some(ConfigChangeEvent::Insert(value))
+                source.upsert(env_id, value.clone());
+                Ok(ConfigChangeEvent::Insert(value))
             }
         }
     }
+    /// Stores the payload filters from a put, those it doesn't have were deleted
+    fn replace_payload_filters(
+        stored: &mut HashMap<FilterId, PayloadFilter>,
+        environment_filter: &EnvironmentFilter,
+        mut filters: HashMap<FilterId, PayloadFilter>,
+    ) -> Vec<ConfigChangeEvent> {
+        filters.retain(|_, filter| environment_filter.matches_project(&filter.proj_key));
+        let removed: Vec<FilterId> = stored
+            .keys()
+            .filter(|filter_id| !filters.contains_key(filter_id))
+            .cloned()
+            .collect();
+        let mut changes = Vec::new();
+        for (filter_id, filter) in filters {
+            match stored.insert(filter_id, filter.clone()) {
+                None => changes.push(ConfigChangeEvent::FilterInsert(filter)),
+                Some(previous) if previous != filter => {
+                    changes.push(ConfigChangeEvent::FilterUpdate {
+                        previous,
+                        current: filter,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for filter_id in removed {
+            changes.extend(
+                stored
+                    .remove(&filter_id)
+                    .map(ConfigChangeEvent::FilterDelete),
+            );
+        }
+        changes
+    }
     #[instrument(skip(self, msg))]
     fn process_message(
         mut self: std::pin::Pin<&mut Self>,
@@ -184,44 +945,80 @@ impl AutoConfigClient {
         match msg {
             Message::Put(PutEvent {
                 path,
-                data: PutData { environments },
+                data:
+                    PutData {
+                        mut environments,
+                        filters,
+                    },
             }) if path == "/" => {
                 let span = debug_span!("put", path=?path, environment_count=?environments.len());
                 let _enter = span.enter();
-                let changes = if this.environments.is_empty() {
+                let resync = std::mem::take(this.resync_pending);
+                environments.retain(|_, env| this.filter.matches(env));
+                for env_id in environments.keys() {
+                    this.tombstones.remove(env_id);
+                }
+                let mut changes = if this.environments.is_empty() {
                     debug!("initializing in-memory cache");
 
-                    let is_initialized = *this.is_initialized;
-                    let mut changes = if is_initialized {
-                        VecDeque::with_capacity(environments.len())
-                    } else {
-                        let mut c = VecDeque::with_capacity(environments.len() + 1);
-                        c.push_back(ConfigChangeEvent::Initialized);
-                        c
-                    };
-                    *this.environments = environments;
-
-                    changes.extend(
-                        this.environments
-                            .values()
-                            .map(|env| ConfigChangeEvent::Insert(env.clone())),
-                    );
-                    if is_initialized {
-                        *this.is_initialized = true;
+                    let mut changes = VecDeque::with_capacity(environments.len() + 1);
+                    for (env_id, env) in environments {
+                        this.environments.upsert(env_id, env.clone());
+                        changes.push_back(ConfigChangeEvent::Insert(env));
                     }
+                    if !*this.is_initialized {
+                        changes.push_front(ConfigChangeEvent::Initialized(
+                            InitializedSummary::new(
+                                &*this.environments,
+                                Some(this.created_at.elapsed()),
+                            ),
+                        ));
+                    }
+
+                    *this.is_initialized = true;
                     changes
                 } else {
                     trace!("merging environments into in-memory cache");
                     let mut changes = VecDeque::new();
+                    // restored from a snapshot, this is the first put from the server
+                    let newly_initialized = !*this.is_initialized;
+                    *this.is_initialized = true;
+                    // a put replaces everything, environments it doesn't have were deleted
+                    let removed: Vec<EnvironmentId> = this
+                        .environments
+                        .iter()
+                        .map(|(env_id, _)| env_id)
+                        .filter(|env_id| !environments.contains_key(env_id))
+                        .collect();
                     for (key, value) in environments {
-                        if let Some(change) =
-                            Self::update_environment(this.environments, key, value)
+                        if let Ok(change) =
+                            Self::update_environment(this.environments, key, value, resync)
                         {
                             changes.push_back(change);
                         }
                     }
+                    for env_id in removed {
+                        if let Some(env) = this.environments.remove(&env_id) {
+                            debug!(env_id=%env_id, "removing environment missing from put");
+                            this.tombstones.insert(env_id, env.version);
+                            changes.push_back(ConfigChangeEvent::Delete(env));
+                        }
+                    }
+                    if newly_initialized {
+                        changes.push_front(ConfigChangeEvent::Initialized(
+                            InitializedSummary::new(
+                                &*this.environments,
+                                Some(this.created_at.elapsed()),
+                            ),
+                        ));
+                    }
                     changes
                 };
+                changes.extend(Self::replace_payload_filters(
+                    this.payload_filters,
+                    this.filter,
+                    filters,
+                ));
                 changes
             }
             Message::Put(PutEvent { path, .. }) => warn_span!("put", path=?path).in_scope(|| {
@@ -235,10 +1032,54 @@ impl AutoConfigClient {
                 debug_span!("patch", env_id=env_id.as_str(), received_version=%environment.version)
                     .in_scope(|| {
                         let mut changes = VecDeque::new();
-                        if let Some(change) =
-                            Self::update_environment(this.environments, env_id, environment)
-                        {
-                            changes.push_back(change);
+                        let received_version = environment.version;
+                        match this.tombstones.get(&env_id) {
+                            Some(tombstone) if !received_version.is_newer_than(tombstone.version) => {
+                                debug!(deleted_version=%tombstone.version, "ignoring patch for deleted environment");
+                                this.stats.record_ignored_update();
+                                VersionConflict::PatchAfterDelete {
+                                    env_id,
+                                    deleted_version: tombstone.version,
+                                    received_version,
+                                }
+                                .report(this.conflicts);
+                                return changes;
+                            }
+                            Some(_) => this.tombstones.remove(&env_id),
+                            None => {}
+                        }
+                        if !this.filter.matches(&environment) {
+                            // an environment that was renamed out of the filter is removed
+                            match this.environments.get(&env_id) {
+                                Some(existing)
+                                    if environment.version.is_newer_than(existing.version) =>
+                                {
+                                    debug!(
+                                        "removing environment that no longer matches the filter"
+                                    );
+                                    this.environments.remove(&env_id);
+                                    changes.push_back(ConfigChangeEvent::Delete(existing));
+                                }
+                                _ => trace!("ignoring environment that doesn't match the filter"),
+                            }
+                        } else {
+                            match Self::update_environment(
+                                this.environments,
+                                env_id.clone(),
+                                environment,
+                                false,
+                            ) {
+                                Ok(change) => changes.push_back(change),
+                                Err(stored_version) => {
+                                    this.stats.record_ignored_update();
+                                    VersionConflict::StalePatch {
+                                        env_id,
+                                        stored_version,
+                                        received_version,
+                                    }
+                                    .report(this.conflicts);
+                                }
+                            }
                         }
                         changes
                     })
@@ -247,24 +1088,37 @@ impl AutoConfigClient {
                 debug_span!("delete", env_id=env_id.as_str(), received_version=%version).in_scope(
                     || {
                         let mut changes = VecDeque::new();
-                        let entry = this.environments.entry(env_id.clone());
-                        match entry {
-                            Entry::Occupied(e) => {
-                                debug_span!("occupied", previous_version=%e.get().version).in_scope(
-                                    || {
-                                        if e.get().version < version {
+                        match this.environments.get(&env_id) {
+                            Some(existing) => {
+                                debug_span!("occupied", previous_version=%existing.version)
+                                    .in_scope(|| {
+                                        if version.is_newer_than(existing.version) {
                                             debug!("removing environment with received version");
-                                            changes
-                                                .push_back(ConfigChangeEvent::Delete(e.remove()));
+                                            this.environments.remove(&env_id);
+                                            this.tombstones.insert(env_id, version);
+                                            changes.push_back(ConfigChangeEvent::Delete(existing));
                                         } else {
                                             debug!("ignoring delete with older version");
+                                            this.stats.record_ignored_update();
+                                            VersionConflict::StaleDelete {
+                                                stored_version: existing.version,
+                                                env_id,
+                                                received_version: version,
+                                            }
+                                            .report(this.conflicts);
                                         }
-                                    },
-                                )
+                                    })
                             }
-                            Entry::Vacant(_) => {
+                            None => {
                                 debug_span!("vacant").in_scope(|| {
                                     debug!("received delete event for unknown environment");
+                                    // the patch it deletes may still be on its way
+                                    this.tombstones.insert(env_id.clone(), version);
+                                    VersionConflict::UnknownDelete {
+                                        env_id,
+                                        received_version: version,
+                                    }
+                                    .report(this.conflicts);
                                 });
                             }
                         }
@@ -272,11 +1126,77 @@ impl AutoConfigClient {
                     },
                 )
             }
+            Message::PatchFilter(FilterPatchEvent { filter_id, filter }) => {
+                let span =
+                    debug_span!("patch_filter", %filter_id, received_version=%filter.version);
+                let _enter = span.enter();
+                let mut changes = VecDeque::new();
+                if !this.filter.matches_project(&filter.proj_key) {
+                    trace!("ignoring payload filter that doesn't match the filter");
+                    return changes;
+                }
+                let stored = this.payload_filters.get(&filter_id).map(|f| f.version);
+                match stored {
+                    Some(stored_version) if !filter.version.is_newer_than(stored_version) => {
+                        debug!(%stored_version, "ignoring payload filter with older version");
+                        this.stats.record_ignored_update();
+                    }
+                    _ => changes.push_back(
+                        match this.payload_filters.insert(filter_id, filter.clone()) {
+                            Some(previous) => ConfigChangeEvent::FilterUpdate {
+                                previous,
+                                current: filter,
+                            },
+                            None => ConfigChangeEvent::FilterInsert(filter),
+                        },
+                    ),
+                }
+                changes
+            }
+            Message::DeleteFilter(FilterDeleteEvent { filter_id, version }) => {
+                let span = debug_span!("delete_filter", %filter_id, received_version=%version);
+                let _enter = span.enter();
+                let mut changes = VecDeque::new();
+                match this.payload_filters.get(&filter_id).map(|f| f.version) {
+                    Some(stored_version) if version.is_newer_than(stored_version) => {
+                        debug!("removing payload filter with received version");
+                        changes.extend(
+                            this.payload_filters
+                                .remove(&filter_id)
+                                .map(ConfigChangeEvent::FilterDelete),
+                        );
+                    }
+                    Some(stored_version) => {
+                        debug!(
+                            %stored_version,
+                            "ignoring payload filter delete with older version"
+                        );
+                        this.stats.record_ignored_update();
+                    }
+                    None => debug!("received delete event for unknown payload filter"),
+                }
+                changes
+            }
             Message::Reconnect => {
                 let span = debug_span!("reconnect");
                 let _span = span.enter();
                 debug!("server requested reconnect");
-                self.event_source.as_mut().reconnect();
+                let this = self.as_mut().get_mut();
+                if this.reconnect_timer.is_some() {
+                    debug!("ignoring reconnect, one is already scheduled");
+                    return VecDeque::new();
+                }
+                let delay = reconnect_delay(
+                    this.reconnect_delay_window,
+                    this.min_reconnect_interval,
+                    this.last_server_reconnect,
+                );
+                if delay.is_zero() {
+                    this.follow_reconnect();
+                } else {
+                    debug!(?delay, "scheduled reconnect");
+                    this.reconnect_timer = Some(Box::pin(tokio::time::sleep(delay)));
+                }
                 VecDeque::new()
             }
         }
@@ -287,39 +1207,1076 @@ impl Stream for AutoConfigClient {
     type Item = Result<ConfigChangeEvent, AutoConfigClientError>;
 
     fn poll_next(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let span = debug_span!("event");
-        let _span = span.enter();
+        self.poll_next_sequenced(cx)
+            .map(|change| change.map(|change| change.map(|change| change.change)))
+    }
+}
+
+/// How long to wait before following a `reconnect` message: a random delay within `window`,
+/// but never sooner than `min_interval` after the previous one
+fn reconnect_delay(
+    window: Duration,
+    min_interval: Duration,
+    last_reconnect: Option<Instant>,
+) -> Duration {
+    let jitter = if window.is_zero() {
+        Duration::ZERO
+    } else {
+        window.mul_f64(rand::Rng::gen_range(&mut rand::thread_rng(), 0.0..=1.0))
+    };
+    let throttle = last_reconnect
+        .map(|last| (last + min_interval).saturating_duration_since(Instant::now()))
+        .unwrap_or_default();
+    jitter.max(throttle)
+}
+
+impl AutoConfigClient {
+    fn follow_reconnect(&mut self) {
+        self.reconnect_timer = None;
+        self.last_server_reconnect = Some(Instant::now());
+        if let Some(event_source) = self.event_source.as_mut() {
+            event_source
+                .as_mut()
+                .reconnect(ReconnectReason::ServerRequested);
+        }
+    }
+
+    /// Queues [`ConfigChangeEvent::Stale`] or [`ConfigChangeEvent::Fresh`] when the time since
+    /// the last frame crosses the threshold
+    fn poll_staleness(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        let this = self.as_mut().get_mut();
+        let (threshold, event_source) = match (this.stale_after, this.event_source.as_ref()) {
+            (Some(threshold), Some(event_source)) => (threshold, event_source),
+            _ => return Poll::Pending,
+        };
+        let last_frame_at = event_source.last_frame_at().unwrap_or(this.created_at);
         loop {
-            let this = self.as_mut().project();
-            match this.changes.pop_front() {
-                Some(change) => return std::task::Poll::Ready(Some(Ok(change))),
-                None => match futures::ready!(this.event_source.poll_next(cx)) {
-                    Some(Ok(event)) => {
-                        let msg = Message::try_from(event)
-                            .map_err(AutoConfigClientError::EventParseError);
-                        match msg {
-                            Ok(msg) => debug_span!("message").in_scope(|| {
-                                let mut changes = { self.as_mut().process_message(msg.clone()) };
-
-                                if !changes.is_empty() {
-                                    self.as_mut().changes.append(&mut changes)
-                                }
-                            }),
-                            Err(e) => {
+            let since_last_frame = last_frame_at.elapsed();
+            if since_last_frame >= threshold {
+                // the next frame wakes the stream, no need for a timer until then
+                this.staleness_timer = None;
+                if this.is_stale {
+                    return Poll::Pending;
+                }
+                warn!(?since_last_frame, "stream is stale");
+                this.is_stale = true;
+                this.changes
+                    .push_back((None, ConfigChangeEvent::Stale { since_last_frame }));
+                return Poll::Ready(());
+            }
+            if this.is_stale {
+                debug!("stream is fresh again");
+                this.is_stale = false;
+                this.changes.push_back((None, ConfigChangeEvent::Fresh));
+                return Poll::Ready(());
+            }
+            let deadline = tokio::time::Instant::from_std(last_frame_at + threshold);
+            let timer = match this.staleness_timer.as_mut() {
+                Some(timer) if timer.deadline() == deadline => timer,
+                _ => this
+                    .staleness_timer
+                    .insert(Box::pin(tokio::time::sleep_until(deadline))),
+            };
+            futures::ready!(timer.as_mut().poll(cx));
+        }
+    }
+
+    /// Reconnects once a delayed `reconnect` message is due
+    fn poll_reconnect(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        let this = self.as_mut().get_mut();
+        match this.reconnect_timer.as_mut() {
+            Some(timer) => futures::ready!(timer.as_mut().poll(cx)),
+            None => return Poll::Pending,
+        }
+        debug!("following delayed reconnect");
+        this.follow_reconnect();
+        Poll::Ready(())
+    }
+
+    /// Drops expired sdk keys once they expire and queues an update for each environment
+    fn poll_expired(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<()> {
+        let this = self.as_mut().get_mut();
+        if std::mem::take(&mut this.reschedule_expiry) {
+            let next_expiry = this
+                .environments
+                .iter()
+                .filter_map(|(_, env)| env.sdk_key.expiring_at())
+                .min();
+            this.expiry_timer = next_expiry.map(|expires_at| {
+                let delay = expires_at.saturating_duration_since(Timestamp::now());
+                trace!(?delay, "scheduled pruning of expired sdk key");
+                Box::pin(tokio::time::sleep(delay))
+            });
+        }
+        match this.expiry_timer.as_mut() {
+            Some(timer) => futures::ready!(timer.as_mut().poll(cx)),
+            None => return Poll::Pending,
+        }
+        this.expiry_timer = None;
+        let now = Timestamp::now();
+        let expired: Vec<(EnvironmentId, EnvironmentConfig)> = this
+            .environments
+            .iter()
+            .filter(|(_, env)| env.sdk_key.expiring_at().is_some_and(|at| at <= now))
+            .collect();
+        for (env_id, previous) in expired {
+            let mut env = previous.clone();
+            env.sdk_key.prune_expired(now);
+            debug!(env_id=%env.env_id, proj_key=%env.proj_key, env_key=%env.env_key, "removing expired sdk key");
+            this.environments.upsert(env_id, env.clone());
+            this.changes.push_back((
+                None,
+                ConfigChangeEvent::Update {
+                    changed: env.diff(&previous),
+                    previous,
+                    current: env,
+                },
+            ));
+        }
+        this.publish();
+        Poll::Ready(())
+    }
+
+    /// Reads the next event and queues the changes it causes
+    fn poll_message(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<(), AutoConfigClientError>>> {
+        let this = self.as_mut().project();
+        let event_source = match this.event_source.as_mut() {
+            Some(event_source) => event_source,
+            // offline clients never receive another event
+            None => return Poll::Pending,
+        };
+        match futures::ready!(event_source.as_mut().poll_next(cx)) {
+            Some(Ok(event)) => {
+                // the failover is only known to have worked once the secondary key connected
+                if !*this.rotation_reported
+                    && this
+                        .key_rotation
+                        .as_ref()
+                        .is_some_and(KeyRotation::is_rotated)
+                {
+                    *this.rotation_reported = true;
+                    this.changes
+                        .push_back((None, ConfigChangeEvent::CredentialRotated));
+                }
+                let message_id = event.id.as_ref().map(|id| id.to_string());
+                let msg = this
+                    .parser
+                    .parse(event)
+                    .map_err(AutoConfigClientError::EventParseError);
+                match msg {
+                    Ok(msg) => debug_span!("message").in_scope(|| {
+                        let started = Instant::now();
+                        let changes = { self.as_mut().process_message(msg.clone()) };
+                        self.as_mut().project().stats.record_message(&msg, started);
+
+                        if !changes.is_empty() {
+                            self.publish();
+                            let this = self.as_mut().get_mut();
+                            // the initial put is never held back
+                            let initial =
+                                matches!(changes.front(), Some(ConfigChangeEvent::Initialized(_)));
+                            for change in changes {
+                                let change = match this.coalescer.as_mut() {
+                                    Some(coalescer) if !initial => {
+                                        coalescer.push(message_id.clone(), change)
+                                    }
+                                    _ => Some((message_id.clone(), change)),
+                                };
+                                this.changes.extend(change);
+                            }
+                        }
+                    }),
+                    Err(e) => {
+                        let this = self.as_mut().project();
+                        this.stats.record_parse_error();
+                        match this.parse_error_policy {
+                            ParseErrorPolicy::Fail => {
                                 error!(error=%e, "failed to parse event");
-                                return std::task::Poll::Ready(Some(Err(e)));
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                            ParseErrorPolicy::SkipAndLog => {
+                                warn!(error=%e, ?message_id, "skipping event that failed to parse")
+                            }
+                            ParseErrorPolicy::Skip => {
+                                debug!(error=%e, ?message_id, "skipping event that failed to parse")
                             }
                         }
                     }
-                    Some(Err(e)) => {
-                        return std::task::Poll::Ready(Some(Err(e.into())));
-                    }
-                    None => return std::task::Poll::Ready(None),
-                },
-            };
+                }
+                Poll::Ready(Some(Ok(())))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventsource::test_util::{MockResponse, MockServer};
+    use crate::test_util::EnvironmentFixture;
+    use futures::StreamExt;
+
+    fn environment(version: u64) -> String {
+        EnvironmentFixture::new().version(version).to_json()
+    }
+
+    #[tokio::test]
+    async fn patches_published_environments_in_place() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let patch = |version| {
+            format!(
+                "event: patch\ndata: {{\"path\":\"/environments/62ea8c4afac9b011945f6791\",\"data\":{}}}\n\n",
+                environment(version)
+            )
+        };
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .frame(patch(7))
+            .frame(patch(8))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        let env_id = EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap();
+
+        // nobody subscribed yet, environments come straight from the store
+        client.next().await.unwrap().unwrap();
+        client.next().await.unwrap().unwrap();
+        assert_eq!(client.environments()[&env_id].version, 6);
+
+        let mut environments = client.subscribe();
+        assert_eq!(environments.borrow_and_update()[&env_id].version, 6);
+        let published = Arc::as_ptr(&environments.borrow());
+        client.next().await.unwrap().unwrap();
+        assert!(environments.has_changed().unwrap());
+        assert_eq!(environments.borrow_and_update()[&env_id].version, 7);
+        assert_eq!(Arc::as_ptr(&environments.borrow()), published);
+
+        // a receiver holding on to the previous map keeps it
+        let previous = environments.borrow().clone();
+        client.next().await.unwrap().unwrap();
+        assert_eq!(previous[&env_id].version, 7);
+        assert_eq!(environments.borrow_and_update()[&env_id].version, 8);
+        assert_eq!(client.environments()[&env_id].version, 8);
+    }
+
+    #[tokio::test]
+    async fn waits_until_initialized() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let server = MockServer::start([
+            MockResponse::sse()
+                .comment("keep-alive")
+                .frame(format!("event: put\ndata: {}\n\n", put))
+                .hold(),
+            MockResponse::sse().hold(),
+        ])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        let mut environments = client.subscribe();
+        client
+            .as_mut()
+            .wait_until_initialized(Some(Duration::from_secs(5)))
+            .await
+            .unwrap();
+        assert!(client.is_initialized());
+        assert!(environments.has_changed().unwrap());
+        assert_eq!(environments.borrow_and_update().len(), 1);
+        match client.next().await {
+            Some(Ok(ConfigChangeEvent::Initialized(summary))) => {
+                assert_eq!(summary.environment_count, 1);
+                assert_eq!(summary.project_keys, ["default"]);
+                assert!(summary.connect_latency.is_some());
+                let json = serde_json::to_value(ConfigChangeEvent::Initialized(summary)).unwrap();
+                assert_eq!(json["kind"], "initialized");
+                assert!(json["data"]["connectLatencyMs"].is_u64());
+            }
+            other => panic!("expected initialized, got {:?}", other),
+        }
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client
+                .wait_until_initialized(Some(Duration::from_millis(20)))
+                .await,
+            Err(AutoConfigClientError::InitializationTimeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn prunes_expired_sdk_keys() {
+        let mut environment: serde_json::Value = serde_json::from_str(&environment(6)).unwrap();
+        environment["sdkKey"]["expiring"] = serde_json::json!({
+            "value": "sdk-011511cd-335b-47af-9e01-05a0daf1d71e",
+            "expiresAt": Timestamp::now().as_unix_millis() + 50,
+        });
+        let put = serde_json::json!({
+            "path": "/",
+            "data": {"environments": {"62ea8c4afac9b011945f6791": environment}},
+        });
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+        match client.next().await {
+            Some(Ok(ConfigChangeEvent::Update {
+                current, changed, ..
+            })) => {
+                assert_eq!(changed, [EnvironmentField::SdkKeyExpiring]);
+                assert!(serde_json::to_value(&current.sdk_key).unwrap()["expiring"].is_null());
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_environments_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("environments.json");
+        std::fs::write(
+            &path,
+            format!(r#"{{"62ea8c4afac9b011945f6791":{}}}"#, environment(6)),
+        )
+        .unwrap();
+        let client = AutoConfigClient::from_file(&path).unwrap();
+        assert!(client.is_initialized());
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+        assert!(futures::poll!(client.next()).is_pending());
+        assert_eq!(client.snapshot().environments.len(), 1);
+
+        assert!(AutoConfigClient::from_file(dir.path().join("missing.json")).is_err());
+    }
+
+    #[tokio::test]
+    async fn bootstraps_then_merges_stream() {
+        let stale: EnvironmentConfig = serde_json::from_str(
+            &environment(1).replace("62ea8c4afac9b011945f6791", "62ea8c4afac9b011945f6792"),
+        )
+        .unwrap();
+        let current: EnvironmentConfig = serde_json::from_str(&environment(6)).unwrap();
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(7)
+        );
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        )
+        .bootstrap(HashMap::from([
+            (current.env_id.clone(), current),
+            (stale.env_id.clone(), stale),
+        ]));
+        futures::pin_mut!(client);
+        let changes: Vec<_> = client
+            .as_mut()
+            .take(5)
+            .map(|change| change.unwrap())
+            .collect()
+            .await;
+        assert!(matches!(changes[0], ConfigChangeEvent::Initialized(_)));
+        assert!(matches!(changes[1], ConfigChangeEvent::Insert(_)));
+        assert!(matches!(changes[2], ConfigChangeEvent::Insert(_)));
+        assert!(
+            matches!(&changes[3], ConfigChangeEvent::Update { current, .. } if current.version == 7)
+        );
+        assert!(matches!(&changes[4], ConfigChangeEvent::Delete(env) if env.version == 1));
+        assert_eq!(client.environments().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn restores_without_replaying_inserts() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let patch = format!(
+            r#"{{"path":"/environments/62ea8c4afac9b011945f6791","data":{}}}"#,
+            environment(7)
+        );
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\nid: 2\ndata: {}\n\n", put))
+            .frame(format!("event: patch\nid: 3\ndata: {}\n\n", patch))])
+        .await;
+        let environments = serde_json::from_str::<PutEvent>(&put)
+            .unwrap()
+            .data
+            .environments;
+        let snapshot = Snapshot {
+            environments,
+            last_event_id: Some("1".to_owned()),
+        };
+
+        let client =
+            AutoConfigClient::restore_from_builder(EventSourceBuilder::get(server.url()), snapshot)
+                .unwrap();
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        match client.next().await {
+            Some(Ok(ConfigChangeEvent::Update {
+                previous,
+                current,
+                changed,
+            })) => {
+                assert_eq!(previous.version, 6);
+                assert_eq!(current.version, 7);
+                assert_eq!(changed, [EnvironmentField::Version]);
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+        assert_eq!(server.requests()[0].header("last-event-id"), Some("1"));
+        assert_eq!(client.snapshot().last_event_id.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn restore_reports_builder_errors() {
+        let builder = EventSourceBuilder::get("http://localhost:1/".parse().unwrap())
+            .header("X-Team platform", "infra");
+        let snapshot = Snapshot {
+            environments: HashMap::new(),
+            last_event_id: None,
+        };
+        assert!(matches!(
+            AutoConfigClient::restore_from_builder(builder, snapshot),
+            Err(EventSourceBuilderError::InvalidHeaderName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn counts_processed_messages() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let stale_patch = format!(
+            r#"{{"path":"/environments/62ea8c4afac9b011945f6791","data":{}}}"#,
+            environment(5)
+        );
+        let delete = r#"{"path":"/environments/62ea8c4afac9b011945f6791","version":7}"#;
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .frame(format!("event: patch\ndata: {}\n\n", stale_patch))
+            .frame("event: patch\ndata: not json\n\n")
+            .frame(format!("event: delete\ndata: {}\n\n", delete))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Err(AutoConfigClientError::EventParseError(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Delete(_)))
+        ));
+
+        let stats = client.stats();
+        assert_eq!(stats.puts, 1);
+        assert_eq!(stats.patches, 1);
+        assert_eq!(stats.deletes, 1);
+        assert_eq!(stats.ignored_updates, 1);
+        assert_eq!(stats.parse_errors, 1);
+        assert!(stats.last_processing_time.is_some());
+        assert_eq!(stats.event_source.unwrap().connections, 1);
+    }
+
+    #[tokio::test]
+    async fn skips_unparsable_events() {
+        let delete = r#"{"path":"/environments/62ea8c4afac9b011945f6791","version":7}"#;
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!(
+                "event: put\ndata: {}\n\n",
+                r#"{"path":"/","data":{"environments":{}}}"#
+            ))
+            .frame("event: patch\ndata: not json\n\n")
+            .frame("event: something-new\ndata: {}\n\n")
+            .frame(format!(
+                "event: patch\ndata: {{\"path\":\"/environments/62ea8c4afac9b011945f6791\",\"data\":{}}}\n\n",
+                environment(6)
+            ))
+            .frame(format!("event: delete\ndata: {}\n\n", delete))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        )
+        .on_parse_error(ParseErrorPolicy::Skip);
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Delete(_)))
+        ));
+        assert_eq!(client.stats().parse_errors, 2);
+        assert_eq!(
+            "Skip-And-Log".parse().ok(),
+            Some(ParseErrorPolicy::SkipAndLog)
+        );
+    }
+
+    #[tokio::test]
+    async fn tracks_payload_filters() {
+        let filter = |version| {
+            format!(
+                r#"{{"projKey":"default","key":"mobile-flags","version":{}}}"#,
+                version
+            )
+        };
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{}},"filters":{{"{}":{}}}}}}}"#,
+            "64a447c454eaac132a068d80",
+            filter(2)
+        );
+        let delete = r#"{"path":"/filters/64a447c454eaac132a068d80","version":4}"#;
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .frame(format!(
+                "event: patch\ndata: {{\"path\":\"/filters/64a447c454eaac132a068d80\",\"data\":{}}}\n\n",
+                filter(3)
+            ))
+            .frame(format!("event: delete\ndata: {}\n\n", delete))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::FilterInsert(filter))) if filter.version == 2
+        ));
+        let project: ProjectKey = serde_json::from_str(r#""default""#).unwrap();
+        assert_eq!(client.filter_keys(&project)[0].as_ref(), "mobile-flags");
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::FilterUpdate { previous, current }))
+                if previous.version == 2 && current.version == 3
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::FilterDelete(_)))
+        ));
+        assert_eq!(client.payload_filters().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn reports_version_conflicts() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let stale_patch = format!(
+            r#"{{"path":"/environments/62ea8c4afac9b011945f6791","data":{}}}"#,
+            environment(6)
+        );
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .frame(format!("event: patch\ndata: {}\n\n", stale_patch))
+            .frame(
+                r#"event: delete
+data: {"path":"/environments/62ea8c4afac9b011945f6791","version":5}
+
+"#,
+            )
+            .frame(
+                r#"event: delete
+data: {"path":"/environments/5f6e2e6b9c2d1a0c3b4a5d6e","version":1}
+
+"#,
+            )
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        let mut conflicts = client.subscribe_conflicts();
+        assert!(tokio::time::timeout(Duration::from_millis(200), async {
+            while client.next().await.is_some() {}
+        })
+        .await
+        .is_err());
+
+        let env_id = EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap();
+        assert_eq!(
+            conflicts.try_recv().unwrap(),
+            VersionConflict::StalePatch {
+                env_id: env_id.clone(),
+                stored_version: Version::new(6),
+                received_version: Version::new(6),
+            }
+        );
+        assert_eq!(
+            conflicts.try_recv().unwrap(),
+            VersionConflict::StaleDelete {
+                env_id,
+                stored_version: Version::new(6),
+                received_version: Version::new(5),
+            }
+        );
+        assert!(matches!(
+            conflicts.try_recv().unwrap(),
+            VersionConflict::UnknownDelete {
+                received_version,
+                ..
+            } if received_version == 1
+        ));
+        assert_eq!(client.stats().ignored_updates, 2);
+    }
+
+    #[tokio::test]
+    async fn ignores_patches_for_deleted_environments() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let patch = |version| {
+            format!(
+                "event: patch\ndata: {{\"path\":\"/environments/62ea8c4afac9b011945f6791\",\"data\":{}}}\n\n",
+                environment(version)
+            )
+        };
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .frame(
+                r#"event: delete
+data: {"path":"/environments/62ea8c4afac9b011945f6791","version":7}
+
+"#,
+            )
+            .frame(patch(7))
+            .frame(patch(8))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        let mut conflicts = client.subscribe_conflicts();
+        assert!(tokio::time::timeout(Duration::from_millis(200), async {
+            while client.next().await.is_some() {}
+        })
+        .await
+        .is_err());
+
+        let env_id = EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap();
+        assert_eq!(
+            conflicts.try_recv().unwrap(),
+            VersionConflict::PatchAfterDelete {
+                env_id: env_id.clone(),
+                deleted_version: Version::new(7),
+                received_version: Version::new(7),
+            }
+        );
+        assert!(conflicts.try_recv().is_err());
+        assert_eq!(client.environments()[&env_id].version, Version::new(8));
+    }
+
+    #[tokio::test]
+    async fn reports_stale_stream() {
+        // staleness is measured with the real clock, so the gap between the keep-alives is
+        // far longer than the threshold to leave room for slow test machines
+        let server = MockServer::start([MockResponse::sse()
+            .comment("keep-alive")
+            .delay(Duration::from_secs(1))
+            .comment("keep-alive")
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        )
+        .stale_after(Duration::from_millis(50));
+        futures::pin_mut!(client);
+        match client.next().await {
+            Some(Ok(ConfigChangeEvent::Stale { since_last_frame })) => {
+                assert!(since_last_frame >= Duration::from_millis(50))
+            }
+            other => panic!("expected stale, got {:?}", other),
+        }
+        assert!(client.is_stale());
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Fresh))
+        ));
+        assert!(!client.is_stale());
+    }
+
+    #[tokio::test]
+    async fn resync_replaces_environments() {
+        let mut other: serde_json::Value = serde_json::from_str(&environment(3)).unwrap();
+        other["envId"] = "5f6e2e6b9c2d1a0c3b4a5d6e".into();
+        let first_put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{},"5f6e2e6b9c2d1a0c3b4a5d6e":{}}}}}}}"#,
+            environment(6),
+            other
+        );
+        // older than what's stored, only a resync accepts it
+        let mut rolled_back: serde_json::Value = serde_json::from_str(&environment(5)).unwrap();
+        rolled_back["envName"] = "Rolled back".into();
+        let second_put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            rolled_back
+        );
+        let server = MockServer::start([
+            MockResponse::sse()
+                .frame(format!("event: put\nid: 1\ndata: {}\n\n", first_put))
+                .hold(),
+            MockResponse::sse()
+                .frame(format!("event: put\ndata: {}\n\n", second_put))
+                .hold(),
+        ])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        let changes: Vec<_> = client.as_mut().take(3).collect().await;
+        assert_eq!(changes.len(), 3);
+
+        client.as_mut().resync();
+        match client.next().await {
+            Some(Ok(ConfigChangeEvent::Update { current, .. })) => {
+                assert_eq!(current.version, 5)
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Delete(env))) if env.version == 3
+        ));
+        let requests = server.requests();
+        assert_eq!(requests[0].header("last-event-id"), None);
+        assert_eq!(requests[1].header("last-event-id"), None);
+        assert_eq!(client.environments().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_secondary_key() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let server = MockServer::start([
+            MockResponse::status(401),
+            MockResponse::sse()
+                .frame(format!("event: put\ndata: {}\n\n", put))
+                .hold(),
+        ])
+        .await;
+        let primary =
+            RelayAutoConfigKey::try_from("rel-3d560391-904c-4afd-8075-faad7652ed1d").unwrap();
+        let secondary =
+            RelayAutoConfigKey::try_from("rel-011511cd-335b-47af-9e01-05a0daf1d71e").unwrap();
+        let client = AutoConfigClient::with_secondary_key(
+            primary.clone(),
+            secondary.clone(),
+            Endpoint::Custom(server.url()),
+        )
+        .unwrap();
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::CredentialRotated))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some(primary.expose_secret())
+        );
+        assert_eq!(
+            requests[1].header("authorization"),
+            Some(secondary.expose_secret())
+        );
+    }
+
+    #[tokio::test]
+    async fn numbers_change_events() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\nid: 7\ndata: {}\n\n", put))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        );
+        futures::pin_mut!(client);
+        let initialized = client.as_mut().next_sequenced().await.unwrap().unwrap();
+        assert_eq!(initialized.sequence, 1);
+        assert_eq!(initialized.message_id.as_deref(), Some("7"));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+
+        let inserted = AutoConfigClient::offline(HashMap::from([(
+            EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap(),
+            serde_json::from_str(&environment(6)).unwrap(),
+        )]));
+        futures::pin_mut!(inserted);
+        inserted.as_mut().next_sequenced().await.unwrap().unwrap();
+        let insert = inserted.as_mut().next_sequenced().await.unwrap().unwrap();
+        assert_eq!(insert.sequence, 2);
+        let json = serde_json::to_value(&insert).unwrap();
+        assert_eq!(json["sequence"], 2);
+        assert!(json["messageId"].is_null());
+        assert_eq!(json["kind"], "insert");
+        assert_eq!(insert.change.kind(), "insert");
+        assert_eq!(json["data"]["version"], 6);
+    }
+
+    #[tokio::test]
+    async fn merges_put_into_existing_store() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(7)
+        );
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .hold()])
+        .await;
+        let env_id = EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap();
+        let store = HashMap::from([(
+            env_id.clone(),
+            serde_json::from_str::<EnvironmentConfig>(&environment(6)).unwrap(),
+        )]);
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        )
+        .with_store(store);
+        assert_eq!(client.environments().len(), 1);
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Update { current, .. })) if current.version == 7
+        ));
+        assert_eq!(client.environments()[&env_id].version, 7);
+    }
+
+    #[tokio::test]
+    async fn calls_registered_callbacks() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let patch = format!(
+            r#"{{"path":"/environments/62ea8c4afac9b011945f6791","data":{}}}"#,
+            environment(7)
+        );
+        let delete = r#"{"path":"/environments/62ea8c4afac9b011945f6791","version":8}"#;
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .frame(format!("event: patch\ndata: {}\n\n", patch))
+            .frame(format!("event: delete\ndata: {}\n\n", delete))])
+        .await;
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (inserts, updates, deletes) = (calls.clone(), calls.clone(), calls.clone());
+        AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url())
+                .max_retries(0)
+                .build()
+                .unwrap(),
+        )
+        .on_insert(move |env| {
+            inserts
+                .lock()
+                .unwrap()
+                .push(format!("insert {}", env.version))
+        })
+        .on_update(move |previous, current, _| {
+            updates
+                .lock()
+                .unwrap()
+                .push(format!("update {} {}", previous.version, current.version))
+        })
+        .on_delete(move |env| {
+            deletes
+                .lock()
+                .unwrap()
+                .push(format!("delete {}", env.version))
+        })
+        .run()
+        .await
+        .unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["insert v6", "update v6 v7", "delete v7"]
+        );
+    }
+
+    #[tokio::test]
+    async fn coalesces_bursts_of_changes() {
+        let put = format!(
+            r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+            environment(6)
+        );
+        let patch = |version| {
+            Message::Patch(PatchEvent {
+                env_id: EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap(),
+                environment: serde_json::from_str(&environment(version)).unwrap(),
+            })
+        };
+        let server = MockServer::start([MockResponse::sse()
+            .frame(format!("event: put\ndata: {}\n\n", put))
+            .message(&patch(7))
+            .message(&patch(8))
+            .hold()])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        )
+        .coalesce(Duration::from_millis(100));
+        futures::pin_mut!(client);
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Initialized(_)))
+        ));
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(ConfigChangeEvent::Insert(_)))
+        ));
+        match client.next().await {
+            Some(Ok(ConfigChangeEvent::Update {
+                previous, current, ..
+            })) => {
+                assert_eq!(previous.version, 6);
+                assert_eq!(current.version, 8);
+            }
+            other => panic!("expected an update, got {:?}", other),
+        }
+        assert!(futures::poll!(client.next()).is_pending());
+    }
+
+    #[test]
+    fn reconnect_delay_stays_in_window() {
+        assert_eq!(
+            reconnect_delay(Duration::ZERO, Duration::from_secs(60), None),
+            Duration::ZERO
+        );
+        for _ in 0..100 {
+            assert!(
+                reconnect_delay(Duration::from_secs(1), Duration::ZERO, None)
+                    <= Duration::from_secs(1)
+            );
+        }
+        let delay = reconnect_delay(
+            Duration::ZERO,
+            Duration::from_secs(60),
+            Some(Instant::now()),
+        );
+        assert!(delay > Duration::from_secs(59) && delay <= Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn throttles_server_reconnects() {
+        let server = MockServer::start([
+            MockResponse::sse()
+                .frame("event: reconnect\ndata: {}\n\n")
+                .hold(),
+            MockResponse::sse()
+                .frame("event: reconnect\ndata: {}\n\n")
+                .hold(),
+            MockResponse::sse().hold(),
+        ])
+        .await;
+        let client = AutoConfigClient::from_event_source(
+            EventSourceBuilder::get(server.url()).build().unwrap(),
+        )
+        .min_reconnect_interval(Duration::from_secs(60));
+        futures::pin_mut!(client);
+        // the first reconnect is followed right away, the second waits out the interval
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.stats().reconnects < 2 {
+            assert!(Instant::now() < deadline, "reconnects weren't received");
+            let _ = tokio::time::timeout(Duration::from_millis(50), client.next()).await;
         }
+        assert_eq!(server.requests().len(), 2);
+        let timer = client
+            .reconnect_timer
+            .as_ref()
+            .expect("reconnect is throttled");
+        assert!(timer.deadline() > tokio::time::Instant::now() + Duration::from_secs(55));
     }
 }
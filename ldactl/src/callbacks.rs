@@ -0,0 +1,50 @@
+use crate::{
+    messages::{EnvironmentConfig, EnvironmentField},
+    ConfigChangeEvent,
+};
+
+type EnvironmentCallback = Box<dyn FnMut(&EnvironmentConfig) + Send>;
+type UpdateCallback =
+    Box<dyn FnMut(&EnvironmentConfig, &EnvironmentConfig, &[EnvironmentField]) + Send>;
+
+/// Closures registered with [`crate::AutoConfigClient::on_insert`] and friends
+#[derive(Default)]
+pub(crate) struct Callbacks {
+    insert: Vec<EnvironmentCallback>,
+    update: Vec<UpdateCallback>,
+    delete: Vec<EnvironmentCallback>,
+}
+
+impl Callbacks {
+    pub(crate) fn on_insert(&mut self, f: impl FnMut(&EnvironmentConfig) + Send + 'static) {
+        self.insert.push(Box::new(f));
+    }
+
+    pub(crate) fn on_update(
+        &mut self,
+        f: impl FnMut(&EnvironmentConfig, &EnvironmentConfig, &[EnvironmentField]) + Send + 'static,
+    ) {
+        self.update.push(Box::new(f));
+    }
+
+    pub(crate) fn on_delete(&mut self, f: impl FnMut(&EnvironmentConfig) + Send + 'static) {
+        self.delete.push(Box::new(f));
+    }
+
+    /// Calls the closures registered for the kind of `change`, in the order they were added
+    pub(crate) fn dispatch(&mut self, change: &ConfigChangeEvent) {
+        match change {
+            ConfigChangeEvent::Insert(env) => self.insert.iter_mut().for_each(|f| f(env)),
+            ConfigChangeEvent::Update {
+                previous,
+                current,
+                changed,
+            } => self
+                .update
+                .iter_mut()
+                .for_each(|f| f(previous, current, changed)),
+            ConfigChangeEvent::Delete(env) => self.delete.iter_mut().for_each(|f| f(env)),
+            _ => {}
+        }
+    }
+}
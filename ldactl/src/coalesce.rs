@@ -0,0 +1,183 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Future;
+use tokio::time::{Instant, Sleep};
+use tracing::trace;
+
+use crate::{
+    messages::{EnvironmentConfig, EnvironmentId},
+    ConfigChangeEvent,
+};
+
+struct Pending {
+    env_id: EnvironmentId,
+    message_id: Option<String>,
+    change: ConfigChangeEvent,
+    due: Instant,
+}
+
+/// Holds back change events until their environment has been quiet for a while, see
+/// [`crate::AutoConfigClient::coalesce`]
+pub(crate) struct Coalescer {
+    window: Duration,
+    // in the order the environments first changed
+    pending: VecDeque<Pending>,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl Coalescer {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: VecDeque::new(),
+            timer: None,
+        }
+    }
+
+    /// Holds back `change` if it's about an environment, otherwise hands it back
+    pub(crate) fn push(
+        &mut self,
+        message_id: Option<String>,
+        change: ConfigChangeEvent,
+    ) -> Option<(Option<String>, ConfigChangeEvent)> {
+        let env_id = match &change {
+            ConfigChangeEvent::Insert(env)
+            | ConfigChangeEvent::Update { current: env, .. }
+            | ConfigChangeEvent::Delete(env) => env.env_id.clone(),
+            _ => return Some((message_id, change)),
+        };
+        let due = Instant::now() + self.window;
+        match self.pending.iter().position(|p| p.env_id == env_id) {
+            Some(index) => {
+                let earlier = self.pending.remove(index).unwrap();
+                match merge(earlier.change, change) {
+                    Some(change) => {
+                        trace!(%env_id, "coalesced change event");
+                        self.pending.insert(
+                            index,
+                            Pending {
+                                env_id,
+                                message_id,
+                                change,
+                                due,
+                            },
+                        )
+                    }
+                    None => trace!(%env_id, "change events cancelled out"),
+                }
+            }
+            None => self.pending.push_back(Pending {
+                env_id,
+                message_id,
+                change,
+                due,
+            }),
+        }
+        None
+    }
+
+    /// Resolves with the change events whose environment has been quiet for the whole window
+    pub(crate) fn poll_due(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Vec<(Option<String>, ConfigChangeEvent)>> {
+        loop {
+            let next_due = match self.pending.iter().map(|p| p.due).min() {
+                Some(due) => due,
+                None => {
+                    self.timer = None;
+                    return Poll::Pending;
+                }
+            };
+            if next_due <= Instant::now() {
+                let now = Instant::now();
+                let (due, pending) = std::mem::take(&mut self.pending)
+                    .into_iter()
+                    .partition(|p| p.due <= now);
+                self.pending = pending;
+                return Poll::Ready(due.into_iter().map(|p| (p.message_id, p.change)).collect());
+            }
+            let timer = match self.timer.as_mut() {
+                Some(timer) if timer.deadline() == next_due => timer,
+                _ => self
+                    .timer
+                    .insert(Box::pin(tokio::time::sleep_until(next_due))),
+            };
+            futures::ready!(timer.as_mut().poll(cx));
+        }
+    }
+
+    /// Everything that's held back, for when the stream ends
+    pub(crate) fn drain(&mut self) -> Vec<(Option<String>, ConfigChangeEvent)> {
+        self.timer = None;
+        self.pending
+            .drain(..)
+            .map(|p| (p.message_id, p.change))
+            .collect()
+    }
+}
+
+/// Combines two changes to the same environment into one, `None` if they cancel out
+fn merge(earlier: ConfigChangeEvent, later: ConfigChangeEvent) -> Option<ConfigChangeEvent> {
+    match (earlier, later) {
+        (ConfigChangeEvent::Insert(_), ConfigChangeEvent::Update { current, .. }) => {
+            Some(ConfigChangeEvent::Insert(current))
+        }
+        (ConfigChangeEvent::Insert(_), ConfigChangeEvent::Delete(_)) => None,
+        (ConfigChangeEvent::Update { previous, .. }, ConfigChangeEvent::Update { current, .. })
+        | (ConfigChangeEvent::Delete(previous), ConfigChangeEvent::Insert(current)) => {
+            update(previous, current)
+        }
+        (_, later) => Some(later),
+    }
+}
+
+fn update(previous: EnvironmentConfig, current: EnvironmentConfig) -> Option<ConfigChangeEvent> {
+    (previous != current).then(|| ConfigChangeEvent::Update {
+        changed: current.diff(&previous),
+        previous,
+        current,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::EnvironmentFixture;
+
+    fn environment(version: u64) -> EnvironmentConfig {
+        EnvironmentFixture::new().version(version).build()
+    }
+
+    fn update_event(previous: u64, current: u64) -> ConfigChangeEvent {
+        update(environment(previous), environment(current)).unwrap()
+    }
+
+    #[test]
+    fn merges_changes_to_the_same_environment() {
+        assert!(matches!(
+            merge(update_event(6, 7), update_event(7, 8)),
+            Some(ConfigChangeEvent::Update { previous, current, .. })
+                if previous.version == 6 && current.version == 8
+        ));
+        assert!(matches!(
+            merge(ConfigChangeEvent::Insert(environment(1)), update_event(1, 2)),
+            Some(ConfigChangeEvent::Insert(env)) if env.version == 2
+        ));
+        assert!(merge(
+            ConfigChangeEvent::Insert(environment(1)),
+            ConfigChangeEvent::Delete(environment(1))
+        )
+        .is_none());
+        assert!(merge(
+            ConfigChangeEvent::Delete(environment(1)),
+            ConfigChangeEvent::Insert(environment(1))
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+use miette::Diagnostic;
+use serde::Deserialize;
+use thiserror::Error;
+
+const FILE_NAMES: &[&str] = &["ldactl.toml", "ldactl.yaml", "ldactl.yml"];
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ConfigFileError {
+    #[error("failed to read {}", .0.display())]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("unsupported config file {}", .0.display())]
+    #[diagnostic(help("config files must end with .toml, .yaml or .yml"))]
+    Format(PathBuf),
+    #[error("failed to parse {}", .0.display())]
+    Toml(PathBuf, #[source] toml::de::Error),
+    #[error("failed to parse {}", .0.display())]
+    Yaml(PathBuf, #[source] serde_yaml::Error),
+    #[error("{}: unknown setting {key:?}", .path.display())]
+    #[diagnostic(help("settings are named after the long form of the command line flags"))]
+    UnknownSetting { path: PathBuf, key: String },
+}
+
+/// A setting's value, lists go to flags that take several values
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    List(Vec<Scalar>),
+    Scalar(Scalar),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scalar::Bool(value) => value.fmt(f),
+            Scalar::Integer(value) => value.fmt(f),
+            Scalar::Float(value) => value.fmt(f),
+            Scalar::String(value) => f.write_str(value),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+struct Settings {
+    /// Log filter used when `RUST_LOG` isn't set
+    log: Option<String>,
+    /// Everything else, keyed by the long flag name
+    #[serde(flatten)]
+    flags: BTreeMap<String, Setting>,
+}
+
+/// Settings read from `ldactl.toml` or `ldactl.yaml`
+///
+/// Files are flat tables of settings named after the long flags, such as
+/// `stream-uri = "eu"` or `stream-uri: eu`, with arrays for flags that take lists.
+/// [`ConfigFile::apply`] turns them into the flags' default values, so flags and environment
+/// variables still take precedence over the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    settings: Settings,
+}
+
+impl ConfigFile {
+    /// Loads `path` if given, otherwise the first config file found in the working directory,
+    /// `$XDG_CONFIG_HOME/ldactl` (`~/.config/ldactl`) or `/etc/ldactl`
+    pub fn find(path: Option<&Path>) -> Result<Option<Self>, ConfigFileError> {
+        if let Some(path) = path {
+            return Self::load(path).map(Some);
+        }
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        let dirs = [
+            Some(PathBuf::from(".")),
+            config_home.map(|dir| dir.join("ldactl")),
+        ]
+        .into_iter()
+        .flatten()
+        .chain([PathBuf::from("/etc/ldactl")]);
+        for dir in dirs {
+            for name in FILE_NAMES {
+                let path = dir.join(name);
+                if path.is_file() {
+                    return Self::load(&path).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigFileError::Io(path.to_owned(), e))?;
+        Self::parse(path, &contents)
+    }
+
+    fn parse(path: &Path, contents: &str) -> Result<Self, ConfigFileError> {
+        let settings = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                toml::from_str(contents).map_err(|e| ConfigFileError::Toml(path.to_owned(), e))?
+            }
+            // a file with nothing but comments is an empty document
+            Some("yaml" | "yml") => serde_yaml::from_str::<Option<Settings>>(contents)
+                .map_err(|e| ConfigFileError::Yaml(path.to_owned(), e))?
+                .unwrap_or_default(),
+            _ => return Err(ConfigFileError::Format(path.to_owned())),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            settings,
+        })
+    }
+
+    /// The `log` setting, for when `RUST_LOG` isn't set
+    pub fn log(&self) -> Option<&str> {
+        self.settings.log.as_deref()
+    }
+
+    /// Makes every setting the default value of the flag with the same long name, in
+    /// `command` or any of its subcommands
+    pub fn apply(&self, command: Command) -> Result<Command, ConfigFileError> {
+        let mut unknown: BTreeSet<&str> = self.settings.flags.keys().map(String::as_str).collect();
+        let command = with_defaults(command, &self.settings.flags, &mut unknown);
+        match unknown.into_iter().next() {
+            Some(key) => Err(ConfigFileError::UnknownSetting {
+                path: self.path.clone(),
+                key: key.to_owned(),
+            }),
+            None => Ok(command),
+        }
+    }
+}
+
+fn with_defaults<'a>(
+    command: Command,
+    flags: &'a BTreeMap<String, Setting>,
+    unknown: &mut BTreeSet<&'a str>,
+) -> Command {
+    command
+        .mut_args(|arg| {
+            let Some((key, setting)) = arg.get_long().and_then(|long| flags.get_key_value(long))
+            else {
+                return arg;
+            };
+            unknown.remove(key.as_str());
+            match setting {
+                Setting::Scalar(value) => arg.default_value(value.to_string()),
+                Setting::List(values) => arg.default_values(values.iter().map(Scalar::to_string)),
+            }
+        })
+        .mut_subcommands(|subcommand| with_defaults(subcommand, flags, unknown))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn cli() -> Command {
+        Command::new("ldactl")
+            .arg(
+                Arg::new("stream-uri")
+                    .long("stream-uri")
+                    .global(true)
+                    .default_value("commercial"),
+            )
+            .subcommand(
+                Command::new("watch")
+                    .arg(
+                        Arg::new("project")
+                            .long("project")
+                            .value_delimiter(',')
+                            .action(ArgAction::Append),
+                    )
+                    .arg(Arg::new("redact").long("redact").action(ArgAction::SetTrue)),
+            )
+    }
+
+    #[test]
+    fn parses_toml_and_yaml() {
+        let toml = ConfigFile::parse(
+            Path::new("ldactl.toml"),
+            r#"
+# fleet defaults
+credential = "file:/run/secrets/ld-key"
+stream-uri = 'eu' # comment
+project = ["default", "mobile"]
+redact = true
+log = "info"
+"#,
+        )
+        .unwrap();
+        let yaml = ConfigFile::parse(
+            Path::new("ldactl.yaml"),
+            r#"---
+credential: file:/run/secrets/ld-key
+stream-uri: "eu"
+project: [default, mobile]
+redact: true
+log: info
+"#,
+        )
+        .unwrap();
+        assert_eq!(toml.settings, yaml.settings);
+        assert_eq!(toml.log(), Some("info"));
+        assert_eq!(
+            toml.settings.flags["project"],
+            Setting::List(vec![
+                Scalar::String("default".to_owned()),
+                Scalar::String("mobile".to_owned())
+            ])
+        );
+        assert_eq!(
+            ConfigFile::parse(Path::new("ldactl.yml"), "# nothing yet\n")
+                .unwrap()
+                .settings,
+            Settings::default()
+        );
+
+        assert!(matches!(
+            ConfigFile::parse(Path::new("ldactl.toml"), "stream-uri = "),
+            Err(ConfigFileError::Toml(..))
+        ));
+        assert!(matches!(
+            ConfigFile::parse(Path::new("ldactl.json"), ""),
+            Err(ConfigFileError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn settings_are_defaults() {
+        let config = ConfigFile::parse(
+            Path::new("ldactl.toml"),
+            "stream-uri = \"eu\"\nproject = [\"default\", \"mobile\"]\nredact = true\n",
+        )
+        .unwrap();
+        let command = config.apply(cli()).unwrap();
+
+        let matches = command
+            .clone()
+            .try_get_matches_from(["ldactl", "watch"])
+            .unwrap();
+        let watch = matches.subcommand_matches("watch").unwrap();
+        assert_eq!(watch.get_one::<String>("stream-uri").unwrap(), "eu");
+        assert_eq!(
+            watch
+                .get_many::<String>("project")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["default", "mobile"]
+        );
+        assert!(watch.get_flag("redact"));
+
+        // flags win over the file
+        let matches = command
+            .try_get_matches_from([
+                "ldactl",
+                "watch",
+                "--stream-uri",
+                "federal",
+                "--project=mobile",
+            ])
+            .unwrap();
+        let watch = matches.subcommand_matches("watch").unwrap();
+        assert_eq!(watch.get_one::<String>("stream-uri").unwrap(), "federal");
+        assert_eq!(
+            watch
+                .get_many::<String>("project")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["mobile"]
+        );
+
+        let config = ConfigFile::parse(Path::new("ldactl.toml"), "\nendpoint = \"eu\"").unwrap();
+        assert!(matches!(
+            config.apply(cli()),
+            Err(ConfigFileError::UnknownSetting { key, .. }) if key == "endpoint"
+        ));
+    }
+}
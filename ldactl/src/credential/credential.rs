@@ -1,18 +1,75 @@
 use serde::{Deserialize, Serialize};
 
 use crate::credential::{
-    error::CredentialError, kind::CredentialKind, ClientSideId, MobileKey, RelayAutoConfigKey,
-    ServerSideKey,
+    error::CredentialError, kind::CredentialKind, ApiToken, ClientSideId, MobileKey,
+    RelayAutoConfigKey, ServerSideKey,
 };
 
 use super::{error::ExpectedCredential, LaunchDarklyCredential};
 
+/// Any kind of LaunchDarkly credential, see [`Credential::parse`]
+///
+/// Serializes as the plain credential string and infers the kind again when deserializing.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum Credential {
     Server(ServerSideKey),
     Mobile(MobileKey),
     Client(ClientSideId),
     RelayAutoConfig(RelayAutoConfigKey),
+    Api(ApiToken),
+}
+
+impl Credential {
+    /// Infers the kind from the prefix, or the length for client-side ids, and validates it
+    ///
+    /// ```
+    /// use launchdarkly_autoconfig::credential::{
+    ///     Credential, CredentialKind, LaunchDarklyCredential,
+    /// };
+    ///
+    /// let credential = Credential::parse("sdk-011511cd-335b-47af-9e01-05a0daf1d71e").unwrap();
+    /// assert_eq!(credential.kind(), CredentialKind::ServerSide);
+    /// assert!(credential.as_server_side().is_some());
+    /// ```
+    pub fn parse(s: &str) -> Result<Self, CredentialError> {
+        s.parse()
+    }
+
+    pub fn as_server_side(&self) -> Option<&ServerSideKey> {
+        match self {
+            Credential::Server(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_mobile(&self) -> Option<&MobileKey> {
+        match self {
+            Credential::Mobile(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn as_client_side(&self) -> Option<&ClientSideId> {
+        match self {
+            Credential::Client(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_relay_auto_config(&self) -> Option<&RelayAutoConfigKey> {
+        match self {
+            Credential::RelayAutoConfig(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn as_api_token(&self) -> Option<&ApiToken> {
+        match self {
+            Credential::Api(a) => Some(a),
+            _ => None,
+        }
+    }
 }
 
 impl LaunchDarklyCredential for Credential {
@@ -23,6 +80,7 @@ impl LaunchDarklyCredential for Credential {
             Credential::Mobile(_) => CredentialKind::MobileKey,
             Credential::Client(_) => CredentialKind::ClientSide,
             Credential::RelayAutoConfig(_) => CredentialKind::RelayAutoConfig,
+            Credential::Api(_) => CredentialKind::ApiToken,
         }
     }
 
@@ -33,6 +91,7 @@ impl LaunchDarklyCredential for Credential {
             Credential::Mobile(m) => m.as_str(),
             Credential::Client(c) => c.as_str(),
             Credential::RelayAutoConfig(r) => r.as_str(),
+            Credential::Api(a) => a.as_str(),
         }
     }
 }
@@ -41,6 +100,7 @@ fn try_parse_kind(b: &[u8]) -> Result<CredentialKind, CredentialError> {
         Some(b"sdk-") => Ok(CredentialKind::ServerSide),
         Some(b"mob-") => Ok(CredentialKind::MobileKey),
         Some(b"rel-") => Ok(CredentialKind::RelayAutoConfig),
+        Some(b"api-") => Ok(CredentialKind::ApiToken),
         Some(_) => Ok(CredentialKind::ClientSide),
         _ => Err(CredentialError::InvalidLength {
             expected: ExpectedCredential::Any,
@@ -59,9 +119,37 @@ impl TryFrom<String> for Credential {
             CredentialKind::RelayAutoConfig => {
                 Ok(Self::RelayAutoConfig(RelayAutoConfigKey::try_from(s)?))
             }
+            CredentialKind::ApiToken => Ok(Self::Api(ApiToken::try_from(s)?)),
         }
     }
 }
+impl From<Credential> for String {
+    fn from(credential: Credential) -> Self {
+        credential.into_string()
+    }
+}
+
+/// Infers the kind from the prefix
+impl std::str::FromStr for Credential {
+    type Err = CredentialError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s.to_owned())
+    }
+}
+
+/// Masked unless it's a client-side id, which isn't a secret
+impl std::fmt::Display for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Credential::Server(s) => s.fmt(f),
+            Credential::Mobile(m) => m.fmt(f),
+            Credential::Client(c) => c.fmt(f),
+            Credential::RelayAutoConfig(r) => r.fmt(f),
+            Credential::Api(a) => a.fmt(f),
+        }
+    }
+}
+
 impl AsRef<str> for Credential {
     #[inline]
     fn as_ref(&self) -> &str {
@@ -77,6 +165,32 @@ impl AsRef<[u8]> for Credential {
             Credential::Mobile(m) => m.as_ref(),
             Credential::Client(c) => c.as_ref(),
             Credential::RelayAutoConfig(r) => r.as_ref(),
+            Credential::Api(a) => a.as_ref(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_kind_when_deserializing() {
+        let credentials: Vec<Credential> = serde_json::from_value(serde_json::json!([
+            "sdk-011511cd-335b-47af-9e01-05a0daf1d71e",
+            "mob-b5734766-5a3d-4b41-b63f-2669a4fb6497",
+            "62ea8c4afac9b011945f6791",
+        ]))
+        .unwrap();
+        assert!(credentials[0].as_server_side().is_some());
+        assert!(credentials[1].as_mobile().is_some());
+        assert!(credentials[2].as_client_side().is_some());
+        assert!(credentials[0].as_mobile().is_none());
+        assert_eq!(
+            serde_json::to_value(&credentials[1]).unwrap(),
+            "mob-b5734766-5a3d-4b41-b63f-2669a4fb6497"
+        );
+
+        assert!(serde_json::from_str::<Credential>(r#""sdk-011511cd""#).is_err());
+    }
+}
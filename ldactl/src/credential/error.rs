@@ -3,7 +3,10 @@ use std::fmt::{Display, Formatter};
 use thiserror::Error;
 
 use super::{
-    consts::{CLIENT_SIDE_ID_LEN, MOBILE_KEY_LEN, RELAY_AUTO_CONFIG_KEY_LEN, SERVER_SIDE_KEY_LEN},
+    consts::{
+        API_TOKEN_LEN, CLIENT_SIDE_ID_LEN, MOBILE_KEY_LEN, RELAY_AUTO_CONFIG_KEY_LEN,
+        SERVER_SIDE_KEY_LEN,
+    },
     CredentialKind,
 };
 
@@ -39,14 +42,15 @@ pub enum ExpectedCredentialSize {
 
 impl Display for ExpectedCredentialSize {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        assert!(
+        const _: () = assert!(
             SERVER_SIDE_KEY_LEN == MOBILE_KEY_LEN
                 && SERVER_SIDE_KEY_LEN == RELAY_AUTO_CONFIG_KEY_LEN
+                && SERVER_SIDE_KEY_LEN == API_TOKEN_LEN
         );
         match self {
             ExpectedCredentialSize::ForAny => write!(
                 f,
-                "one of {} (server-side,mobile-key,relay-auto-config), {} (client-side)",
+                "one of {} (server-side,mobile-key,relay-auto-config,api-token), {} (client-side)",
                 SERVER_SIDE_KEY_LEN, CLIENT_SIDE_ID_LEN
             ),
             ExpectedCredentialSize::ForKind(kind) => write!(f, "{}", kind.len()),
@@ -62,10 +66,11 @@ pub enum ExpectedCredential {
 
 impl ExpectedCredential {
     fn expected_sizes_message(&self) -> String {
-        const KINDS: &[CredentialKind; 3] = &[
+        const KINDS: &[CredentialKind; 4] = &[
             CredentialKind::ServerSide,
             CredentialKind::MobileKey,
             CredentialKind::RelayAutoConfig,
+            CredentialKind::ApiToken,
         ];
         match self {
             ExpectedCredential::Any => format!(
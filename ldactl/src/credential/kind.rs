@@ -1,5 +1,6 @@
 use super::consts::{
-    CLIENT_SIDE_ID_LEN, MOBILE_KEY_LEN, RELAY_AUTO_CONFIG_KEY_LEN, SERVER_SIDE_KEY_LEN,
+    API_TOKEN_LEN, CLIENT_SIDE_ID_LEN, MOBILE_KEY_LEN, RELAY_AUTO_CONFIG_KEY_LEN,
+    SERVER_SIDE_KEY_LEN,
 };
 use std::fmt::{Display, Formatter};
 
@@ -9,6 +10,7 @@ pub enum CredentialKind {
     MobileKey,
     ClientSide,
     RelayAutoConfig,
+    ApiToken,
 }
 
 impl std::fmt::Debug for CredentialKind {
@@ -24,6 +26,7 @@ impl Display for CredentialKind {
             CredentialKind::MobileKey => write!(f, "Mobile Key"),
             CredentialKind::ClientSide => write!(f, "Client-side Id"),
             CredentialKind::RelayAutoConfig => write!(f, "Relay AutoConfig Key"),
+            CredentialKind::ApiToken => write!(f, "API Access Token"),
         }
     }
 }
@@ -36,15 +39,18 @@ impl CredentialKind {
             CredentialKind::MobileKey => "mobile-key",
             CredentialKind::ClientSide => "client-side-id",
             CredentialKind::RelayAutoConfig => "relay-auto-config-key",
+            CredentialKind::ApiToken => "api-token",
         }
     }
     #[inline]
+    #[allow(clippy::len_without_is_empty)]
     pub const fn len(&self) -> usize {
         match self {
             CredentialKind::ServerSide => SERVER_SIDE_KEY_LEN,
             CredentialKind::MobileKey => MOBILE_KEY_LEN,
             CredentialKind::RelayAutoConfig => RELAY_AUTO_CONFIG_KEY_LEN,
             CredentialKind::ClientSide => CLIENT_SIDE_ID_LEN,
+            CredentialKind::ApiToken => API_TOKEN_LEN,
         }
     }
 
@@ -54,6 +60,7 @@ impl CredentialKind {
             CredentialKind::ServerSide => Some("sdk-"),
             CredentialKind::MobileKey => Some("mob-"),
             CredentialKind::RelayAutoConfig => Some("rel-"),
+            CredentialKind::ApiToken => Some("api-"),
             CredentialKind::ClientSide => None,
         }
     }
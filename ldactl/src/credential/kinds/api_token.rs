@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use crate::credential::{
+    error::CredentialError, util::fmt_masked, CredentialKind, HasConstKind, LaunchDarklyCredential,
+    LaunchDarklyCredentialExt,
+};
+
+/// Access token for the LaunchDarkly REST API, not used by the stream itself
+///
+/// Displays and debugs masked, use [`LaunchDarklyCredential::expose_secret`] for the token
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ApiToken(String);
+
+impl HasConstKind for ApiToken {
+    const KIND: CredentialKind = CredentialKind::ApiToken;
+}
+impl LaunchDarklyCredential for ApiToken {
+    fn kind(&self) -> CredentialKind {
+        Self::KIND
+    }
+}
+impl LaunchDarklyCredentialExt for ApiToken {
+    type Inner = String;
+
+    unsafe fn from_inner_unchecked(s: Self::Inner) -> Self {
+        Self(s)
+    }
+}
+
+impl AsRef<str> for ApiToken {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+impl AsRef<[u8]> for ApiToken {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+impl TryFrom<&[u8]> for ApiToken {
+    type Error = CredentialError;
+    fn try_from(b: &[u8]) -> Result<Self, Self::Error> {
+        Self::try_from_bytes(b)
+    }
+}
+
+impl TryFrom<&str> for ApiToken {
+    type Error = CredentialError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::try_from_str(s)
+    }
+}
+impl TryFrom<String> for ApiToken {
+    type Error = CredentialError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_from_string(s)
+    }
+}
+impl std::str::FromStr for ApiToken {
+    type Err = CredentialError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_str(s)
+    }
+}
+
+impl std::fmt::Display for ApiToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_masked(Self::KIND, &self.0, f)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ApiToken {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for ApiToken {}
+
+impl std::fmt::Debug for ApiToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ApiToken")
+            .field(&format_args!("{}", self))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{credential::Credential, error::ExpectedCredential};
+
+    #[test]
+    fn parses_api_tokens() {
+        let token: ApiToken = "api-6c596994-34d0-4137-84c6-bef64a1732d0".parse().unwrap();
+        assert_eq!(token.kind(), CredentialKind::ApiToken);
+        assert_eq!(
+            token.to_string(),
+            "api-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxx1732d0"
+        );
+        let credential: Credential = "api-6c596994-34d0-4137-84c6-bef64a1732d0".parse().unwrap();
+        assert!(matches!(credential, Credential::Api(_)));
+
+        assert!(matches!(
+            "sdk-6c596994-34d0-4137-84c6-bef64a1732d0".parse::<ApiToken>(),
+            Err(CredentialError::InvalidPrefix {
+                expected: "api-",
+                ..
+            })
+        ));
+        assert!(matches!(
+            "api-6c596994".parse::<ApiToken>(),
+            Err(CredentialError::InvalidLength {
+                expected: ExpectedCredential::Kind(CredentialKind::ApiToken),
+                ..
+            })
+        ));
+    }
+}
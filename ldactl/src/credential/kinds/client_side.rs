@@ -8,6 +8,7 @@ use crate::credential::{
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String")]
 pub struct ClientSideId(String);
 impl Display for ClientSideId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -61,6 +62,12 @@ impl TryFrom<String> for ClientSideId {
         Self::try_from_string(s)
     }
 }
+impl std::str::FromStr for ClientSideId {
+    type Err = CredentialError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_str(s)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
 use crate::credential::{
-    error::CredentialError, CredentialKind, HasConstKind, LaunchDarklyCredential,
+    error::CredentialError, util::fmt_masked, CredentialKind, HasConstKind, LaunchDarklyCredential,
     LaunchDarklyCredentialExt,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Displays and debugs masked, use [`LaunchDarklyCredential::expose_secret`] for the key
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String")]
 pub struct MobileKey(String);
 
 impl HasConstKind for MobileKey {
@@ -55,9 +57,32 @@ impl TryFrom<String> for MobileKey {
         Self::try_from_string(s)
     }
 }
+impl std::str::FromStr for MobileKey {
+    type Err = CredentialError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_str(s)
+    }
+}
 
 impl std::fmt::Display for MobileKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_str())
+        fmt_masked(Self::KIND, &self.0, f)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for MobileKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for MobileKey {}
+
+impl std::fmt::Debug for MobileKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MobileKey")
+            .field(&format_args!("{}", self))
+            .finish()
     }
 }
@@ -2,8 +2,10 @@ mod server_side;
 mod mobile;
 mod client_side;
 mod relay_auto_config;
+mod api_token;
 
 pub use server_side::*;
 pub use mobile::*;
 pub use client_side::*;
-pub use relay_auto_config::*;
\ No newline at end of file
+pub use relay_auto_config::*;
+pub use api_token::*;
\ No newline at end of file
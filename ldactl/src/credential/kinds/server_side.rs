@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
 use crate::credential::{
-    error::CredentialError, CredentialKind, HasConstKind, LaunchDarklyCredential,
+    error::CredentialError, util::fmt_masked, CredentialKind, HasConstKind, LaunchDarklyCredential,
     LaunchDarklyCredentialExt,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Displays and debugs masked, use [`LaunchDarklyCredential::expose_secret`] for the key
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String")]
 pub struct ServerSideKey(String);
 
 impl HasConstKind for ServerSideKey {
@@ -55,13 +57,32 @@ impl TryFrom<String> for ServerSideKey {
         Self::try_from_string(s)
     }
 }
+impl std::str::FromStr for ServerSideKey {
+    type Err = CredentialError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_str(s)
+    }
+}
 
 impl std::fmt::Display for ServerSideKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "sdk-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxx{}",
-            self.0.get(self.0.len() - 6..).unwrap_or("xxxxxx")
-        )
+        fmt_masked(Self::KIND, &self.0, f)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ServerSideKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for ServerSideKey {}
+
+impl std::fmt::Debug for ServerSideKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ServerSideKey")
+            .field(&format_args!("{}", self))
+            .finish()
     }
 }
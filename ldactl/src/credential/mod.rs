@@ -1,17 +1,24 @@
+#[allow(clippy::module_inception)]
 mod credential;
 pub mod error;
 mod kind;
 mod kinds;
+mod source;
 
 mod traits;
 mod util;
+mod value_parser;
+pub use credential::Credential;
 pub use kind::*;
 pub use kinds::*;
+pub use source::{load, resolve, CredentialSourceError};
 pub use traits::*;
+pub use value_parser::CredentialValueParser;
 
 mod consts {
     pub const SERVER_SIDE_KEY_LEN: usize = 40;
     pub const RELAY_AUTO_CONFIG_KEY_LEN: usize = 40;
     pub const MOBILE_KEY_LEN: usize = 40;
     pub const CLIENT_SIDE_ID_LEN: usize = 24;
+    pub const API_TOKEN_LEN: usize = 40;
 }
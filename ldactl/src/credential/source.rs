@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use super::error::CredentialError;
+
+const FILE_PREFIX: &str = "file:";
+const ENV_PREFIX: &str = "env:";
+
+#[derive(Error, Debug)]
+pub enum CredentialSourceError {
+    #[error("failed to read credential from {path:?}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("environment variable {0:?} is not set")]
+    MissingEnv(String),
+
+    #[error("environment variable {0:?} is not valid utf8")]
+    InvalidEnv(String),
+
+    #[error(transparent)]
+    Invalid(#[from] CredentialError),
+}
+
+/// Reads a credential that may be given by reference instead of by value
+///
+/// `file:/run/secrets/ld-key` reads it from a file, such as a mounted secret, and
+/// `env:VAR_NAME` from another environment variable, so the key itself doesn't show up in
+/// process listings. Anything else is the credential itself. Surrounding whitespace is trimmed
+/// so files can end with a newline.
+///
+/// ```no_run
+/// use launchdarkly_autoconfig::credential::{self, RelayAutoConfigKey};
+///
+/// let key: RelayAutoConfigKey = credential::load("file:/run/secrets/ld-key").unwrap();
+/// ```
+pub fn load<T>(value: &str) -> Result<T, CredentialSourceError>
+where
+    T: TryFrom<String, Error = CredentialError>,
+{
+    Ok(T::try_from(resolve(value)?)?)
+}
+
+/// The credential `value` refers to, see [`load`]
+pub fn resolve(value: &str) -> Result<String, CredentialSourceError> {
+    let value = if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+        std::fs::read_to_string(path).map_err(|source| CredentialSourceError::Read {
+            path: path.into(),
+            source,
+        })?
+    } else if let Some(var) = value.strip_prefix(ENV_PREFIX) {
+        std::env::var(var).map_err(|e| match e {
+            std::env::VarError::NotPresent => CredentialSourceError::MissingEnv(var.to_owned()),
+            std::env::VarError::NotUnicode(_) => CredentialSourceError::InvalidEnv(var.to_owned()),
+        })?
+    } else {
+        value.to_owned()
+    };
+    Ok(trim(value))
+}
+
+fn trim(value: String) -> String {
+    let trimmed = value.trim();
+    if trimmed.len() == value.len() {
+        return value;
+    }
+    let trimmed = trimmed.to_owned();
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(&mut { value });
+    trimmed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{LaunchDarklyCredential, RelayAutoConfigKey};
+    use std::io::Write;
+
+    const KEY: &str = "rel-3d560391-904c-4afd-8075-faad7652ed1d";
+
+    #[test]
+    fn loads_from_files_and_env() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "{}", KEY).unwrap();
+        let key: RelayAutoConfigKey = load(&format!("file:{}", file.path().display())).unwrap();
+        assert_eq!(key.expose_secret(), KEY);
+
+        std::env::set_var("LDACTL_TEST_CREDENTIAL_SOURCE", KEY);
+        let key: RelayAutoConfigKey = load("env:LDACTL_TEST_CREDENTIAL_SOURCE").unwrap();
+        assert_eq!(key.expose_secret(), KEY);
+
+        let key: RelayAutoConfigKey = load(KEY).unwrap();
+        assert_eq!(key.expose_secret(), KEY);
+
+        assert!(matches!(
+            load::<RelayAutoConfigKey>("env:LDACTL_TEST_CREDENTIAL_SOURCE_MISSING"),
+            Err(CredentialSourceError::MissingEnv(_))
+        ));
+        assert!(matches!(
+            load::<RelayAutoConfigKey>("file:/nonexistent/ld-key"),
+            Err(CredentialSourceError::Read { .. })
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::credential::util::{validate_credential_uuid, validate_uuid_format};
+use crate::credential::util::validate_uuid_format;
 
 use super::{error::CredentialError, CredentialKind};
 
@@ -12,9 +12,18 @@ pub trait LaunchDarklyCredential: Sized + AsRef<str> + AsRef<[u8]> + TryFrom<Str
         self.as_ref()
     }
 
+    /// Copies the credential, with the `zeroize` feature the copy isn't wiped on drop
     fn into_string(self) -> String {
         self.as_str().into()
     }
+
+    /// The full credential, for sending it to LaunchDarkly
+    ///
+    /// `Display` and `Debug` mask secret kinds, this makes it obvious where the key itself is
+    /// handed out.
+    fn expose_secret(&self) -> &str {
+        self.as_str()
+    }
 }
 
 pub trait HasConstKind {
@@ -23,6 +32,9 @@ pub trait HasConstKind {
 
 pub trait LaunchDarklyCredentialExt: LaunchDarklyCredential + HasConstKind {
     type Inner: From<String>;
+    /// # Safety
+    ///
+    /// `s` must already be a valid credential of kind [`HasConstKind::KIND`]
     unsafe fn from_inner_unchecked(s: Self::Inner) -> Self;
 
     fn try_validate(b: &[u8]) -> Result<(), CredentialError> {
@@ -76,8 +88,14 @@ pub trait LaunchDarklyCredentialExt: LaunchDarklyCredential + HasConstKind {
         Self::try_from_bytes(s.as_bytes())
     }
 
+    #[cfg_attr(not(feature = "zeroize"), allow(clippy::question_mark))]
     fn try_from_string(s: String) -> Result<Self, CredentialError> {
-        Self::try_validate(s.as_bytes())?;
+        if let Err(e) = Self::try_validate(s.as_bytes()) {
+            // rejected keys can still be secrets, such as a key of the wrong kind
+            #[cfg(feature = "zeroize")]
+            zeroize::Zeroize::zeroize(&mut { s });
+            return Err(e);
+        }
         // Same as try_from_str
         Ok(unsafe { Self::from_inner_unchecked(s.into()) })
     }
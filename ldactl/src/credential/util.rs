@@ -1,37 +1,5 @@
 use super::{error::CredentialError, CredentialKind};
 
-pub fn validate_credential_uuid(kind: CredentialKind, s: &[u8]) -> Result<(), CredentialError> {
-    let prefix = kind
-        .prefix()
-        .expect("Only prefixed kinds can be used with validate_prefix_key");
-
-    let len = prefix.as_bytes().len();
-    if len != kind.len() {
-        return Err(CredentialError::InvalidLength {
-            expected: kind.into(),
-            found: len,
-        });
-    }
-    match s.get(..len) {
-        Some(p) if p == prefix.as_bytes() => Ok(()),
-        Some(p) => Err(CredentialError::InvalidPrefix {
-            expected: prefix,
-            found: Some(String::from_utf8_lossy(p).into()),
-        }),
-        None => Err(CredentialError::InvalidLength {
-            expected: kind.into(),
-            found: s.len(),
-        }),
-    }?;
-    match s.get(len..) {
-        Some(s) => validate_uuid_format(kind, s),
-        None => Err(CredentialError::InvalidLength {
-            expected: kind.into(),
-            found: s.len(),
-        }),
-    }
-}
-
 pub fn validate_uuid_format(kind: CredentialKind, s: &[u8]) -> Result<(), CredentialError> {
     assert_ne!(kind, CredentialKind::ClientSide);
 
@@ -74,6 +42,21 @@ pub fn validate_uuid_format(kind: CredentialKind, s: &[u8]) -> Result<(), Creden
     }
 }
 
+/// Writes a key with everything but its prefix and last 6 characters masked, such as
+/// `sdk-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxf1d71e`
+pub(crate) fn fmt_masked(
+    kind: CredentialKind,
+    s: &str,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    write!(
+        f,
+        "{}xxxxxxxx-xxxx-xxxx-xxxx-xxxxxx{}",
+        kind.prefix().unwrap_or_default(),
+        s.get(s.len().saturating_sub(6)..).unwrap_or("xxxxxx")
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,6 +111,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn masks_all_secret_kinds() {
+        use crate::credential::credential::Credential;
+        use crate::credential::{
+            LaunchDarklyCredential, LaunchDarklyCredentialExt, MobileKey, RelayAutoConfigKey,
+            ServerSideKey,
+        };
+
+        let sdk_key =
+            ServerSideKey::try_from_str("sdk-011511cd-335b-47af-9e01-05a0daf1d71e").unwrap();
+        assert_eq!(
+            sdk_key.to_string(),
+            "sdk-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxf1d71e"
+        );
+        assert_eq!(
+            format!("{:?}", sdk_key),
+            "ServerSideKey(sdk-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxf1d71e)"
+        );
+        assert_eq!(
+            sdk_key.expose_secret(),
+            "sdk-011511cd-335b-47af-9e01-05a0daf1d71e"
+        );
+
+        let mob_key = MobileKey::try_from_str("mob-b5734766-5a3d-4b41-b63f-2669a4fb6497").unwrap();
+        assert_eq!(
+            mob_key.to_string(),
+            "mob-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxfb6497"
+        );
+        let credential = Credential::Mobile(mob_key);
+        assert!(!format!("{:?} {}", credential, credential).contains("b5734766"));
+
+        let rel_key =
+            RelayAutoConfigKey::try_from_str("rel-3d560391-904c-4afd-8075-faad7652ed1d").unwrap();
+        assert!(!format!("{:?}", rel_key).contains("3d560391"));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn secret_kinds_zeroize_on_drop() {
+        use crate::credential::{ApiToken, MobileKey, RelayAutoConfigKey, ServerSideKey};
+        fn assert_zeroize_on_drop<T: zeroize::ZeroizeOnDrop>() {}
+        assert_zeroize_on_drop::<ServerSideKey>();
+        assert_zeroize_on_drop::<MobileKey>();
+        assert_zeroize_on_drop::<RelayAutoConfigKey>();
+        assert_zeroize_on_drop::<ApiToken>();
+    }
+
     #[test]
     fn fails_if_not_hex() {
         let uuid = "00U00000-0000-0000-0000-000000000000";
@@ -0,0 +1,106 @@
+use std::{ffi::OsStr, marker::PhantomData};
+
+use clap::{builder::TypedValueParser, error::ErrorKind, Arg, Command};
+
+use super::{error::CredentialError, source::load, LaunchDarklyCredentialExt};
+
+/// Parses command line arguments into a credential of kind `T`
+///
+/// ```no_run
+/// # use launchdarkly_autoconfig::credential::{CredentialValueParser, RelayAutoConfigKey};
+/// #[derive(clap::Parser)]
+/// struct Args {
+///     #[arg(long, value_parser = CredentialValueParser::<RelayAutoConfigKey>::new())]
+///     credential: RelayAutoConfigKey,
+/// }
+/// ```
+///
+/// Values can refer to a file or another environment variable, such as
+/// `file:/run/secrets/ld-key`, see [`super::load`].
+///
+/// Unlike clap's default parser for `FromStr` types, errors don't repeat the rejected value,
+/// which may well be a secret of another kind.
+#[derive(Debug)]
+pub struct CredentialValueParser<T>(PhantomData<fn() -> T>);
+
+impl<T> CredentialValueParser<T> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for CredentialValueParser<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for CredentialValueParser<T> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TypedValueParser for CredentialValueParser<T>
+where
+    T: LaunchDarklyCredentialExt + TryFrom<String, Error = CredentialError>,
+    T: Clone + Send + Sync + 'static,
+{
+    type Value = T;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let arg = arg.map_or_else(|| "...".to_owned(), ToString::to_string);
+        let value = value.to_str().ok_or_else(|| {
+            clap::Error::raw(
+                ErrorKind::InvalidUtf8,
+                format!("invalid utf-8 in {}\n", arg),
+            )
+            .with_cmd(cmd)
+        })?;
+        load::<T>(value).map_err(|e| {
+            clap::Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid {} for {}: {}\n", T::KIND, arg, e),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credential::{LaunchDarklyCredential, RelayAutoConfigKey, ServerSideKey};
+
+    fn command() -> Command {
+        Command::new("test").arg(
+            Arg::new("credential")
+                .long("credential")
+                .value_parser(CredentialValueParser::<RelayAutoConfigKey>::new()),
+        )
+    }
+
+    #[test]
+    fn parses_credentials_without_echoing_them() {
+        let key = "rel-3d560391-904c-4afd-8075-faad7652ed1d";
+        let matches = command()
+            .try_get_matches_from(["test", "--credential", key])
+            .unwrap();
+        let credential = matches.get_one::<RelayAutoConfigKey>("credential").unwrap();
+        assert_eq!(credential.expose_secret(), key);
+
+        let sdk_key = "sdk-3d560391-904c-4afd-8075-faad7652ed1d";
+        let error = command()
+            .try_get_matches_from(["test", "--credential", sdk_key])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+        assert!(!error.to_string().contains("3d560391"), "{}", error);
+
+        assert!(sdk_key.parse::<ServerSideKey>().is_ok());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fmt, str::FromStr};
+
+use miette::Diagnostic;
+use reqwest::Url;
+use thiserror::Error;
+
+const COMMERCIAL: &str = "https://stream.launchdarkly.com/";
+const EU: &str = "https://stream.eu.launchdarkly.com/";
+const FEDERAL: &str = "https://stream.launchdarkly.us/";
+const RELAY_AUTO_CONFIG_PATH: &str = "relay_auto_config";
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("invalid endpoint {0:?}")]
+#[diagnostic(help("use commercial, eu, federal or the base url of the stream"))]
+pub struct EndpointParseError(String, #[source] <Url as FromStr>::Err);
+
+/// Which LaunchDarkly instance to stream from
+///
+/// Parses from `commercial`, `eu`, `federal` or the base url of any other stream, such as
+/// `https://stream.example.com/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Endpoint {
+    /// `stream.launchdarkly.com`
+    #[default]
+    Commercial,
+    /// `stream.eu.launchdarkly.com`
+    Eu,
+    /// `stream.launchdarkly.us`, for the federal instance
+    Federal,
+    /// Base url of the stream, `relay_auto_config` is appended to it
+    Custom(Url),
+}
+
+impl Endpoint {
+    pub fn base_url(&self) -> Url {
+        match self {
+            Endpoint::Commercial => Url::parse(COMMERCIAL).unwrap(),
+            Endpoint::Eu => Url::parse(EU).unwrap(),
+            Endpoint::Federal => Url::parse(FEDERAL).unwrap(),
+            Endpoint::Custom(url) => url.clone(),
+        }
+    }
+
+    /// Url of the Relay AutoConfig stream
+    pub fn stream_url(&self) -> Url {
+        let mut base = self.base_url();
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        base.join(RELAY_AUTO_CONFIG_PATH).unwrap()
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = EndpointParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "commercial" => Ok(Endpoint::Commercial),
+            "eu" => Ok(Endpoint::Eu),
+            "federal" => Ok(Endpoint::Federal),
+            _ => Url::parse(s)
+                .map(Endpoint::Custom)
+                .map_err(|e| EndpointParseError(s.to_owned(), e)),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.stream_url())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presets_and_urls() {
+        assert_eq!("EU".parse::<Endpoint>().unwrap(), Endpoint::Eu);
+        assert_eq!(
+            Endpoint::Federal.stream_url().as_str(),
+            "https://stream.launchdarkly.us/relay_auto_config"
+        );
+        let custom: Endpoint = "https://relay.example.com/ld".parse().unwrap();
+        assert_eq!(
+            custom.stream_url().as_str(),
+            "https://relay.example.com/ld/relay_auto_config"
+        );
+        assert!("stream.example.com".parse::<Endpoint>().is_err());
+    }
+}
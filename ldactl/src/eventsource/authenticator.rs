@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use reqwest::{Request, StatusCode};
+
+/// Error returned by [`Authenticator::authorize`]
+pub type AuthorizeError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Adds credentials to each connection attempt made by [`super::EventSource`]
+///
+/// `authorize` runs before every connection attempt, so expiring tokens can be refreshed
+/// without recreating the event source and losing its `Last-Event-ID`. When the server rejects
+/// a connection with 401 or 403, the event source authorizes again and retries once right away
+/// before treating the status like any other error.
+#[async_trait]
+pub trait Authenticator: Send {
+    /// Add credentials to the request, usually by inserting an `Authorization` header.
+    /// Errors are retried according to the configured reconnect policy
+    async fn authorize(&mut self, request: &mut Request) -> Result<(), AuthorizeError>;
+
+    /// Called when the server rejects the credentials, before `authorize` runs again.
+    /// Use it to drop cached tokens
+    fn on_auth_failure(&mut self, status: StatusCode) {
+        let _ = status;
+    }
+}
+
+pub(crate) type SharedAuthenticator = Arc<Mutex<Box<dyn Authenticator>>>;
+
+/// Statuses that mean the credentials were rejected
+pub(crate) fn is_auth_failure(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder,
+    };
+    use super::*;
+    use futures::StreamExt;
+
+    /// Hands out a new token after every auth failure
+    struct RotatingToken {
+        generation: usize,
+        authorized: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Authenticator for RotatingToken {
+        async fn authorize(&mut self, request: &mut Request) -> Result<(), AuthorizeError> {
+            let token = format!("token-{}", self.generation);
+            request
+                .headers_mut()
+                .insert(reqwest::header::AUTHORIZATION, token.parse()?);
+            self.authorized.lock().unwrap().push(token);
+            Ok(())
+        }
+
+        fn on_auth_failure(&mut self, status: StatusCode) {
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            self.generation += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn reauthorizes_after_auth_failure() {
+        let server = MockServer::start([
+            MockResponse::status(401),
+            MockResponse::sse().event("hello"),
+        ])
+        .await;
+        let authorized = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .authenticator(RotatingToken {
+                    generation: 0,
+                    authorized: authorized.clone(),
+                })
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        assert_eq!(*authorized.lock().unwrap(), ["token-0", "token-1"]);
+        let requests = server.requests();
+        assert_eq!(requests[0].header("authorization"), Some("token-0"));
+        assert_eq!(requests[1].header("authorization"), Some("token-1"));
+    }
+}
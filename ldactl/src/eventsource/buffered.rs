@@ -0,0 +1,77 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{channel::mpsc, SinkExt, Stream, StreamExt};
+use tokio::task::JoinHandle;
+use tokio_sse_codec::{BytesStr, Event};
+use tracing::{debug, Instrument};
+
+use super::{EventSource, EventSourceError};
+
+type Item = Result<Event<BytesStr>, EventSourceError>;
+
+/// Reads events ahead of the consumer into a bounded queue, see [`EventSource::into_buffered`]
+///
+/// Dropping it stops the background task and closes the connection.
+pub struct BufferedEventSource {
+    receiver: mpsc::Receiver<Item>,
+    task: JoinHandle<()>,
+}
+
+impl BufferedEventSource {
+    pub(crate) fn spawn(event_source: EventSource, capacity: usize) -> Self {
+        // the sender gets a guaranteed slot of its own, so the queue holds `capacity` items
+        let (mut sender, receiver) = mpsc::channel(capacity.saturating_sub(1));
+        let task = tokio::spawn(
+            async move {
+                let mut event_source = Box::pin(event_source);
+                while let Some(item) = event_source.next().await {
+                    if sender.send(item).await.is_err() {
+                        debug!("buffered event source dropped, closing connection");
+                        break;
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+        Self { receiver, task }
+    }
+}
+
+impl Stream for BufferedEventSource {
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for BufferedEventSource {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder,
+    };
+    use super::*;
+
+    #[tokio::test]
+    async fn forwards_events_in_order() {
+        let server =
+            MockServer::start([MockResponse::sse().event("one").event("two").event("three")]).await;
+        let es = EventSourceBuilder::get(server.url()).build().unwrap();
+        let events: Vec<_> = es
+            .into_buffered(1)
+            .map(|item| item.unwrap().data.to_string())
+            .collect()
+            .await;
+        assert_eq!(events, ["one", "two", "three"]);
+    }
+}
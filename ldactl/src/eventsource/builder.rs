@@ -1,22 +1,40 @@
-use std::{convert::Infallible, fmt};
+use std::{collections::HashSet, convert::Infallible, fmt, sync::Arc};
 
 use backoff::backoff::Backoff;
-use eventsource_client::ClientBuilder;
+use futures::lock::Mutex;
+use miette::Diagnostic;
 use reqwest::{
     header::{self, HeaderName, HeaderValue, InvalidHeaderName, InvalidHeaderValue},
-    ClientBuilder as ReqwestClientBuilder, Url,
+    ClientBuilder as ReqwestClientBuilder, IntoUrl, StatusCode, Url,
 };
 use thiserror::Error;
 use tokio_sse_codec::BytesStr;
-use tracing::{debug_span, Span};
+use tower::{Layer, Service};
+use tracing::warn;
 
-use super::{sse_backoff::WithMinimumBackoff, EventSource};
+use super::{
+    authenticator::{Authenticator, SharedAuthenticator},
+    eventsource::{
+        set_standard_headers, BodyFactory, LastEventIdMode, OnRetry, DEFAULT_CONTENT_TYPE,
+        DEFAULT_READ_BUFFER_CAPACITY, DEFAULT_USER_AGENT,
+    },
+    failover::{Endpoints, DEFAULT_FAILOVER_AFTER, DEFAULT_PRIMARY_PROBE_INTERVAL},
+    middleware::{box_layer, build_service, BoxLayer, HttpService},
+    reconnect_policy::ReconnectPolicy,
+    recording::Recorder,
+    redirect::{redirect_policy, DEFAULT_MAX_REDIRECTS},
+    retryable::{is_retryable_status, RetryStatusPredicate},
+    sse_backoff::{
+        CircuitBreaker, CircuitBreakerBackoff, ExactBackoffDuration, Jitter, JitteredBackoff,
+        LimitedBackoff, RateLimitedBackoff, ReconnectLimiter, RetryFieldMode, WithMinimumBackoff,
+    },
+    EventSource,
+};
 mod http {
-    pub use reqwest::header;
     pub use reqwest::Error;
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, Diagnostic)]
 pub enum EventSourceBuilderError {
     #[error("failed to build request")]
     Request(#[from] http::Error),
@@ -25,7 +43,7 @@ pub enum EventSourceBuilderError {
     #[error("invalid header name")]
     InvalidHeaderName(#[from] InvalidHeaderName),
     #[error("error while building headers: {0}")]
-    Other(#[source] Box<dyn std::error::Error>),
+    Other(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
 impl From<Infallible> for EventSourceBuilderError {
     fn from(_: Infallible) -> Self {
@@ -35,31 +53,76 @@ impl From<Infallible> for EventSourceBuilderError {
 
 pub struct EventSourceBuilder {
     read_timeout_duration: std::time::Duration,
-    backoff: Option<Box<dyn backoff::backoff::Backoff>>,
+    reconnect_policy: Option<Box<dyn ReconnectPolicy>>,
     client_builder: ReqwestClientBuilder,
     request: Result<reqwest::Request, EventSourceBuilderError>,
     last_event_id: Option<BytesStr>,
     error: Option<EventSourceBuilderError>,
     redirect_policy: reqwest::redirect::Policy,
+    max_redirects: usize,
+    retry_status: RetryStatusPredicate,
+    content_types: Vec<String>,
+    max_retries: Option<usize>,
+    max_elapsed: Option<std::time::Duration>,
+    jitter: Jitter,
+    retry_field_mode: RetryFieldMode,
+    authenticator: Option<SharedAuthenticator>,
+    on_retry: Option<OnRetry>,
+    body_factory: Option<BodyFactory>,
+    last_event_id_mode: LastEventIdMode,
+    failover_urls: Vec<Url>,
+    failover_after: usize,
+    primary_probe_interval: Option<std::time::Duration>,
+    max_idle_reconnects: Option<usize>,
+    max_event_size: Option<usize>,
+    initialization_timeout: Option<std::time::Duration>,
+    read_buffer_capacity: usize,
+    layers: Vec<BoxLayer>,
+    circuit_breaker: Option<CircuitBreaker>,
+    reconnect_limiter: Option<std::sync::Arc<ReconnectLimiter>>,
+    recorder: Option<Recorder>,
+    event_filter: Option<HashSet<String>>,
+    default_headers: reqwest::header::HeaderMap,
+    user_agent: HeaderValue,
+    reresolve_on_reconnect: bool,
 }
 
 impl EventSourceBuilder {
     pub fn from_request(request: reqwest::Request) -> Self {
-        let mut request = request;
-        request
-            .headers_mut()
-            .insert("accept", "text/event-stream".parse().unwrap());
-        request
-            .headers_mut()
-            .insert("cache-control", "no-cache".parse().unwrap());
         Self {
             read_timeout_duration: std::time::Duration::from_secs(5 * 60),
-            backoff: None,
+            reconnect_policy: None,
             client_builder: ReqwestClientBuilder::new(),
             request: Ok(request),
             last_event_id: None,
             error: None,
             redirect_policy: reqwest::redirect::Policy::default(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            retry_status: Arc::new(is_retryable_status),
+            content_types: vec![DEFAULT_CONTENT_TYPE.into()],
+            max_retries: None,
+            max_elapsed: None,
+            jitter: Jitter::None,
+            retry_field_mode: RetryFieldMode::Minimum,
+            authenticator: None,
+            on_retry: None,
+            body_factory: None,
+            last_event_id_mode: LastEventIdMode::Header,
+            failover_urls: Vec::new(),
+            failover_after: DEFAULT_FAILOVER_AFTER,
+            primary_probe_interval: Some(DEFAULT_PRIMARY_PROBE_INTERVAL),
+            max_idle_reconnects: None,
+            max_event_size: None,
+            initialization_timeout: None,
+            read_buffer_capacity: DEFAULT_READ_BUFFER_CAPACITY,
+            layers: Vec::new(),
+            circuit_breaker: None,
+            reconnect_limiter: None,
+            recorder: None,
+            event_filter: None,
+            default_headers: reqwest::header::HeaderMap::new(),
+            user_agent: HeaderValue::from_static(DEFAULT_USER_AGENT),
+            reresolve_on_reconnect: false,
         }
     }
     pub fn new(url: Url) -> Self {
@@ -88,19 +151,210 @@ impl EventSourceBuilder {
         self.client_builder = client_builder;
         self
     }
+    /// Keep cookies set by the server across reconnects, for endpoints that rely on session cookies
+    pub fn cookie_store(mut self, enable: bool) -> Self {
+        self.client_builder = self.client_builder.cookie_store(enable);
+        self
+    }
+    /// Use a custom cookie store, for example a [`reqwest::cookie::Jar`] seeded with a session cookie.
+    /// Implies [`EventSourceBuilder::cookie_store`]
+    pub fn cookie_provider<C>(mut self, cookie_store: Arc<C>) -> Self
+    where
+        C: reqwest::cookie::CookieStore + 'static,
+    {
+        self.client_builder = self.client_builder.cookie_provider(cookie_store);
+        self
+    }
+    /// Send all traffic through a proxy. `http://`, `https://` and `socks5://` urls are supported,
+    /// credentials can be passed in the url
+    pub fn proxy<U: IntoUrl>(self, proxy_url: U) -> Self {
+        match reqwest::Proxy::all(proxy_url) {
+            Ok(proxy) => self.proxy_with(proxy),
+            Err(e) => self.with_error(e.into()),
+        }
+    }
+    /// Add a preconfigured proxy, for example one that only applies to https or uses basic auth
+    pub fn proxy_with(mut self, proxy: reqwest::Proxy) -> Self {
+        self.client_builder = self.client_builder.proxy(proxy);
+        self
+    }
+    /// Ignore proxies configured by the environment such as `HTTPS_PROXY`
+    pub fn no_proxy(mut self) -> Self {
+        self.client_builder = self.client_builder.no_proxy();
+        self
+    }
+    /// Resolve hostnames with a custom resolver instead of the system's `getaddrinfo`
+    pub fn dns_resolver<R>(mut self, resolver: Arc<R>) -> Self
+    where
+        R: reqwest::dns::Resolve + 'static,
+    {
+        self.client_builder = self.client_builder.dns_resolver(resolver);
+        self
+    }
+    /// Connect to `addr` for `domain` without resolving it. The port of `addr` is ignored,
+    /// the url's port is used instead
+    pub fn resolve(mut self, domain: &str, addr: std::net::SocketAddr) -> Self {
+        self.client_builder = self.client_builder.resolve(domain, addr);
+        self
+    }
+    /// Open a new connection, and resolve the hostname again, on every reconnect instead of reusing
+    /// a pooled connection. Long-lived processes then follow DNS failovers of the stream endpoint
+    /// rather than staying pinned to the address they first connected to
+    pub fn reresolve_on_reconnect(mut self, enable: bool) -> Self {
+        self.reresolve_on_reconnect = enable;
+        self
+    }
+    /// Trust an additional root certificate, for example a corporate proxy's CA
+    pub fn add_root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.client_builder = self.client_builder.add_root_certificate(certificate);
+        self
+    }
+    /// Trust an additional PEM encoded root certificate
+    pub fn add_root_certificate_pem(self, pem: &[u8]) -> Self {
+        match reqwest::Certificate::from_pem(pem) {
+            Ok(certificate) => self.add_root_certificate(certificate),
+            Err(e) => self.with_error(e.into()),
+        }
+    }
+    /// Present a client certificate to endpoints that require mutual TLS
+    pub fn identity(mut self, identity: reqwest::Identity) -> Self {
+        self.client_builder = self.client_builder.identity(identity);
+        self
+    }
+    /// Refuse to connect with TLS versions older than `version`
+    pub fn min_tls_version(mut self, version: reqwest::tls::Version) -> Self {
+        self.client_builder = self.client_builder.min_tls_version(version);
+        self
+    }
+    /// Accept invalid or self-signed certificates. This makes the connection vulnerable to
+    /// man-in-the-middle attacks and should only be used for testing
+    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        if accept_invalid_certs {
+            warn!("certificate validation is disabled for this event source");
+        }
+        self.client_builder = self
+            .client_builder
+            .danger_accept_invalid_certs(accept_invalid_certs);
+        self
+    }
+    // keep the first error, it's reported by build
+    fn with_error(mut self, error: EventSourceBuilderError) -> Self {
+        self.error.get_or_insert(error);
+        self
+    }
     pub fn read_timeout(mut self, read_timeout: std::time::Duration) -> Self {
         self.read_timeout_duration = read_timeout;
         self
     }
-    pub fn with_backoff_strategy<T>(mut self, backoff_strategy: T) -> Self
+    /// Fail with [`super::EventSourceError::InitializationTimeout`] if the first connection isn't
+    /// established within `timeout`, counting from the first poll. Retries after the first
+    /// successful connection are unaffected
+    pub fn initialization_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.initialization_timeout = Some(timeout);
+        self
+    }
+    /// Fail with [`super::EventSourceError::DecodeError`] when a single event grows beyond
+    /// `max_event_size` bytes instead of buffering it without limit
+    pub fn max_event_size(mut self, max_event_size: usize) -> Self {
+        self.max_event_size = Some(max_event_size);
+        self
+    }
+    /// Write every chunk of every response body to `writer`, so the session can be replayed
+    /// later with [`super::ReplayStream`]. Write errors are logged and otherwise ignored
+    pub fn record_to<W>(mut self, writer: W) -> Self
     where
-        T: Backoff + Sized + 'static,
+        W: std::io::Write + Send + 'static,
     {
-        self.backoff = Some(Box::new(backoff_strategy));
+        self.recorder = Some(Recorder::new(writer));
+        self
+    }
+    /// Initial capacity of the buffer the response body is read into. Defaults to 8KiB
+    pub fn read_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.read_buffer_capacity = capacity;
+        self
+    }
+    /// Reconnect immediately when the read timeout elapses instead of going through the backoff.
+    /// [`super::EventSourceError::ReadTimeoutElapsed`] is only yielded after `max_idle_reconnects`
+    /// consecutive idle connections, the event source keeps reconnecting afterwards
+    pub fn idle_reconnect(mut self, max_idle_reconnects: usize) -> Self {
+        self.max_idle_reconnects = Some(max_idle_reconnects);
+        self
+    }
+    pub fn with_backoff_strategy<T>(self, backoff_strategy: T) -> Self
+    where
+        T: Backoff + Send + Sized + 'static,
+    {
+        self.reconnect_policy(backoff_strategy)
+    }
+    /// Decide how long to wait between connection attempts. See [`ReconnectPolicy`]
+    ///
+    /// `max_retries`, `max_elapsed`, `jitter` and the server's `retry:` field are applied on top of it
+    pub fn reconnect_policy<P>(mut self, reconnect_policy: P) -> Self
+    where
+        P: ReconnectPolicy + 'static,
+    {
+        self.reconnect_policy = Some(Box::new(reconnect_policy));
+        self
+    }
+    /// Give up with [`super::EventSourceError::MaxRetriesExceeded`] after `max_retries` consecutive failed attempts.
+    /// Applies on top of whichever backoff strategy is configured
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+    /// Give up with [`super::EventSourceError::MaxRetriesExceeded`] once retrying has taken longer than `max_elapsed`.
+    /// Applies on top of whichever backoff strategy is configured
+    pub fn max_elapsed(mut self, max_elapsed: std::time::Duration) -> Self {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+    /// Randomize retry delays so a fleet of clients doesn't reconnect at the same time.
+    /// See [`Jitter`] for the available strategies
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    /// Back off for [`CircuitBreaker::open_for`] after repeated failures instead of retrying
+    /// on the usual schedule, so an endpoint that keeps failing isn't hammered forever
+    pub fn circuit_breaker(mut self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+    /// Wait for a token from a [`ReconnectLimiter`] shared with other event sources before each
+    /// reconnect, on top of the reconnect policy's delay
+    pub fn reconnect_limiter(mut self, limiter: std::sync::Arc<ReconnectLimiter>) -> Self {
+        self.reconnect_limiter = Some(limiter);
+        self
+    }
+    /// Choose whether the server's `retry:` field sets a minimum or the exact reconnect delay.
+    /// Defaults to [`RetryFieldMode::Minimum`]
+    pub fn retry_field_mode(mut self, mode: RetryFieldMode) -> Self {
+        self.retry_field_mode = mode;
+        self
+    }
+    /// Authorize every connection attempt, refreshing credentials when the server rejects them.
+    /// See [`Authenticator`]
+    pub fn authenticator<A>(mut self, authenticator: A) -> Self
+    where
+        A: Authenticator + 'static,
+    {
+        self.authenticator = Some(Arc::new(Mutex::new(Box::new(authenticator))));
+        self
+    }
+    /// Change the request before each connection attempt, for example to add diagnostic headers
+    /// or rotate between endpoints.
+    ///
+    /// Runs before every attempt including the first, after permanent redirects have been applied
+    /// and before the [`Authenticator`]. `attempt` starts at 1 and resets after a successful connection
+    pub fn on_retry<F>(mut self, on_retry: F) -> Self
+    where
+        F: FnMut(&mut reqwest::Request, usize) + Send + 'static,
+    {
+        self.on_retry = Some(Box::new(on_retry));
         self
     }
     pub fn with_expontential_backoff(
-        mut self,
+        self,
         initial_delay: std::time::Duration,
         max_delay: std::time::Duration,
         max_elapsed_time: std::time::Duration,
@@ -113,10 +367,59 @@ impl EventSourceBuilder {
                 .build(),
         )
     }
+    /// Decide which HTTP status codes should be retried.
+    /// Defaults to [`is_retryable_status`] (5xx, 408 and 429)
+    pub fn retry_status<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(StatusCode) -> bool + Send + Sync + 'static,
+    {
+        self.retry_status = Arc::new(predicate);
+        self
+    }
+    /// Only yield events with one of the given names, unnamed events are named `message`.
+    /// Other events are dropped before they're yielded but still update the last event id,
+    /// comments and `retry:` fields are unaffected
+    pub fn filter_events<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.event_filter = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+    /// Only retry the given HTTP status codes, every other status closes the event source
+    pub fn retry_statuses<I>(self, statuses: I) -> Self
+    where
+        I: IntoIterator<Item = StatusCode>,
+    {
+        let statuses: HashSet<StatusCode> = statuses.into_iter().collect();
+        self.retry_status(move |status| statuses.contains(&status))
+    }
+    /// Content types accepted from the server. Responses with any other `Content-Type`
+    /// close the event source with [`super::EventSourceError::InvalidContentType`].
+    /// Defaults to `text/event-stream`
+    pub fn content_types<I, S>(mut self, content_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.content_types = content_types.into_iter().map(Into::into).collect();
+        self
+    }
+    /// Skip `Content-Type` validation entirely
+    pub fn allow_any_content_type(self) -> Self {
+        self.content_types(Vec::<String>::new())
+    }
     pub fn last_event(mut self, last_event_id: Option<BytesStr>) -> Self {
         self.last_event_id = last_event_id;
         self
     }
+    /// Choose whether the last event id is sent as the `Last-Event-ID` header, a query parameter or both.
+    /// Defaults to [`LastEventIdMode::Header`]
+    pub fn last_event_id_mode(mut self, mode: LastEventIdMode) -> Self {
+        self.last_event_id_mode = mode;
+        self
+    }
     // copied from reqwest::RequestBuilder
     // mit license
 
@@ -171,6 +474,41 @@ impl EventSourceBuilder {
         self
     }
 
+    /// Headers sent with every request made by the underlying client.
+    ///
+    /// Merged with headers set by earlier calls, values replace earlier ones with the same name.
+    pub fn default_headers(mut self, headers: reqwest::header::HeaderMap) -> Self {
+        util::replace_headers(&mut self.default_headers, headers);
+        self
+    }
+    /// Add default headers from the environment variable `var`, see [`headers_from_env`].
+    /// Does nothing if the variable isn't set
+    pub fn env_headers(self, var: &str) -> Self {
+        match headers_from_env(var) {
+            Ok(Some(headers)) => self.default_headers(headers),
+            Ok(None) => self,
+            Err(e) => self.with_error(e),
+        }
+    }
+
+    /// Set the `User-Agent` header, defaults to `launchdarkly-autoconfig/<version>`.
+    ///
+    /// `Accept: text/event-stream` and `Cache-Control: no-cache` are always sent as well, unless
+    /// they're set explicitly with [`EventSourceBuilder::header`]
+    pub fn user_agent<V>(mut self, user_agent: V) -> Self
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<EventSourceBuilderError>,
+    {
+        match HeaderValue::try_from(user_agent) {
+            Ok(user_agent) => {
+                self.user_agent = user_agent;
+                self
+            }
+            Err(e) => self.with_error(e.into()),
+        }
+    }
+
     /// Enable HTTP bearer authentication.
     /// Sets `Authorization: Bearer <token>`.
     pub fn bearer_auth<T>(self, token: T) -> Self
@@ -194,6 +532,22 @@ impl EventSourceBuilder {
         if let Ok(ref mut req) = self.request {
             *req.body_mut() = Some(body.into());
         }
+        self.body_factory = None;
+        self
+    }
+    /// Create a new request body for each connection attempt.
+    ///
+    /// Use this for bodies that can't be cloned, such as streams, which would otherwise fail with
+    /// [`super::EventSourceError::RequestCloneError`] when reconnecting. Replaces any body set with
+    /// [`EventSourceBuilder::body`]
+    pub fn body_factory<F>(mut self, body_factory: F) -> Self
+    where
+        F: Fn() -> reqwest::Body + Send + 'static,
+    {
+        if let Ok(ref mut req) = self.request {
+            *req.body_mut() = None;
+        }
+        self.body_factory = Some(Box::new(body_factory));
         self
     }
     /// Enables a request timeout.
@@ -211,45 +565,185 @@ impl EventSourceBuilder {
         self.redirect_policy = policy;
         self
     }
+    /// Fallback urls to try, in order, when the primary url keeps failing.
+    /// The last event id is carried over so no events are missed when switching
+    pub fn failover_urls<I>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        self.failover_urls = urls.into_iter().collect();
+        self
+    }
+    /// Number of consecutive failed attempts before moving to the next failover url. Defaults to 3
+    pub fn failover_after(mut self, failures: usize) -> Self {
+        self.failover_after = failures;
+        self
+    }
+    /// How often to try the primary url again while connected to a failover url, `None` disables it.
+    /// Defaults to 5 minutes
+    pub fn primary_probe_interval(mut self, interval: Option<std::time::Duration>) -> Self {
+        self.primary_probe_interval = interval;
+        self
+    }
+    /// Wrap every connection request in a [`tower::Layer`], for example to add tracing propagation
+    /// or a retry budget. Layers are applied in the order they're added, the first one being the
+    /// outermost. Errors returned by layers are reported as [`super::EventSourceError::Middleware`]
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<HttpService> + Send + 'static,
+        L::Service:
+            Service<reqwest::Request, Response = reqwest::Response> + Clone + Send + 'static,
+        <L::Service as Service<reqwest::Request>>::Error: Into<tower::BoxError>,
+        <L::Service as Service<reqwest::Request>>::Future: Send + 'static,
+    {
+        self.layers.push(box_layer(layer));
+        self
+    }
+    /// Maximum number of redirects to follow for a single connection attempt.
+    /// Exceeding it closes the event source with [`super::EventSourceError::TooManyRedirects`]
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
     pub fn build(self) -> Result<super::EventSource, EventSourceBuilderError> {
-        let req = self.request?;
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut req = self.request?;
+        set_standard_headers(req.headers_mut(), self.user_agent);
 
         let url = std::sync::Arc::new(std::sync::Mutex::new(Some(req.url().clone())));
-        let redirect_policy = {
-            let url = url.clone();
-            let inner_redirect_policy = self.redirect_policy;
-            reqwest::redirect::Policy::custom(move |attempt| {
-                if attempt.status() == reqwest::StatusCode::MOVED_PERMANENTLY {
-                    let mut url = url.lock().unwrap();
-                    *url = Some(attempt.url().clone());
-                }
-                inner_redirect_policy.redirect(attempt)
-            })
-        };
-        let client = self.client_builder.redirect(redirect_policy).build()?;
+        let redirect_policy =
+            redirect_policy(url.clone(), self.max_redirects, self.redirect_policy);
+        let mut client_builder = self.client_builder.redirect(redirect_policy);
+        if !self.default_headers.is_empty() {
+            client_builder = client_builder.default_headers(self.default_headers);
+        }
+        if self.reresolve_on_reconnect {
+            client_builder = client_builder.pool_max_idle_per_host(0);
+        }
+        let client = client_builder.build()?;
         let backoff = self
-            .backoff
+            .reconnect_policy
             .unwrap_or(Box::new(backoff::ExponentialBackoff::default()));
+        let backoff: Box<dyn ReconnectPolicy> = match self.jitter {
+            Jitter::None => backoff,
+            jitter => Box::new(JitteredBackoff::new(backoff, jitter)),
+        };
+        let backoff: Box<dyn ReconnectPolicy> = match self.circuit_breaker {
+            None => backoff,
+            Some(config) => Box::new(CircuitBreakerBackoff::new(backoff, config)),
+        };
+        let backoff: Box<dyn ReconnectPolicy> = match (self.max_retries, self.max_elapsed) {
+            (None, None) => backoff,
+            (max_retries, max_elapsed) => {
+                Box::new(LimitedBackoff::new(backoff, max_retries, max_elapsed))
+            }
+        };
+        let endpoints = match self.failover_urls.is_empty() {
+            true => None,
+            false => Some(Endpoints::new(
+                req.url().clone(),
+                self.failover_urls,
+                self.failover_after,
+                self.primary_probe_interval,
+            )),
+        };
         let last_event_id = self.last_event_id;
+        let service = match self.layers.is_empty() {
+            true => None,
+            false => Some(build_service(client.clone(), self.layers)),
+        };
         let request_builder = reqwest::RequestBuilder::from_parts(client, req);
+        let backoff: Box<dyn ReconnectPolicy> = match self.retry_field_mode {
+            RetryFieldMode::Minimum => {
+                Box::new(backoff.with_minimum_duration(std::time::Duration::ZERO))
+            }
+            RetryFieldMode::Exact => Box::new(ExactBackoffDuration::new(backoff)),
+        };
+        // the limiter goes last so tokens are taken for the delay that's actually used
+        let reconnect_policy: Box<dyn ReconnectPolicy> = match self.reconnect_limiter {
+            None => backoff,
+            Some(limiter) => Box::new(RateLimitedBackoff::new(backoff, limiter)),
+        };
 
         Ok(EventSource {
             request_builder,
-            backoff: backoff.with_minimum_duration(std::time::Duration::ZERO),
+            reconnect_policy,
             last_event_id,
             retry_url: url,
             state: super::state_util::EventSourceState::Initial,
             read_timeout: self.read_timeout_duration,
             retry_attempts: 0,
             is_retrying: false,
+            connection_info: None,
+            retry_status: self.retry_status,
+            content_types: self.content_types,
+            authenticator: self.authenticator,
+            reauthorized: false,
+            on_retry: self.on_retry,
+            body_factory: self.body_factory,
+            last_event_id_mode: self.last_event_id_mode,
+            endpoints,
+            max_idle_reconnects: self.max_idle_reconnects,
+            idle_reconnects: 0,
+            stats: Default::default(),
+            max_event_size: self.max_event_size,
+            read_buffer_capacity: self.read_buffer_capacity,
+            service,
+            initialization_timeout: self.initialization_timeout,
+            initialization_deadline: None,
+            last_reconnect_reason: None,
+            recorder: self.recorder,
+            event_filter: self.event_filter,
+            spare_reader: None,
         })
     }
 }
 
+/// Environment variable read by the CLI for extra request headers, see [`headers_from_env`]
+pub const EXTRA_HEADERS_ENV: &str = "LD_EXTRA_HEADERS";
+
+/// Read headers from the environment variable `var`, returning `None` if it isn't set.
+///
+/// Headers are written as `Name: value`, one per line, for example
+/// `X-Proxy-Auth: secret\nX-Team: platform`. Values may contain `;`, as in
+/// `Cookie: a=1; b=2`
+pub fn headers_from_env(
+    var: &str,
+) -> Result<Option<reqwest::header::HeaderMap>, EventSourceBuilderError> {
+    match std::env::var(var) {
+        Ok(value) => util::parse_header_list(&value).map(Some),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(EventSourceBuilderError::Other(Box::new(e))),
+    }
+}
+
 mod util {
     use reqwest::header::{Entry, HeaderMap, OccupiedEntry};
 
     use super::*;
+
+    pub(crate) fn parse_header_list(value: &str) -> Result<HeaderMap, EventSourceBuilderError> {
+        let mut headers = HeaderMap::new();
+        for entry in value.lines() {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (name, value) = entry.split_once(':').ok_or_else(|| {
+                EventSourceBuilderError::Other(
+                    format!("expected `Name: value`, found `{}`", entry).into(),
+                )
+            })?;
+            headers.append(
+                HeaderName::try_from(name.trim())?,
+                HeaderValue::try_from(value.trim())?,
+            );
+        }
+        Ok(headers)
+    }
+
     pub(crate) fn replace_headers(dst: &mut HeaderMap, src: HeaderMap) {
         // IntoIter of HeaderMap yields (Option<HeaderName>, HeaderValue).
         // The first time a name is yielded, it will be Some(name), and if
@@ -279,3 +773,117 @@ mod util {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header_list() {
+        let headers = util::parse_header_list(
+            "X-Proxy-Auth: secret\r\nx-team:platform\n\nX-Team: infra\nCookie: a=1; b=2\n",
+        )
+        .unwrap();
+        assert_eq!(headers["x-proxy-auth"], "secret");
+        assert_eq!(headers["cookie"], "a=1; b=2");
+        assert_eq!(
+            headers.get_all("x-team").iter().collect::<Vec<_>>(),
+            ["platform", "infra"]
+        );
+        assert!(util::parse_header_list("missing separator").is_err());
+        assert!(util::parse_header_list("bad name: value").is_err());
+    }
+
+    #[test]
+    fn sets_standard_headers() {
+        let url = Url::parse("http://localhost/stream").unwrap();
+        let es = EventSourceBuilder::get(url.clone())
+            .user_agent("my-service/1.0")
+            .build()
+            .unwrap();
+        let request = es.request_builder.try_clone().unwrap().build().unwrap();
+        assert_eq!(request.headers()[header::ACCEPT], "text/event-stream");
+        assert_eq!(request.headers()[header::CACHE_CONTROL], "no-cache");
+        assert_eq!(request.headers()[header::USER_AGENT], "my-service/1.0");
+
+        // explicit headers win
+        let es = EventSourceBuilder::get(url)
+            .header(header::CACHE_CONTROL, "max-age=0")
+            .build()
+            .unwrap();
+        let request = es.request_builder.try_clone().unwrap().build().unwrap();
+        assert_eq!(request.headers()[header::CACHE_CONTROL], "max-age=0");
+        assert_eq!(request.headers()[header::USER_AGENT], DEFAULT_USER_AGENT);
+    }
+
+    /// Resolves every name to localhost, counting lookups
+    struct CountingResolver(std::sync::atomic::AtomicUsize);
+
+    impl reqwest::dns::Resolve for CountingResolver {
+        fn resolve(&self, _: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let addrs: reqwest::dns::Addrs = Box::new(std::iter::once(std::net::SocketAddr::from(
+                ([127, 0, 0, 1], 0),
+            )));
+            Box::pin(futures::future::ready(Ok(addrs)))
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_again_on_reconnect() {
+        use super::super::test_util::{MockResponse, MockServer};
+        use futures::StreamExt;
+
+        let server = MockServer::start([
+            MockResponse::sse().event("one").disconnect(),
+            MockResponse::sse().event("two"),
+        ])
+        .await;
+        let mut url = server.url();
+        url.set_host(Some("stream.test")).unwrap();
+        let resolver = Arc::new(CountingResolver(Default::default()));
+        let mut es = Box::pin(
+            EventSourceBuilder::get(url)
+                .with_backoff_strategy(backoff::backoff::Constant::new(
+                    std::time::Duration::from_millis(1),
+                ))
+                .dns_resolver(resolver.clone())
+                .reresolve_on_reconnect(true)
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "one");
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "two");
+        assert_eq!(resolver.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // the last call wins
+        assert!(
+            !EventSourceBuilder::get(server.url())
+                .reresolve_on_reconnect(true)
+                .reresolve_on_reconnect(false)
+                .reresolve_on_reconnect
+        );
+    }
+
+    #[test]
+    fn invalid_root_certificate_fails_build() {
+        let result = EventSourceBuilder::get(Url::parse("http://localhost/stream").unwrap())
+            .add_root_certificate_pem(b"not a certificate")
+            .build();
+        assert!(matches!(result, Err(EventSourceBuilderError::Request(_))));
+    }
+
+    #[test]
+    fn invalid_proxy_fails_build() {
+        let url = Url::parse("http://localhost/stream").unwrap();
+        let result = EventSourceBuilder::get(url.clone())
+            .proxy("not a proxy url")
+            .build();
+        assert!(matches!(result, Err(EventSourceBuilderError::Request(_))));
+
+        assert!(EventSourceBuilder::get(url)
+            .proxy("socks5://localhost:1080")
+            .build()
+            .is_ok());
+    }
+}
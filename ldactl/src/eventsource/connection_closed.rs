@@ -0,0 +1,143 @@
+use std::{error::Error, fmt};
+
+/// How the connection was closed, see [`ConnectionClosed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionClosedKind {
+    /// The server sent an HTTP/2 GOAWAY frame, usually because it's draining connections
+    GoAway,
+    /// The server reset the HTTP/2 stream
+    StreamReset,
+    /// The TCP connection was reset
+    ConnectionReset,
+}
+
+/// Details of a connection the server or network closed, see
+/// [`super::EventSourceError::ConnectionClosed`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionClosed {
+    pub kind: ConnectionClosedKind,
+    /// HTTP/2 error code such as `NO_ERROR` or `ENHANCE_YOUR_CALM`
+    pub reason: Option<String>,
+    /// Debug data the server sent with the GOAWAY frame
+    pub debug_data: Option<String>,
+}
+
+impl ConnectionClosed {
+    /// Whether the server is shutting the connection down on purpose rather than failing
+    pub fn is_graceful(&self) -> bool {
+        self.kind == ConnectionClosedKind::GoAway && self.reason.as_deref() == Some("NO_ERROR")
+    }
+
+    /// Looks through the error's sources for a GOAWAY, stream reset or connection reset
+    pub(crate) fn from_error(error: &(dyn Error + 'static)) -> Option<Self> {
+        let mut source = Some(error);
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<h2::Error>() {
+                return Self::from_h2(e);
+            }
+            if let Some(e) = e.downcast_ref::<std::io::Error>() {
+                if e.kind() == std::io::ErrorKind::ConnectionReset {
+                    return Some(Self {
+                        kind: ConnectionClosedKind::ConnectionReset,
+                        reason: None,
+                        debug_data: None,
+                    });
+                }
+            }
+            source = e.source();
+        }
+        None
+    }
+
+    fn from_h2(error: &h2::Error) -> Option<Self> {
+        if let Some(io) = error.get_io() {
+            return Self::from_error(io);
+        }
+        let kind = match (error.is_go_away(), error.is_reset()) {
+            _ if !error.is_remote() => return None,
+            (true, _) => ConnectionClosedKind::GoAway,
+            (_, true) => ConnectionClosedKind::StreamReset,
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            reason: error.reason().map(|reason| format!("{:?}", reason)),
+            debug_data: debug_data(&error.to_string()),
+        })
+    }
+}
+
+/// h2 only exposes the debug data through `Display`, which appends it as ` (b"...")`
+fn debug_data(message: &str) -> Option<String> {
+    let start = message.find(" (b\"")? + 4;
+    let end = message.rfind("\")")?;
+    (start < end).then(|| message[start..end].to_owned())
+}
+
+impl fmt::Display for ConnectionClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ConnectionClosedKind::GoAway => write!(f, "server sent GOAWAY")?,
+            ConnectionClosedKind::StreamReset => write!(f, "server reset the stream")?,
+            ConnectionClosedKind::ConnectionReset => write!(f, "connection reset")?,
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        if let Some(debug_data) = &self.debug_data {
+            write!(f, ": {}", debug_data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{EventSourceBuilder, EventSourceError};
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn parses_debug_data() {
+        assert_eq!(
+            debug_data("connection error received: not a result of an error (b\"draining\")"),
+            Some("draining".to_owned())
+        );
+        assert_eq!(
+            debug_data("connection error received: protocol error"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn reset_connection_is_reported() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url =
+            reqwest::Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\nd\r\ndata: hello\n\n\r\n")
+                .await
+                .unwrap();
+            // closing with a zero linger sends a RST instead of a FIN
+            #[allow(deprecated)]
+            socket.set_linger(Some(std::time::Duration::ZERO)).unwrap();
+        });
+        let mut es = Box::pin(EventSourceBuilder::get(url).max_retries(0).build().unwrap());
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        match es.next().await {
+            Some(Err(EventSourceError::MaxRetriesExceeded(_, Some(e)))) => match *e {
+                EventSourceError::ConnectionClosed(closed, _) => {
+                    assert_eq!(closed.kind, ConnectionClosedKind::ConnectionReset);
+                    assert!(!closed.is_graceful());
+                }
+                other => panic!("expected ConnectionClosed, got {:?}", other),
+            },
+            other => panic!("expected MaxRetriesExceeded, got {:?}", other),
+        }
+    }
+}
@@ -7,6 +7,7 @@
 //!
 //! It should be implemented for any error that can occur while processing the stream.
 //!
+use super::connection_closed::ConnectionClosed;
 use super::eventsource::EventSourceError;
 use reqwest::Error as ReqwestError;
 use std::error::Error;
@@ -19,16 +20,44 @@ pub trait EventSourceErrorInnerError: Error + Into<EventSourceError> + private::
         self.into()
     }
 }
-// identity impl
+// identity impl, request errors are classified again since they're often wrapped directly
 impl EventSourceErrorInnerError for EventSourceError {
     #[inline]
     fn into_event_source_error(self) -> EventSourceError {
-        self
+        match self {
+            EventSourceError::RequestError(e) => e.into_event_source_error(),
+            e => e,
+        }
+    }
+}
+// I/O errors from the response body are wrapped by the decoder, unwrap them so
+// errors like read timeouts keep their type
+impl EventSourceErrorInnerError for SseDecodeError {
+    fn into_event_source_error(self) -> EventSourceError {
+        match self {
+            SseDecodeError::Io(e) => e.into_event_source_error(),
+            e => e.into(),
+        }
+    }
+}
+
+// The redirect policy can only report errors through reqwest, so pull our own error back out
+impl EventSourceErrorInnerError for ReqwestError {
+    fn into_event_source_error(self) -> EventSourceError {
+        match self
+            .source()
+            .and_then(|e| e.downcast_ref::<EventSourceError>())
+        {
+            Some(EventSourceError::TooManyRedirects(count)) => {
+                EventSourceError::TooManyRedirects(*count)
+            }
+            _ => match ConnectionClosed::from_error(&self) {
+                Some(closed) => EventSourceError::ConnectionClosed(closed, self),
+                None => self.into(),
+            },
+        }
     }
 }
-// trival Into/From impls
-impl EventSourceErrorInnerError for SseDecodeError {}
-impl EventSourceErrorInnerError for ReqwestError {}
 
 // Downcast IO errors if the inner error is an eventsource error
 // Otherwise, just bubble it back up as EventSourceError::IO
@@ -36,11 +65,12 @@ impl EventSourceErrorInnerError for IOError {
     fn into_event_source_error(self) -> EventSourceError {
         // can be replaced with std::error::Error::downcast when it's stable
         match self.get_ref() {
-            Some(e) if e.is::<EventSourceError>() => *self
+            Some(e) if e.is::<EventSourceError>() => self
                 .into_inner()
                 .expect("std::io::Error::into_inner failed unexpectly. This should never happen")
                 .downcast::<EventSourceError>()
-                .expect("downcast<EventSourceError> failed unexpectedly. This should never happen"),
+                .expect("downcast<EventSourceError> failed unexpectedly. This should never happen")
+                .into_event_source_error(),
             _ => self.into(),
         }
     }
@@ -1,24 +1,35 @@
 use std::{
-    borrow::BorrowMut,
-    ops::{Add, AddAssign, Deref, DerefMut},
-    pin::{self, pin, Pin},
+    ops::{AddAssign, Deref},
+    pin::Pin,
     sync::{Arc, Mutex},
     task::Poll::{self, Pending, Ready},
     time::Duration,
 };
 
 use sse_codec::BytesStr;
-use tokio_sse_codec::{self as sse_codec, Event};
+use tokio_sse_codec::{self as sse_codec, Event, Frame};
 
-use super::sse_backoff::{MinimumBackoffDuration, WithMinimumBackoff};
+use super::authenticator::{is_auth_failure, AuthorizeError, SharedAuthenticator};
+use super::buffered::BufferedEventSource;
+use super::connection_closed::ConnectionClosed;
+use super::failover::Endpoints;
+use super::frame_stream::FrameStream;
+use super::middleware::{self, HttpService};
+use super::received::ReceivedStream;
+use super::reconnect_policy::ReconnectPolicy;
+use super::recording::Recorder;
+use super::redirect::{redirect_policy, DEFAULT_MAX_REDIRECTS};
+use super::response_meta::ResponseMeta;
+use super::sse_backoff::WithMinimumBackoff;
+use super::stats::{EventSourceStats, StatsRecorder};
 use crate::eventsource::{
     errorext::EventSourceErrorInnerError,
-    retryable::Retryable,
+    retryable::{is_retryable_status, RetryStatusPredicate},
     state_util::{macros::run_state, EventSourceState, NextState, StateAction, StateProj},
 };
 
-use backoff::{backoff::Backoff, retry, ExponentialBackoff};
-use futures::{Future, FutureExt, StreamExt, TryStreamExt};
+use backoff::{backoff::Backoff, ExponentialBackoff};
+use futures::{Future, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
 
 use miette::Diagnostic;
 use pin_project::pin_project;
@@ -26,19 +37,43 @@ use reqwest::{ClientBuilder, RequestBuilder, Response, Url};
 use thiserror::Error;
 use tokio_stream::Stream;
 
-use tokio_util::{codec::FramedRead, compat::FuturesAsyncReadCompatExt};
+use tokio_util::{
+    codec::{Decoder, FramedRead},
+    compat::FuturesAsyncReadCompatExt,
+};
 use tracing::{debug, debug_span, error, instrument, trace, warn, Span, info};
+use tower::ServiceExt;
 use tracing_futures::Instrument;
 
+pub(crate) const DEFAULT_CONTENT_TYPE: &str = "text/event-stream";
+pub(crate) const DEFAULT_READ_BUFFER_CAPACITY: usize = 8 * 1024;
+pub(crate) const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Sets `Accept`, `Cache-Control` and `User-Agent` unless the request already has them
+pub(crate) fn set_standard_headers(
+    headers: &mut reqwest::header::HeaderMap,
+    user_agent: reqwest::header::HeaderValue,
+) {
+    use reqwest::header::{HeaderValue, ACCEPT, CACHE_CONTROL, USER_AGENT};
+    headers
+        .entry(ACCEPT)
+        .or_insert(HeaderValue::from_static(DEFAULT_CONTENT_TYPE));
+    headers
+        .entry(CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static("no-cache"));
+    headers.entry(USER_AGENT).or_insert(user_agent);
+}
+
 #[derive(Debug, Error, Diagnostic)]
 pub enum EventSourceError {
     #[error("request builder must be cloneable to retry")]
-    #[diagnostic(help("make sure the request builder doesn't use streams or other non-cloneable types in the body"))]
+    #[diagnostic(help("make sure the request builder doesn't use streams or other non-cloneable types in the body, or use EventSourceBuilder::body_factory"))]
     RequestCloneError,
     #[error("request error")]
     RequestError(#[from] reqwest::Error),
     #[error("max retries exceeded after {0} attempts")]
-    #[help = "you can tune max retries by customizing the backoff strategy passed to the event source"]
+    #[help = "you can tune max retries with EventSourceBuilder::max_retries and EventSourceBuilder::max_elapsed, or by customizing the backoff strategy passed to the event source"]
     MaxRetriesExceeded(usize, #[source] Option<Box<EventSourceError>>),
     #[error("error while decoding sse event")]
     #[diagnostic(help("set RUST_LOG=\"{}::eventsource::sse_codec=debug\"", env!("CARGO_PKG_NAME")))]
@@ -49,12 +84,172 @@ pub enum EventSourceError {
     Io(#[from] std::io::Error),
     #[error("max redirects exceeded after {0} attempts")]
     TooManyRedirects(usize),
+    #[error("server ended the stream with 204 No Content")]
+    #[diagnostic(help("the server asked the client to stop reconnecting"))]
+    StreamEnded,
+    #[error("unexpected content type {found:?}, expected one of {expected:?}")]
+    #[diagnostic(help(
+        "make sure the url points to an sse endpoint and isn't being intercepted by a proxy"
+    ))]
+    InvalidContentType {
+        found: Option<String>,
+        expected: Vec<String>,
+    },
+    #[error("failed to authorize request")]
+    AuthorizationError(#[source] AuthorizeError),
+    #[error("middleware error")]
+    Middleware(#[source] tower::BoxError),
+    #[error("failed to connect within {0:?}")]
+    #[diagnostic(help(
+        "check the url and credentials, the last connection error is available from EventSource::stats"
+    ))]
+    InitializationTimeout(Duration),
+    #[error("{0}")]
+    #[diagnostic(help(
+        "GOAWAY with NO_ERROR means the server is draining connections, other reasons point to network or server failures"
+    ))]
+    ConnectionClosed(ConnectionClosed, #[source] reqwest::Error),
+}
+
+/// Stable classification of [`EventSourceError`], see [`EventSourceError::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EventSourceErrorKind {
+    /// The request can't be sent again
+    InvalidRequest,
+    /// The server responded with an error status, see [`EventSourceError::status`]
+    Status,
+    /// Connecting to the server or reading the response failed
+    Network,
+    /// The retry policy gave up
+    RetriesExhausted,
+    /// The stream wasn't valid sse
+    Decode,
+    /// No data was received within the read timeout
+    ReadTimeout,
+    TooManyRedirects,
+    /// The server asked the client to stop reconnecting
+    StreamEnded,
+    InvalidContentType,
+    Authorization,
+    Middleware,
+    InitializationTimeout,
+    /// The server or network closed the connection
+    ConnectionClosed,
+}
+
+impl EventSourceError {
+    /// Classifies the error without matching on its variant and fields
+    pub fn kind(&self) -> EventSourceErrorKind {
+        match self {
+            EventSourceError::RequestCloneError => EventSourceErrorKind::InvalidRequest,
+            EventSourceError::RequestError(e) if e.status().is_some() => {
+                EventSourceErrorKind::Status
+            }
+            EventSourceError::RequestError(_) | EventSourceError::Io(_) => {
+                EventSourceErrorKind::Network
+            }
+            EventSourceError::MaxRetriesExceeded(..) => EventSourceErrorKind::RetriesExhausted,
+            EventSourceError::DecodeError(_) => EventSourceErrorKind::Decode,
+            EventSourceError::ReadTimeoutElapsed(..) => EventSourceErrorKind::ReadTimeout,
+            EventSourceError::TooManyRedirects(_) => EventSourceErrorKind::TooManyRedirects,
+            EventSourceError::StreamEnded => EventSourceErrorKind::StreamEnded,
+            EventSourceError::InvalidContentType { .. } => EventSourceErrorKind::InvalidContentType,
+            EventSourceError::AuthorizationError(_) => EventSourceErrorKind::Authorization,
+            EventSourceError::Middleware(_) => EventSourceErrorKind::Middleware,
+            EventSourceError::InitializationTimeout(_) => {
+                EventSourceErrorKind::InitializationTimeout
+            }
+            EventSourceError::ConnectionClosed(..) => EventSourceErrorKind::ConnectionClosed,
+        }
+    }
+
+    /// HTTP status code returned by the server, if the error was caused by one
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            EventSourceError::RequestError(e) => e.status(),
+            EventSourceError::ConnectionClosed(_, e) => e.status(),
+            _ => None,
+        }
+    }
+}
+
+/// How the last event id is sent to the server when reconnecting
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LastEventIdMode {
+    /// Send the standard `Last-Event-ID` header
+    #[default]
+    Header,
+    /// Send it as the given query parameter, for backends behind proxies that strip headers
+    Query(String),
+    /// Send both the header and the given query parameter
+    HeaderAndQuery(String),
+}
+
+impl LastEventIdMode {
+    fn send_header(&self) -> bool {
+        matches!(
+            self,
+            LastEventIdMode::Header | LastEventIdMode::HeaderAndQuery(_)
+        )
+    }
+    fn query_param(&self) -> Option<&str> {
+        match self {
+            LastEventIdMode::Header => None,
+            LastEventIdMode::Query(param) | LastEventIdMode::HeaderAndQuery(param) => Some(param),
+        }
+    }
+}
+
+/// Why [`EventSource::reconnect`] was called, recorded in tracing spans and available from
+/// [`EventSource::last_reconnect_reason`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReconnectReason {
+    /// The server asked the client to reconnect
+    ServerRequested,
+    /// Credentials changed and the connection should use the new ones
+    CredentialRotated,
+    /// An operator asked for a reconnect, for example through a signal
+    OperatorRequested,
+    /// Any other reason
+    Other(String),
 }
 
+impl std::fmt::Display for ReconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReconnectReason::ServerRequested => write!(f, "server requested"),
+            ReconnectReason::CredentialRotated => write!(f, "credential rotated"),
+            ReconnectReason::OperatorRequested => write!(f, "operator requested"),
+            ReconnectReason::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Replaces any existing values for `name` so redirected urls don't end up with stale ids
+fn set_query_param(url: &mut Url, name: &str, value: &str) {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .into_owned()
+        .filter(|(key, _)| key != name)
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(name, value);
+}
+
+/// Callback that can change each connection attempt, see [`super::EventSourceBuilder::on_retry`]
+pub(crate) type OnRetry = Box<dyn FnMut(&mut reqwest::Request, usize) + Send>;
+
+/// Creates the body for each connection attempt, see [`super::EventSourceBuilder::body_factory`]
+pub(crate) type BodyFactory = Box<dyn Fn() -> reqwest::Body + Send>;
+
 #[pin_project]
 pub struct EventSource {
     pub(super) request_builder: RequestBuilder,
-    pub(super) backoff: MinimumBackoffDuration<Box<dyn Backoff>>,
+    pub(super) reconnect_policy: Box<dyn ReconnectPolicy>,
     #[pin]
     pub(super) state: EventSourceState,
     pub(super) retry_attempts: usize,
@@ -62,6 +257,30 @@ pub struct EventSource {
     pub(super) read_timeout: Duration,
     pub(super) retry_url: Arc<Mutex<Option<reqwest::Url>>>,
     pub(super) is_retrying: bool,
+    pub(super) connection_info: Option<ResponseMeta>,
+    pub(super) retry_status: RetryStatusPredicate,
+    pub(super) content_types: Vec<String>,
+    pub(super) authenticator: Option<SharedAuthenticator>,
+    pub(super) reauthorized: bool,
+    pub(super) on_retry: Option<OnRetry>,
+    pub(super) body_factory: Option<BodyFactory>,
+    pub(super) last_event_id_mode: LastEventIdMode,
+    pub(super) endpoints: Option<Endpoints>,
+    pub(super) max_idle_reconnects: Option<usize>,
+    pub(super) idle_reconnects: usize,
+    pub(super) stats: StatsRecorder,
+    pub(super) max_event_size: Option<usize>,
+    pub(super) read_buffer_capacity: usize,
+    pub(super) service: Option<HttpService>,
+    pub(super) initialization_timeout: Option<Duration>,
+    pub(super) last_reconnect_reason: Option<ReconnectReason>,
+    pub(super) recorder: Option<Recorder>,
+    // event names to yield, everything else is dropped in the poll loop
+    pub(super) event_filter: Option<std::collections::HashSet<String>>,
+    // armed on the first poll, cleared once connected
+    pub(super) initialization_deadline: Option<Pin<Box<tokio::time::Sleep>>>,
+    // decoder and read buffer of the last connection, reused by the next one
+    pub(super) spare_reader: Option<(sse_codec::SseDecoder<BytesStr>, bytes::BytesMut)>,
 }
 
 impl EventSource {
@@ -78,6 +297,39 @@ impl EventSource {
     pub fn read_timeout(&self) -> Duration {
         self.read_timeout
     }
+
+    /// How long the event source has to establish its first connection, `None` if unbounded
+    pub fn initialization_timeout(&self) -> Option<Duration> {
+        self.initialization_timeout
+    }
+
+    /// Largest event the decoder will buffer before failing with a decode error, `None` if unbounded
+    pub fn max_event_size(&self) -> Option<usize> {
+        self.max_event_size
+    }
+
+    /// Initial capacity of the buffer the response body is read into
+    pub fn read_buffer_capacity(&self) -> usize {
+        self.read_buffer_capacity
+    }
+
+    /// Read ahead into a queue of up to `capacity` items on a background task. Once the queue is
+    /// full the task stops reading from the connection, so a slow consumer pushes back on the server
+    /// instead of events piling up in memory. Must be called from within a tokio runtime
+    pub fn into_buffered(self, capacity: usize) -> BufferedEventSource {
+        BufferedEventSource::spawn(self, capacity)
+    }
+
+    /// Status, headers and final url of the response for the most recent successful connection.
+    /// Returns `None` until the first connection is established.
+    pub fn connection_info(&self) -> Option<&ResponseMeta> {
+        self.connection_info.as_ref()
+    }
+
+    /// Connection and traffic counters since the event source was created
+    pub fn stats(&self) -> EventSourceStats {
+        self.stats.snapshot()
+    }
     
     
    
@@ -89,65 +341,199 @@ impl EventSource {
         backoff: T,
     ) -> Result<Self, EventSourceError>
     where
-        T: Backoff + Sized + 'static,
+        T: Backoff + Send + Sized + 'static,
     {
         let builder = req
-        .header("accept", "text/event-stream")
         .try_clone()
         .ok_or_else(|| EventSourceError::RequestCloneError)?;
         let (_, request) = builder.build_split();
-        let request = request?;
+        let mut request = request?;
+        set_standard_headers(
+            request.headers_mut(),
+            reqwest::header::HeaderValue::from_static(DEFAULT_USER_AGENT),
+        );
 
         let url = Arc::new(Mutex::new(Some(request.url().clone())));
-        let client = {
-            let url = url.clone();
-            ClientBuilder::new()
-                .redirect(reqwest::redirect::Policy::custom(move |attempt| {
-                    let count = attempt.previous().len();
-                    if count > 10 {
-                        attempt.error(EventSourceError::TooManyRedirects(count))
-                    } else {
-                        if attempt.status() == reqwest::StatusCode::MOVED_PERMANENTLY {
-                            let next_url = attempt.url().clone();
-                            debug!(url=%next_url, "permanent redirect, setting url for future retries");
-                             let _ = url.lock()
-                                .expect("failed aquire lock for url")
-                                .insert(next_url);
-                            
-                        }
-                        attempt.follow()
-                    }
-                }))
-                .build()?
-        };
-
+        let client = ClientBuilder::new()
+            .redirect(redirect_policy(
+                url.clone(),
+                DEFAULT_MAX_REDIRECTS,
+                reqwest::redirect::Policy::default(),
+            ))
+            .build()?;
 
-        
         // now combine the custom client with the request
         let builder = RequestBuilder::from_parts(client, request);
 
-        let b: Box<dyn Backoff> = Box::new(backoff);
 
         Ok(Self {
             request_builder: builder,
-            backoff: b.with_minimum_duration(Duration::ZERO),
+            reconnect_policy: Box::new(backoff.with_minimum_duration(Duration::ZERO)),
             state: EventSourceState::Initial,
             retry_attempts: 0,
             last_event_id: last_event_id.map(BytesStr::from),
             read_timeout: Duration::from_secs(5 * 60),
             retry_url: url,
-            is_retrying: false
+            is_retrying: false,
+            connection_info: None,
+            retry_status: Arc::new(is_retryable_status),
+            content_types: vec![DEFAULT_CONTENT_TYPE.into()],
+            authenticator: None,
+            reauthorized: false,
+            on_retry: None,
+            body_factory: None,
+            last_event_id_mode: LastEventIdMode::Header,
+            endpoints: None,
+            max_idle_reconnects: None,
+            idle_reconnects: 0,
+            stats: StatsRecorder::default(),
+            max_event_size: None,
+            read_buffer_capacity: DEFAULT_READ_BUFFER_CAPACITY,
+            service: None,
+            initialization_timeout: None,
+            initialization_deadline: None,
+            last_reconnect_reason: None,
+            recorder: None,
+            event_filter: None,
+            spare_reader: None,
         })
     }
     
+    /// Drops the current connection or pending retry and connects again right away
+    #[instrument(skip(self), fields(last_event_id=?self.last_event_id, %reason))]
+    pub fn reconnect(mut self: Pin<&mut Self>, reason: ReconnectReason) {
+        self.as_mut()
+            .set_state(EventSourceState::ForceReconnect(reason, Span::current()))
+    }
+
+    /// Forgets the last event id, so the next connection starts over instead of resuming
     #[instrument(skip(self), fields(last_event_id=?self.last_event_id))]
-    pub fn reconnect(mut self: Pin<&mut Self>) {
-        self.as_mut().project().state.set(EventSourceState::ForceReconnect(Span::current().entered()))
+    pub fn clear_last_event_id(self: Pin<&mut Self>) {
+        *self.project().last_event_id = None;
+    }
+
+    /// Reason given to the most recent [`EventSource::reconnect`] call, `None` if it was never called
+    pub fn last_reconnect_reason(&self) -> Option<&ReconnectReason> {
+        self.last_reconnect_reason.as_ref()
+    }
+    /// Stops the event source without losing frames that were already received.
+    ///
+    /// Any in-flight request or pending retry is dropped, which aborts it. When connected, the
+    /// connection is closed but events already sitting in the read buffer are still yielded before
+    /// the stream resolves with `None`. Dropping the event source also aborts in-flight requests but
+    /// discards buffered frames.
+    #[instrument(skip(self))]
+    pub fn close(mut self: Pin<&mut Self>) {
+        let mut state = self.as_mut().project().state;
+        let next_state = match state.as_mut().project() {
+            StateProj::Connected(framed_read, _) => {
+                debug!(
+                    buffered = framed_read.read_buffer().len(),
+                    "closing event source, yielding buffered frames"
+                );
+                let buf = std::mem::take(framed_read.read_buffer_mut());
+                let decoder = std::mem::take(framed_read.decoder_mut());
+                EventSourceState::Closing(decoder, buf)
+            }
+            StateProj::Closing(..) | StateProj::Closed => return,
+            _ => {
+                debug!("closing event source");
+                EventSourceState::Closed
+            }
+        };
+        state.set(next_state);
+    }
+
+    /// Wraps the event source in a stream that yields every frame, including comments and
+    /// retry fields which are otherwise only logged
+    pub fn into_frame_stream(self) -> FrameStream {
+        FrameStream::new(self)
+    }
+
+    /// Wraps the event source in a stream that tags each event with the connection it arrived on
+    /// and the time it was received
+    pub fn into_received_stream(self) -> ReceivedStream {
+        ReceivedStream::new(self)
+    }
+
+    /// Identifies the current connection. Starts at 0 and increases by one every time a
+    /// connection is established, so events from different reconnects can be told apart
+    pub fn connection_id(&self) -> u64 {
+        self.stats.connection_id()
+    }
+
+    /// When the most recent frame was received, including comments and filtered events.
+    /// Returns `None` until the first frame is received
+    pub fn last_frame_at(&self) -> Option<std::time::Instant> {
+        self.stats.last_frame_at()
+    }
+
+    /// Whether the frame is an event dropped by [`super::EventSourceBuilder::filter_events`]
+    fn is_filtered(&self, frame: &Frame<BytesStr>) -> bool {
+        match (frame, &self.event_filter) {
+            (Frame::Event(event), Some(names)) if !names.contains(event.name.deref()) => {
+                trace!(name = event.name.deref(), "dropping filtered event");
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves to the next state, keeping the buffers of a dropped connection for the next one
+    pub(super) fn set_state(self: Pin<&mut Self>, next_state: EventSourceState) {
+        let mut this = self.project();
+        if let StateProj::Connected(framed_read, _) = this.state.as_mut().project() {
+            let read_buf = std::mem::take(framed_read.read_buffer_mut());
+            let decoder = std::mem::take(framed_read.decoder_mut());
+            *this.spare_reader = Some((decoder, read_buf));
+        }
+        this.state.set(next_state);
+    }
+
+    /// Records the last event id and retry field
+    fn handle_frame(mut self: Pin<&mut Self>, frame: &Frame<BytesStr>) {
+        *self.as_mut().project().idle_reconnects = 0;
+        self.as_mut().project().stats.record_frame();
+        match frame {
+            Frame::Comment(comment) => {
+                let _span = debug_span!("read_frame::comment", ?comment).entered();
+                debug!(comment = comment.deref(), "received comment");
+            }
+            Frame::Event(event) => {
+                let _span = debug_span!(
+                    "read_frame::event",
+                    name = event.name.deref(),
+                    id = ?event.id,
+                    data_len = event.data.len()
+                )
+                .entered();
+                debug!("received event");
+                if let Some(id) = event.id.as_deref() {
+                    if self.last_event_id.as_deref() != Some(id) {
+                        *self.as_mut().project().last_event_id =
+                            Some(BytesStr::from(id.to_owned()));
+                    }
+                }
+            }
+            Frame::Retry(duration) => {
+                let _span = debug_span!("read_frame::retry", ?duration).entered();
+                debug!("received retry field");
+                self.as_mut()
+                    .project()
+                    .reconnect_policy
+                    .on_retry_field(*duration);
+            }
+        }
     }
+
     #[instrument(skip(self,parent),fields(last_event_id=?self.last_event_id, attempt=self.retry_attempts+1))]
-    fn send_request(self: Pin<&mut Self>, parent: Option<tracing::Id>) -> (StateAction, NextState) {
+    fn send_request(
+        mut self: Pin<&mut Self>,
+        parent: Option<tracing::Id>,
+    ) -> (StateAction, NextState) {
         Span::current().follows_from(parent);
         debug!("opening connection to event source");
+        self.as_mut().project().stats.record_attempt();
         let mut builder = match self.request_builder.try_clone() {
             Some(builder) => {
                 debug!("starting new request to event source");
@@ -158,7 +544,7 @@ impl EventSource {
 
                 return (
                     StateAction::Break(Ready(Some(
-                        Err(EventSourceError::RequestCloneError.into()),
+                        Err(EventSourceError::RequestCloneError),
                     ))),
                     Some(EventSourceState::Closed),
                 );
@@ -166,9 +552,11 @@ impl EventSource {
         };
 
         if let Some(last_event_id) = &self.last_event_id {
-            trace!("setting last-event-id header to {}", last_event_id);
-            
-            builder = builder.header("last-event-id", last_event_id.deref());
+            if self.last_event_id_mode.send_header() {
+                trace!("setting last-event-id header to {}", last_event_id);
+
+                builder = builder.header("last-event-id", last_event_id.deref());
+            }
         }
         let (client, request) = builder.build_split();
         let mut request = request.unwrap();
@@ -180,47 +568,159 @@ impl EventSource {
         if let Some(next_url) = next_url {
             *request.url_mut() = next_url;
         }
+        if let (Some(last_event_id), Some(param)) =
+            (&self.last_event_id, self.last_event_id_mode.query_param())
+        {
+            trace!(
+                param,
+                "setting last event id query parameter to {}",
+                last_event_id
+            );
+            set_query_param(request.url_mut(), param, last_event_id);
+        }
+        if let Some(body_factory) = &self.body_factory {
+            *request.body_mut() = Some(body_factory());
+        }
+        let attempt = self.retry_attempts + 1;
+        if let Some(on_retry) = self.as_mut().project().on_retry {
+            on_retry(&mut request, attempt);
+        }
+
+        if let Some(authenticator) = self.authenticator.clone() {
+            debug!("authorizing request");
+            let authorize = async move {
+                authenticator
+                    .lock()
+                    .await
+                    .authorize(&mut request)
+                    .await
+                    .map(|_| request)
+            };
+            return (
+                StateAction::Continue,
+                Some(EventSourceState::Authorize(
+                    authorize.in_current_span().boxed(),
+                    client,
+                    Span::current(),
+                )),
+            );
+        }
 
-        return (
+        self.execute_request(client, request)
+    }
+
+    fn execute_request(
+        self: Pin<&mut Self>,
+        client: reqwest::Client,
+        request: reqwest::Request,
+    ) -> (StateAction, NextState) {
+        (
             StateAction::Continue,
             Some(EventSourceState::Connect(
-                client.execute(request).in_current_span().boxed(),
-                debug_span!(parent: None, "send_request", attempt=self.retry_attempts+1).entered(),
+                match &self.service {
+                    Some(service) => service
+                        .clone()
+                        .oneshot(request)
+                        .map_err(middleware::into_event_source_error)
+                        .in_current_span()
+                        .boxed(),
+                    None => client
+                        .execute(request)
+                        .map_err(EventSourceErrorInnerError::into_event_source_error)
+                        .in_current_span()
+                        .boxed(),
+                },
+                debug_span!(parent: None, "send_request", attempt=self.retry_attempts+1),
             )),
-        );
+        )
+    }
+
+    fn validate_content_type(&self, response: &Response) -> Result<(), EventSourceError> {
+        if self.content_types.is_empty() {
+            return Ok(());
+        }
+        let found = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok());
+        // compare the mime type without parameters such as charset
+        let essence = found.map(|v| v.split(';').next().unwrap_or_default().trim());
+        match essence {
+            Some(essence)
+                if self
+                    .content_types
+                    .iter()
+                    .any(|expected| expected.eq_ignore_ascii_case(essence)) =>
+            {
+                Ok(())
+            }
+            _ => Err(EventSourceError::InvalidContentType {
+                found: found.map(String::from),
+                expected: self.content_types.clone(),
+            }),
+        }
     }
 
     #[instrument(parent=&parent, skip(self,response, parent), fields(host=response.url().host_str(), path=response.url().path()))]
     fn open_stream(
-        self: Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         response: Response,
-        parent: tracing::span::EnteredSpan,
+        parent: tracing::Span,
     ) -> (StateAction, NextState) {
-        debug!("connected to event source");
+        debug!(status=%response.status(), "connected to event source");
+        *self.as_mut().project().connection_info = Some(ResponseMeta::from(&response));
+
+        self.as_mut().project().stats.record_connected();
 
-        let read_timeout = self.read_timeout.clone();
-        let last_event_id = self.last_event_id.clone();
+        let read_timeout = self.read_timeout;
+        let bytes_received = self.stats.bytes_counter();
+        let recorder = self.recorder.clone();
+        if let Some(recorder) = &recorder {
+            recorder.record_connect();
+        }
 
         let inner = tokio_stream::StreamExt::timeout(response.bytes_stream(), read_timeout)
             .map(move |v| match v {
-                Ok(Ok(v)) => Ok(v),
+                Ok(Ok(v)) => {
+                    bytes_received.add(v.len());
+                    if let Some(recorder) = &recorder {
+                        recorder.record_chunk(&v);
+                    }
+                    Ok(v)
+                }
                 Ok(Err(e)) => Err(EventSourceError::RequestError(e)),
                 Err(e) => Err(EventSourceError::ReadTimeoutElapsed(e, read_timeout)),
             })
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            .map_err(std::io::Error::other)
             .into_async_read()
             .compat();
 
-        let framed_read = FramedRead::new(inner, sse_codec::SseDecoder::new())
-            .map_err(|e| EventSourceError::DecodeError(e))
-            .in_current_span()
-            .boxed();
+        let inner: Pin<Box<dyn tokio::io::AsyncRead + Send>> = Box::pin(inner);
+        let spare_reader = self.as_mut().project().spare_reader.take();
+        let framed_read = match spare_reader {
+            // keeps the buffer capacity and the last event id of the previous connection, unless
+            // the id was cleared and the old decoder would bring it back
+            Some((mut decoder, mut read_buf)) if self.last_event_id.is_some() => {
+                decoder.reconnect();
+                read_buf.clear();
+                let mut framed_read = FramedRead::with_capacity(inner, decoder, 0);
+                *framed_read.read_buffer_mut() = read_buf;
+                framed_read
+            }
+            _ => {
+                let decoder = match self.max_event_size {
+                    Some(max_event_size) => sse_codec::SseDecoder::with_max_size(max_event_size),
+                    None => sse_codec::SseDecoder::new(),
+                };
+                FramedRead::with_capacity(inner, decoder, self.read_buffer_capacity)
+            }
+        };
 
         (
             StateAction::Continue,
             Some(EventSourceState::Connected(
                 framed_read,
-                debug_span!("connected").entered(),
+                debug_span!("connected"),
             )),
         )
     }
@@ -231,22 +731,75 @@ impl EventSource {
         e: impl EventSourceErrorInnerError + 'static,
     ) -> (StateAction, NextState) {
         let e = e.into_event_source_error();
+        self.as_mut().project().stats.record_error(&e);
+
+        // idle connections are replaced right away, without counting as a failed attempt
+        if let (EventSourceError::ReadTimeoutElapsed(..), Some(max_idle_reconnects)) =
+            (&e, self.max_idle_reconnects)
+        {
+            *self.as_mut().project().idle_reconnects += 1;
+            let span = debug_span!("idle_reconnect", idle_reconnects = self.idle_reconnects);
+            if self.idle_reconnects <= max_idle_reconnects {
+                info!(parent: &span, "connection idle, reconnecting");
+                return (StateAction::Continue, Some(EventSourceState::New(span)));
+            }
+            warn!(parent: &span, "connection idle after {} reconnects", max_idle_reconnects);
+            *self.as_mut().project().idle_reconnects = 0;
+            return (
+                StateAction::Break(Ready(Some(Err(e)))),
+                Some(EventSourceState::New(span)),
+            );
+        }
+
         self.as_mut().project().retry_attempts.add_assign(1);
         let retry_attempts = self.retry_attempts;
         //let span = error_span!("handle_error").entered();
 
-        if e.is_retryable() {
+        // rejected credentials get one immediate retry with fresh ones
+        if let (Some(status), Some(authenticator)) = (e.status(), &self.authenticator) {
+            if is_auth_failure(status) && !self.reauthorized {
+                warn!(%status, "credentials rejected, authorizing again");
+                if let Some(mut authenticator) = authenticator.try_lock() {
+                    authenticator.on_auth_failure(status);
+                }
+                *self.as_mut().project().reauthorized = true;
+                return (
+                    StateAction::Continue,
+                    Some(EventSourceState::New(Span::current())),
+                );
+            }
+        }
+
+        let is_retryable = match e.status() {
+            Some(status) => (self.retry_status)(status),
+            None => e.is_retryable(),
+        };
+        if is_retryable {
             if !self.is_retrying {
-                self.as_mut().project().backoff.reset();
+                self.as_mut().project().reconnect_policy.reset();
                 *self.as_mut().project().is_retrying = true;
             }
-            if let Some(retry_duration) = self.as_mut().project().backoff.next_backoff() {
+            let next_url = self
+                .as_mut()
+                .project()
+                .endpoints
+                .as_mut()
+                .and_then(Endpoints::on_failure);
+            if let Some(next_url) = next_url {
+                let _ = self
+                    .retry_url
+                    .lock()
+                    .expect("failed to acquire lock for url")
+                    .insert(next_url);
+            }
+            if let Some(retry_duration) = self.as_mut().project().reconnect_policy.next_delay() {
+                self.stats.record_retry_delay(retry_duration);
                 warn!(next_attempt=?retry_duration, "recoverable error occurred, will retry");
                 (
                     StateAction::Continue,
                     Some(EventSourceState::WaitingForRetry(
                         tokio::time::sleep(retry_duration),
-                        Span::current().entered(),
+                        Span::current(),
                     )),
                 )
             } else {
@@ -285,98 +838,398 @@ impl Stream for EventSource {
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
+        loop {
+            break match futures::ready!(self.as_mut().poll_next_frame(cx)) {
+                Some(Ok(Frame::Event(event))) => Ready(Some(Ok(event))),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => Ready(Some(Err(e))),
+                None => Ready(None),
+            };
+        }
+    }
+}
+
+impl EventSource {
+    pub(super) fn poll_next_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<BytesStr>, EventSourceError>>> {
         loop {
             let this = self.as_mut().project();
+            let closed = matches!(
+                &*this.state,
+                EventSourceState::Closing(..) | EventSourceState::Closed
+            );
+            if let Some(deadline) = this.initialization_deadline.as_mut().filter(|_| !closed) {
+                if deadline.as_mut().poll(cx).is_ready() {
+                    let timeout = this.initialization_timeout.unwrap_or_default();
+                    error!(
+                        ?timeout,
+                        "failed to connect before the initialization timeout, closing event source"
+                    );
+                    *this.initialization_deadline = None;
+                    self.as_mut().project().state.set(EventSourceState::Closed);
+                    break Ready(Some(Err(EventSourceError::InitializationTimeout(timeout))));
+                }
+            }
             let state = this.state.project();
             #[allow(unreachable_code)]
             break match state {
                 StateProj::Initial => {
-                    let span = debug_span!("init").entered();
+                    let span = debug_span!("init");
+                    let this = self.as_mut().project();
+                    *this.initialization_deadline = this
+                        .initialization_timeout
+                        .map(|timeout| Box::pin(tokio::time::sleep(timeout)));
                     self.as_mut().project().state.set(EventSourceState::New(span));
                     // reset so we don't trigger the elapsed timeout
-                    self.as_mut().project().backoff.reset();
+                    self.as_mut().project().reconnect_policy.reset();
                     continue;
                 },
-                StateProj::ForceReconnect(parent) => {
-                    let span = debug_span!(parent: &*parent, "force_reconnect").entered();
-                    info!("reconnect requested by client");
+                StateProj::ForceReconnect(reason, parent) => {
+                    let span = debug_span!(parent: &*parent, "force_reconnect", %reason);
+                    info!(parent: &span, %reason, "reconnect requested by client");
+                    *this.last_reconnect_reason = Some(reason.clone());
                     self.as_mut().project().state.set(EventSourceState::New(span));
                     continue;
                 }
-                StateProj::New(follow) => {
+                StateProj::New(_follow) => {
                     run_state!(self, send_request(None))
                 }
 
+                StateProj::Authorize(authorize, client, parent) => {
+                    let _span = debug_span!(parent: &*parent, "authorize").entered();
+                    let client = client.clone();
+
+                    match futures::ready!(authorize.poll_unpin(cx)) {
+                        Ok(request) => run_state!(self, execute_request(client, request)),
+                        Err(e) => {
+                            run_state!(self, handle_error(EventSourceError::AuthorizationError(e)))
+                        }
+                    }
+                }
                 StateProj::Connect(req, parent) => {
                     let p = &*parent;
-                    let span = debug_span!(parent: p, "connect").entered();
+                    let span = debug_span!(parent: p, "connect");
+                    let _entered = span.clone().entered();
 
-                    match futures::ready!(req
-                        .poll_unpin(cx)
-                        .map(|r| r.and_then(Response::error_for_status)))
-                    {
+                    match futures::ready!(req.poll_unpin(cx).map(|r| r.and_then(|response| {
+                        response.error_for_status().map_err(EventSourceError::from)
+                    }))) {
+                        Ok(response) if response.status() == reqwest::StatusCode::NO_CONTENT => {
+                            info!("server responded with 204 No Content, closing event source");
+                            self.as_mut().project().state.set(EventSourceState::Closed);
+                            break Ready(Some(Err(EventSourceError::StreamEnded)));
+                        }
                         Ok(response) => {
+                            if let Err(e) = self.validate_content_type(&response) {
+                                run_state!(self, handle_error(e))
+                            }
                             *self.as_mut().project().retry_attempts = 0;
-                            self.as_mut().project().backoff.reset();
+                            *self.as_mut().project().reauthorized = false;
+                            *self.as_mut().project().initialization_deadline = None;
+                            if let Some(endpoints) = self.as_mut().project().endpoints {
+                                endpoints.on_success();
+                            }
+                            self.as_mut().project().reconnect_policy.on_success();
                             run_state!(self, open_stream(response, span))
                         }
                         Err(e) => run_state!(self, handle_error(e)),
                     }
                 }
                 StateProj::Connected(stream, parent) => {
-                    use sse_codec::Frame;
-
-                    let span = debug_span!(parent:&*parent, "read_frame").entered();
+                    let probe = match this.endpoints {
+                        Some(endpoints) => endpoints.poll_probe(cx),
+                        None => Pending,
+                    };
+                    if let Ready(primary) = probe {
+                        let _ = this
+                            .retry_url
+                            .lock()
+                            .expect("failed to acquire lock for url")
+                            .insert(primary);
+                        let span = debug_span!(parent: &*parent, "probe_primary");
+                        self.as_mut().set_state(EventSourceState::New(span));
+                        continue;
+                    }
+                    let _span = debug_span!(parent:&*parent, "read_frame").entered();
 
                     break match futures::ready!(stream.poll_next_unpin(cx)) {
-                        Some(Ok(frame)) => match frame {
-                            Frame::Comment(comment) => {
-                                let _span = debug_span!("read_frame::comment", ?comment).entered();
-                                span.record("kind", "comment");
-                                debug!(comment=comment.deref(), "received comment");
-
+                        Some(Ok(frame)) => {
+                            self.as_mut().handle_frame(&frame);
+                            if self.is_filtered(&frame) {
                                 continue;
                             }
-                            Frame::Event(event) => {
-                                let _span =
-                                    debug_span!("read_frame::event", name=event.name.deref(), id=?event.id, data_len=event.data.len())
-                                        .entered();
-                                debug!("received event");
-                                if event.id.is_some() && event.id != *this.last_event_id {
-                                    *this.last_event_id = event.id.clone()
-                                }
-
-                                Ready(Some(Ok(event)))
-                            }
-                            Frame::Retry(duration) => {
-                                let _span = debug_span!("read_frame::retry", ?duration).entered();
-                                debug!("received retry field, updated minimum duration");
-
-                                self.as_mut()
-                                    .project()
-                                    .backoff
-                                    .set_minimum_duration(duration);
-                                continue;
-                            }
-                        },
+                            Ready(Some(Ok(frame)))
+                        }
                         Some(Err(e)) => run_state!(self, handle_error(e)),
                         None => Poll::Ready(None),
                     };
                 }
-                StateProj::WaitingForRetry(mut sleep, parent) => {
-                    let span = debug_span!(parent: &*parent, "retry::wait").entered();
-                    match futures::ready!(sleep.poll_unpin(cx)) {
-                        () => {
-                            self.as_mut()
-                                .project()
-                                .state
-                                .set(EventSourceState::New(span));
-                            continue;
+                StateProj::Closing(decoder, buf) => {
+                    let _span = debug_span!("closing").entered();
+                    match decoder.decode(buf) {
+                        Ok(Some(frame)) => {
+                            self.as_mut().handle_frame(&frame);
+                            if self.is_filtered(&frame) {
+                                continue;
+                            }
+                            break Ready(Some(Ok(frame)));
                         }
+                        Ok(None) => debug!("finished yielding buffered frames"),
+                        Err(e) => debug!(error=%e, "discarding buffered data after close"),
                     }
+                    self.as_mut().project().state.set(EventSourceState::Closed);
+                    break Ready(None);
+                }
+                StateProj::WaitingForRetry(mut sleep, parent) => {
+                    let span = debug_span!(parent: &*parent, "retry::wait");
+                    futures::ready!(sleep.poll_unpin(cx));
+                    self.as_mut()
+                        .project()
+                        .state
+                        .set(EventSourceState::New(span));
+                    continue;
                 }
                 StateProj::Closed => break Ready(None),
             };
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder, EventSourceError, ReconnectReason,
+    };
+    use super::set_query_param;
+    use futures::StreamExt;
+    use reqwest::Url;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn close_yields_buffered_events() {
+        // one chunk, so "two" is already buffered when the stream is closed
+        let server = MockServer::start([
+            MockResponse::sse().frame("id: 1\ndata: one\n\nid: 2\ndata: two\n\n")
+        ])
+        .await;
+        let mut es = Box::pin(EventSourceBuilder::get(server.url()).build().unwrap());
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "one");
+        es.as_mut().close();
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "two");
+        assert_eq!(es.last_event_id.as_deref(), Some("2"));
+        assert!(es.next().await.is_none());
+
+        let stats = es.stats();
+        assert_eq!(stats.connection_attempts, 1);
+        assert_eq!(stats.connections, 1);
+        assert_eq!(stats.frames_received, 2);
+        assert!(stats.bytes_received > 0);
+    }
+
+    #[tokio::test]
+    async fn on_retry_changes_each_attempt() {
+        let server = MockServer::start([
+            MockResponse::status(503),
+            MockResponse::sse().event("hello"),
+        ])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(Duration::from_millis(1)))
+                .on_retry(|request, attempt| {
+                    request.url_mut().set_path(&format!("attempt-{}", attempt));
+                })
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        assert_eq!(es.connection_info().unwrap().url().path(), "/attempt-2");
+        assert_eq!(server.requests()[0].path, "/attempt-1");
+    }
+
+    #[tokio::test]
+    async fn body_factory_creates_body_for_each_attempt() {
+        let server = MockServer::start([
+            MockResponse::status(503),
+            MockResponse::sse().event("hello"),
+        ])
+        .await;
+        let bodies = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let created = bodies.clone();
+        let mut es = Box::pin(
+            EventSourceBuilder::post(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(Duration::from_millis(1)))
+                .body_factory(move || {
+                    created.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    // streamed bodies can't be cloned
+                    reqwest::Body::wrap_stream(futures::stream::iter([Ok::<_, std::io::Error>(
+                        "{\"filter\":\"all\"}",
+                    )]))
+                })
+                .on_retry(|request, attempt| {
+                    assert!(request.body().is_some());
+                    request.url_mut().set_path(&format!("attempt-{}", attempt));
+                })
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        assert_eq!(bodies.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(server.requests()[1].path, "/attempt-2");
+    }
+
+    #[tokio::test]
+    async fn filtered_events_still_update_last_event_id() {
+        let server = MockServer::start([MockResponse::sse()
+            .frame("event: put\nid: 1\ndata: one\n\n")
+            .event_with_id("2", "noise")
+            .frame("event: delete\nid: 3\ndata: two\n\n")
+            .frame("event: ping\nid: 4\ndata: noise\n\n")])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .filter_events(["put", "patch", "delete"])
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "one");
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "two");
+        assert!(es.next().await.is_none());
+        assert_eq!(es.last_event_id.as_deref(), Some("4"));
+    }
+
+    #[tokio::test]
+    async fn reconnect_keeps_decoder_state() {
+        let server = MockServer::start([
+            MockResponse::sse()
+                .event_with_id("1", "one")
+                .frame("event: put\ndata: partial\n")
+                .disconnect(),
+            MockResponse::sse().event("two"),
+        ])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(Duration::from_millis(1)))
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "one");
+        // the partial event is discarded but the next one still inherits the last event id
+        let event = es.next().await.unwrap().unwrap();
+        assert_eq!(&*event.data, "two");
+        assert_eq!(&*event.name, "message");
+        assert_eq!(event.id.as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn idle_reconnects_before_erroring() {
+        use tokio::io::AsyncWriteExt;
+        // accepts connections and then never sends anything
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\n")
+                    .await
+                    .unwrap();
+                sockets.push(socket);
+            }
+        });
+        let mut es = Box::pin(
+            EventSourceBuilder::get(url)
+                .read_timeout(Duration::from_millis(20))
+                .idle_reconnect(2)
+                .build()
+                .unwrap(),
+        );
+        match es.next().await {
+            Some(Err(EventSourceError::ReadTimeoutElapsed(..))) => {}
+            other => panic!("expected ReadTimeoutElapsed, got {:?}", other),
+        }
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn initialization_timeout_stops_retrying() {
+        let server = MockServer::start(std::iter::repeat(MockResponse::status(503))).await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(Duration::from_millis(5)))
+                .initialization_timeout(Duration::from_millis(50))
+                .build()
+                .unwrap(),
+        );
+        match es.next().await {
+            Some(Err(EventSourceError::InitializationTimeout(timeout))) => {
+                assert_eq!(timeout, Duration::from_millis(50))
+            }
+            other => panic!("expected InitializationTimeout, got {:?}", other),
+        }
+        assert!(es.stats().connection_attempts > 1);
+        assert!(es.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn reconnect_records_reason() {
+        let server = MockServer::start([
+            MockResponse::sse().event("hello"),
+            MockResponse::sse().event("hello"),
+        ])
+        .await;
+        let mut es = Box::pin(EventSourceBuilder::get(server.url()).build().unwrap());
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        assert_eq!(es.last_reconnect_reason(), None);
+
+        es.as_mut().reconnect(ReconnectReason::CredentialRotated);
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        assert_eq!(
+            es.last_reconnect_reason(),
+            Some(&ReconnectReason::CredentialRotated)
+        );
+        assert_eq!(es.connection_id(), 2);
+    }
+
+    #[tokio::test]
+    async fn no_content_ends_the_stream() {
+        let server = MockServer::start([
+            MockResponse::status(204),
+            MockResponse::sse().event("hello"),
+        ])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(Duration::from_millis(1)))
+                .build()
+                .unwrap(),
+        );
+        match es.next().await {
+            Some(Err(EventSourceError::StreamEnded)) => {}
+            other => panic!("expected StreamEnded, got {:?}", other),
+        }
+        assert!(es.next().await.is_none());
+        assert!(es.next().await.is_none());
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(es.stats().connection_attempts, 1);
+    }
+
+    #[test]
+    fn query_param_replaces_existing_id() {
+        let mut url = Url::parse("http://localhost/stream?filter=a&lastEventId=1").unwrap();
+        set_query_param(&mut url, "lastEventId", "2");
+        assert_eq!(url.query(), Some("filter=a&lastEventId=2"));
+
+        let mut url = Url::parse("http://localhost/stream").unwrap();
+        set_query_param(&mut url, "lastEventId", "a b");
+        assert_eq!(url.query(), Some("lastEventId=a+b"));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use reqwest::Url;
+use tokio::time::Sleep;
+use tracing::{info, warn};
+
+pub(crate) const DEFAULT_FAILOVER_AFTER: usize = 3;
+pub(crate) const DEFAULT_PRIMARY_PROBE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Ordered list of stream urls used by [`super::EventSource`], the first one is the primary
+///
+/// After `failover_after` consecutive failures the event source moves on to the next url.
+/// While connected to a fallback, the primary is retried every `probe_interval` and if that
+/// attempt fails the event source goes straight back to the fallback it came from.
+pub(crate) struct Endpoints {
+    urls: Vec<Url>,
+    current: usize,
+    failures: usize,
+    failover_after: usize,
+    probe_interval: Option<Duration>,
+    probe_timer: Option<Pin<Box<Sleep>>>,
+    // fallback to return to if probing the primary fails
+    probing_from: Option<usize>,
+}
+
+impl Endpoints {
+    pub(crate) fn new(
+        primary: Url,
+        fallbacks: Vec<Url>,
+        failover_after: usize,
+        probe_interval: Option<Duration>,
+    ) -> Self {
+        let mut urls = vec![primary];
+        urls.extend(fallbacks);
+        Self {
+            urls,
+            current: 0,
+            failures: 0,
+            failover_after: failover_after.max(1),
+            probe_interval,
+            probe_timer: None,
+            probing_from: None,
+        }
+    }
+
+    pub(crate) fn current(&self) -> &Url {
+        &self.urls[self.current]
+    }
+
+    /// Records a failed attempt, returning the url to use next if it changed
+    pub(crate) fn on_failure(&mut self) -> Option<Url> {
+        if let Some(fallback) = self.probing_from.take() {
+            warn!(primary=%self.urls[0], "primary endpoint is still failing, returning to fallback");
+            self.switch_to(fallback);
+            return Some(self.current().clone());
+        }
+        self.failures += 1;
+        if self.failures < self.failover_after || self.urls.len() < 2 {
+            return None;
+        }
+        let next = (self.current + 1) % self.urls.len();
+        warn!(from=%self.current(), to=%self.urls[next], failures=self.failures, "failing over to next endpoint");
+        self.switch_to(next);
+        Some(self.current().clone())
+    }
+
+    /// Records a successful connection
+    pub(crate) fn on_success(&mut self) {
+        self.failures = 0;
+        if self.probing_from.take().is_some() {
+            info!(primary=%self.current(), "primary endpoint recovered");
+        }
+    }
+
+    /// Resolves once it's time to try the primary again, returning its url
+    pub(crate) fn poll_probe(&mut self, cx: &mut Context<'_>) -> Poll<Url> {
+        match self.probe_timer.as_mut() {
+            Some(timer) => {
+                futures::ready!(timer.as_mut().poll(cx));
+                self.probe_timer = None;
+                info!(primary=%self.urls[0], "probing primary endpoint");
+                self.probing_from = Some(self.current);
+                self.current = 0;
+                self.failures = 0;
+                Poll::Ready(self.urls[0].clone())
+            }
+            None => Poll::Pending,
+        }
+    }
+
+    fn switch_to(&mut self, index: usize) {
+        self.current = index;
+        self.failures = 0;
+        self.probe_timer = match (index, self.probe_interval) {
+            (0, _) | (_, None) => None,
+            (_, Some(interval)) => Some(Box::pin(tokio::time::sleep(interval))),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::poll_fn;
+
+    fn endpoints(probe_interval: Option<Duration>) -> Endpoints {
+        Endpoints::new(
+            Url::parse("http://primary/").unwrap(),
+            vec![
+                Url::parse("http://secondary/").unwrap(),
+                Url::parse("http://tertiary/").unwrap(),
+            ],
+            2,
+            probe_interval,
+        )
+    }
+
+    #[tokio::test]
+    async fn rotates_after_repeated_failures() {
+        let mut endpoints = endpoints(None);
+        assert!(endpoints.on_failure().is_none());
+        assert_eq!(
+            endpoints.on_failure().unwrap().host_str(),
+            Some("secondary")
+        );
+        assert!(endpoints.on_failure().is_none());
+        endpoints.on_success();
+        assert!(endpoints.on_failure().is_none());
+        assert_eq!(endpoints.on_failure().unwrap().host_str(), Some("tertiary"));
+        endpoints.on_failure();
+        assert_eq!(endpoints.on_failure().unwrap().host_str(), Some("primary"));
+    }
+
+    #[tokio::test]
+    async fn failed_probe_returns_to_fallback() {
+        let mut endpoints = endpoints(Some(Duration::from_millis(1)));
+        endpoints.on_failure();
+        endpoints.on_failure();
+        assert_eq!(endpoints.current().host_str(), Some("secondary"));
+
+        let primary = poll_fn(|cx| endpoints.poll_probe(cx)).await;
+        assert_eq!(primary.host_str(), Some("primary"));
+        assert_eq!(
+            endpoints.on_failure().unwrap().host_str(),
+            Some("secondary")
+        );
+    }
+}
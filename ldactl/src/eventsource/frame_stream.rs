@@ -0,0 +1,77 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use pin_project::pin_project;
+use tokio_sse_codec::{BytesStr, Frame};
+
+use super::{EventSource, EventSourceError};
+
+/// Yields every frame received by an [`EventSource`], see [`EventSource::into_frame_stream`]
+///
+/// Reconnects, retries and `Last-Event-ID` tracking work exactly like they do for the
+/// event stream, comments and retry fields are just passed through as well.
+#[pin_project]
+pub struct FrameStream {
+    #[pin]
+    inner: EventSource,
+}
+
+impl FrameStream {
+    pub(crate) fn new(inner: EventSource) -> Self {
+        Self { inner }
+    }
+    /// The wrapped event source, for calling [`EventSource::reconnect`] or [`EventSource::close`]
+    pub fn event_source(self: Pin<&mut Self>) -> Pin<&mut EventSource> {
+        self.project().inner
+    }
+    /// Unwrap the event source, yielding events only again
+    pub fn into_inner(self) -> EventSource {
+        self.inner
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = Result<Frame<BytesStr>, EventSourceError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next_frame(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder,
+    };
+    use super::*;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn yields_comments_and_retry() {
+        let server = MockServer::start([MockResponse::sse()
+            .comment("keep-alive")
+            .frame("\n")
+            .retry(Duration::from_millis(1500))
+            .event("hello")])
+        .await;
+        let es = EventSourceBuilder::get(server.url()).build().unwrap();
+        let mut frames = Box::pin(es.into_frame_stream());
+        match frames.next().await {
+            Some(Ok(Frame::Comment(comment))) => assert_eq!(&*comment, "keep-alive"),
+            other => panic!("expected comment, got {:?}", other),
+        }
+        match frames.next().await {
+            Some(Ok(Frame::Retry(retry))) => assert_eq!(retry, Duration::from_millis(1500)),
+            other => panic!("expected retry, got {:?}", other),
+        }
+        match frames.next().await {
+            Some(Ok(Frame::Event(event))) => assert_eq!(&*event.data, "hello"),
+            other => panic!("expected event, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use reqwest::{Request, Response};
+use tower::{util::BoxCloneService, BoxError, Layer, Service, ServiceExt};
+
+use super::{errorext::EventSourceErrorInnerError, EventSourceError};
+
+/// Service that sends each connection request, wrapped by layers added with
+/// [`super::EventSourceBuilder::layer`]
+pub type HttpService = BoxCloneService<Request, Response, BoxError>;
+
+pub(crate) type BoxLayer = Box<dyn FnOnce(HttpService) -> HttpService + Send>;
+
+pub(crate) fn box_layer<L>(layer: L) -> BoxLayer
+where
+    L: Layer<HttpService> + Send + 'static,
+    L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+    <L::Service as Service<Request>>::Error: Into<BoxError>,
+    <L::Service as Service<Request>>::Future: Send + 'static,
+{
+    Box::new(move |inner| BoxCloneService::new(layer.layer(inner).map_err(Into::into)))
+}
+
+/// Wraps the client in `layers`, the first layer is the outermost one
+pub(crate) fn build_service(client: reqwest::Client, layers: Vec<BoxLayer>) -> HttpService {
+    let client = BoxCloneService::new(client.map_err(BoxError::from));
+    layers
+        .into_iter()
+        .rev()
+        .fold(client, |service, layer| layer(service))
+}
+
+/// Recovers errors coming from the client, anything else is reported as a middleware error
+pub(crate) fn into_event_source_error(error: BoxError) -> EventSourceError {
+    match error.downcast::<reqwest::Error>() {
+        Ok(e) => e.into_event_source_error(),
+        Err(error) => match error.downcast::<EventSourceError>() {
+            Ok(e) => *e,
+            Err(error) => EventSourceError::Middleware(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder,
+    };
+    use super::*;
+    use futures::StreamExt;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::layer::layer_fn;
+
+    #[tokio::test]
+    async fn layers_wrap_each_request() {
+        let server = MockServer::start([MockResponse::sse().event("hello")]).await;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .layer(layer_fn(move |inner: HttpService| {
+                    let counter = counter.clone();
+                    tower::service_fn(move |request: Request| {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        inner.clone().oneshot(request)
+                    })
+                }))
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn layer_errors_are_middleware_errors() {
+        let error = into_event_source_error("rejected by budget".into());
+        assert!(matches!(error, EventSourceError::Middleware(_)));
+    }
+}
@@ -1,20 +1,45 @@
+mod authenticator;
+mod buffered;
 mod builder;
+mod connection_closed;
 mod errorext;
+#[allow(clippy::module_inception)]
 mod eventsource;
+mod failover;
+mod frame_stream;
+mod middleware;
+mod received;
+mod reconnect_policy;
+mod recording;
+mod redirect;
+mod response_meta;
 mod retryable;
 mod sse_backoff;
 mod state_util;
+mod stats;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
-pub use builder::{EventSourceBuilder, EventSourceBuilderError};
-pub use eventsource::{EventSource, EventSourceError};
+pub use authenticator::{Authenticator, AuthorizeError};
+pub use buffered::BufferedEventSource;
+pub use builder::{
+    headers_from_env, EventSourceBuilder, EventSourceBuilderError, EXTRA_HEADERS_ENV,
+};
+pub use connection_closed::{ConnectionClosed, ConnectionClosedKind};
+pub use eventsource::{
+    EventSource, EventSourceError, EventSourceErrorKind, LastEventIdMode, ReconnectReason,
+};
+pub use frame_stream::FrameStream;
+pub use middleware::HttpService;
+pub use received::{Received, ReceivedStream};
+pub use reconnect_policy::ReconnectPolicy;
+pub use recording::ReplayStream;
+pub use response_meta::ResponseMeta;
+pub use retryable::{is_retryable_status, RetryStatusPredicate};
+pub use sse_backoff::{CircuitBreaker, Jitter, ReconnectLimiter, RetryFieldMode};
+pub use stats::EventSourceStats;
 pub type Result<T> = std::result::Result<T, EventSourceError>;
 
-mod backoff {
-    pub use backoff::backoff::Backoff;
-    pub use backoff::ExponentialBackoff;
-    pub use backoff::ExponentialBackoffBuilder;
-}
-
 // re-exports from reqwest
 pub mod http {
     pub mod header {
@@ -22,9 +47,19 @@ pub mod http {
             HeaderMap, HeaderName, HeaderValue, InvalidHeaderName, InvalidHeaderValue,
         };
     }
-    mod redirect {
-        pub use reqwest::redirect::{Attempt, Policy};
+    pub mod cookie {
+        pub use reqwest::cookie::{CookieStore, Jar};
     }
-    pub use reqwest::{Body, Client, ClientBuilder, IntoUrl, Request, RequestBuilder, Url};
+    pub mod dns {
+        pub use hyper::client::connect::dns::Name;
+        pub use reqwest::dns::{Addrs, Resolve, Resolving};
+    }
+    pub use reqwest::{
+        Body, Client, ClientBuilder, IntoUrl, Proxy, Request, RequestBuilder, Url,
+    };
     pub use reqwest::{Method, StatusCode, Version};
+    pub mod tls {
+        pub use reqwest::tls::Version;
+        pub use reqwest::{Certificate, Identity};
+    }
 }
@@ -0,0 +1,81 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use futures::{Stream, StreamExt};
+use pin_project::pin_project;
+use tokio_sse_codec::{BytesStr, Event};
+
+use super::{EventSource, EventSourceError};
+
+/// An item along with the connection it arrived on and when it was received
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Received<T> {
+    /// The received item
+    pub event: T,
+    /// Connection the item arrived on, see [`EventSource::connection_id`]
+    pub connection_id: u64,
+    /// When the item was read from the stream
+    pub received_at: SystemTime,
+}
+
+/// Yields events wrapped in [`Received`], see [`EventSource::into_received_stream`]
+#[pin_project]
+pub struct ReceivedStream {
+    #[pin]
+    inner: EventSource,
+}
+
+impl ReceivedStream {
+    pub(crate) fn new(inner: EventSource) -> Self {
+        Self { inner }
+    }
+    /// The wrapped event source, for calling [`EventSource::reconnect`] or [`EventSource::close`]
+    pub fn event_source(self: Pin<&mut Self>) -> Pin<&mut EventSource> {
+        self.project().inner
+    }
+    /// Unwrap the event source, yielding plain events again
+    pub fn into_inner(self) -> EventSource {
+        self.inner
+    }
+}
+
+impl Stream for ReceivedStream {
+    type Item = Result<Received<Event<BytesStr>>, EventSourceError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut inner = self.project().inner;
+        let item = futures::ready!(inner.poll_next_unpin(cx));
+        Poll::Ready(item.map(|result| {
+            result.map(|event| Received {
+                event,
+                connection_id: inner.connection_id(),
+                received_at: SystemTime::now(),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder,
+    };
+    use super::*;
+
+    #[tokio::test]
+    async fn tags_events_with_connection() {
+        let server = MockServer::start([MockResponse::sse().event("hello")]).await;
+        let before = SystemTime::now();
+        let es = EventSourceBuilder::get(server.url()).build().unwrap();
+        let mut received = Box::pin(es.into_received_stream());
+        let item = received.next().await.unwrap().unwrap();
+        assert_eq!(&*item.event.data, "hello");
+        assert_eq!(item.connection_id, 1);
+        assert!(item.received_at >= before);
+    }
+}
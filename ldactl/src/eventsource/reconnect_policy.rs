@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use backoff::backoff::Backoff;
+
+/// Decides how long [`super::EventSource`] waits before reconnecting after a recoverable error
+///
+/// Implemented for every [`backoff::backoff::Backoff`] so existing strategies such as
+/// [`backoff::ExponentialBackoff`] can be used directly.
+pub trait ReconnectPolicy: Send {
+    /// Delay before the next connection attempt. Returning `None` stops retrying and closes the
+    /// event source with [`super::EventSourceError::MaxRetriesExceeded`]
+    fn next_delay(&mut self) -> Option<Duration>;
+
+    /// Called after a connection was successfully established
+    fn on_success(&mut self);
+
+    /// Called when the server sends a `retry:` field
+    fn on_retry_field(&mut self, retry: Duration) {
+        let _ = retry;
+    }
+
+    /// Return to the initial state, called before the first connection attempt.
+    /// Defaults to [`ReconnectPolicy::on_success`]
+    fn reset(&mut self) {
+        self.on_success()
+    }
+}
+
+impl<B> ReconnectPolicy for B
+where
+    B: Backoff + Send,
+{
+    fn next_delay(&mut self) -> Option<Duration> {
+        self.next_backoff()
+    }
+
+    fn on_success(&mut self) {
+        Backoff::reset(self)
+    }
+}
+
+impl ReconnectPolicy for Box<dyn ReconnectPolicy> {
+    fn next_delay(&mut self) -> Option<Duration> {
+        (**self).next_delay()
+    }
+
+    fn on_success(&mut self) {
+        (**self).on_success()
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        (**self).on_retry_field(retry)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
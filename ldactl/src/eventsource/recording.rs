@@ -0,0 +1,216 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, Write},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::Stream;
+use tokio_sse_codec::{BytesStr, Event, Frame, SseDecoder};
+use tokio_util::codec::Decoder;
+use tracing::warn;
+
+use super::EventSourceError;
+
+const CONNECT: &str = "connect";
+const DATA: &str = "data";
+
+/// Tees response bodies to a writer, see [`super::EventSourceBuilder::record_to`]
+///
+/// Each connection starts with a `connect` line, followed by a `data <len>` line and `len` raw
+/// bytes for every chunk read from the body.
+#[derive(Clone)]
+pub(crate) struct Recorder(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Recorder {
+    pub(crate) fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(writer))))
+    }
+
+    pub(crate) fn record_connect(&self) {
+        self.write(|w| writeln!(w, "{}", CONNECT));
+    }
+
+    pub(crate) fn record_chunk(&self, chunk: &[u8]) {
+        self.write(|w| {
+            writeln!(w, "{} {}", DATA, chunk.len())?;
+            w.write_all(chunk)?;
+            w.flush()
+        });
+    }
+
+    // recording is best effort, a failing writer shouldn't take the stream down
+    fn write(&self, f: impl FnOnce(&mut dyn Write) -> std::io::Result<()>) {
+        let mut writer = self.0.lock().expect("failed to acquire recorder lock");
+        if let Err(e) = f(&mut **writer) {
+            warn!(error=%e, "failed to record event source session");
+        }
+    }
+}
+
+/// Replays a session recorded with [`super::EventSourceBuilder::record_to`]
+///
+/// Yields the same items as [`super::EventSource`]. Every recorded connection is decoded with a
+/// fresh decoder, like the event source does after reconnecting, so parsing and reconnect bugs
+/// can be reproduced offline. A decode error skips the rest of its connection.
+pub struct ReplayStream {
+    connections: VecDeque<VecDeque<Bytes>>,
+    chunks: VecDeque<Bytes>,
+    decoder: SseDecoder<BytesStr>,
+    buf: BytesMut,
+}
+
+impl ReplayStream {
+    pub fn from_reader(mut reader: impl BufRead) -> std::io::Result<Self> {
+        let mut connections = VecDeque::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            match line.trim_end().split_once(' ') {
+                None if line.trim_end() == CONNECT => connections.push_back(VecDeque::new()),
+                Some((DATA, len)) => {
+                    let len = len
+                        .parse()
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                    let mut chunk = vec![0; len];
+                    reader.read_exact(&mut chunk)?;
+                    if connections.is_empty() {
+                        connections.push_back(VecDeque::new());
+                    }
+                    connections
+                        .back_mut()
+                        .unwrap()
+                        .push_back(Bytes::from(chunk));
+                }
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("unexpected record {:?}", line),
+                    ))
+                }
+            }
+        }
+        let chunks = connections.pop_front().unwrap_or_default();
+        Ok(Self {
+            connections,
+            chunks,
+            decoder: SseDecoder::new(),
+            buf: BytesMut::new(),
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    /// Moves on to the next recorded connection, returns false if there are none left
+    fn next_connection(&mut self) -> bool {
+        self.decoder = SseDecoder::new();
+        self.buf.clear();
+        match self.connections.pop_front() {
+            Some(chunks) => {
+                self.chunks = chunks;
+                true
+            }
+            None => {
+                self.chunks.clear();
+                false
+            }
+        }
+    }
+}
+
+impl Stream for ReplayStream {
+    type Item = Result<Event<BytesStr>, EventSourceError>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match this.decoder.decode(&mut this.buf) {
+                Ok(Some(Frame::Event(event))) => return Poll::Ready(Some(Ok(event))),
+                Ok(Some(_)) => continue,
+                Ok(None) => {}
+                Err(e) => {
+                    this.next_connection();
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+            }
+            if let Some(chunk) = this.chunks.pop_front() {
+                this.buf.extend_from_slice(&chunk);
+                continue;
+            }
+            // an event cut off by the end of its connection is never dispatched
+            if !this.next_connection() {
+                return Poll::Ready(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder,
+    };
+    use super::*;
+    use futures::StreamExt;
+
+    /// Shares the recorded bytes with the test
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_session() {
+        let server = MockServer::start([
+            MockResponse::sse()
+                .event_with_id("1", "one")
+                .frame("data: cut")
+                .disconnect(),
+            MockResponse::sse().comment("keep-alive").event("two"),
+        ])
+        .await;
+        let recording = SharedBuf::default();
+        let live = EventSourceBuilder::get(server.url())
+            .with_backoff_strategy(backoff::backoff::Constant::new(
+                std::time::Duration::from_millis(1),
+            ))
+            .record_to(recording.clone())
+            .build()
+            .unwrap();
+        let live: Vec<_> = live
+            .map(|event| event.unwrap().data.to_string())
+            .collect()
+            .await;
+        assert_eq!(live, ["one", "two"]);
+
+        let recording = recording.0.lock().unwrap().clone();
+        let replayed: Vec<_> = ReplayStream::from_reader(&recording[..])
+            .unwrap()
+            .map(|event| event.unwrap().data.to_string())
+            .collect()
+            .await;
+        assert_eq!(replayed, live);
+    }
+
+    #[test]
+    fn rejects_unknown_records() {
+        assert!(ReplayStream::from_reader(&b"connect\nbogus 3\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+
+use reqwest::{redirect::Policy, StatusCode, Url};
+use tracing::debug;
+
+use super::EventSourceError;
+
+pub(crate) const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Builds the redirect policy used by [`super::EventSource`]
+///
+/// Permanent redirects (301, 308) update `retry_url` so future reconnects go straight to the new location.
+/// Temporary redirects (302, 303, 307) are followed but reconnects keep using the original url.
+/// Once more than `max_redirects` have been followed, the request fails with [`EventSourceError::TooManyRedirects`]
+pub(crate) fn redirect_policy(
+    retry_url: Arc<Mutex<Option<Url>>>,
+    max_redirects: usize,
+    inner: Policy,
+) -> Policy {
+    Policy::custom(move |attempt| {
+        let count = attempt.previous().len();
+        if count > max_redirects {
+            return attempt.error(EventSourceError::TooManyRedirects(count));
+        }
+        match attempt.status() {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT => {
+                let next_url = attempt.url().clone();
+                debug!(url=%next_url, status=%attempt.status(), "permanent redirect, setting url for future retries");
+                let _ = retry_url
+                    .lock()
+                    .expect("failed to acquire lock for url")
+                    .insert(next_url);
+            }
+            status => {
+                debug!(url=%attempt.url(), %status, "temporary redirect, future retries will use the original url");
+            }
+        }
+        inner.redirect(attempt)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder, EventSourceError,
+    };
+    use futures::StreamExt;
+    use reqwest::Url;
+
+    fn redirect_response(status: u16, location: &str) -> MockResponse {
+        MockResponse::status(status).header("location", location)
+    }
+
+    async fn retry_url_after_first_event(base: Url, path: &str) -> Url {
+        let mut es = Box::pin(
+            EventSourceBuilder::get(base.join(path).unwrap())
+                .build()
+                .unwrap(),
+        );
+        let event = es.next().await.unwrap().unwrap();
+        assert_eq!(&*event.data, "hello");
+        let url = es.retry_url.lock().unwrap().clone().unwrap();
+        url
+    }
+
+    #[tokio::test]
+    async fn permanent_redirects_rebase() {
+        for status in [301, 308] {
+            let server = MockServer::start([
+                redirect_response(status, "/new"),
+                MockResponse::sse().event("hello"),
+            ])
+            .await;
+            let url = retry_url_after_first_event(server.url(), "old").await;
+            assert_eq!(url, server.url().join("new").unwrap(), "{}", status);
+            assert_eq!(server.requests()[1].path, "/new");
+        }
+    }
+
+    #[tokio::test]
+    async fn temporary_redirects_keep_original_url() {
+        for status in [302, 307] {
+            let server = MockServer::start([
+                redirect_response(status, "/new"),
+                MockResponse::sse().event("hello"),
+            ])
+            .await;
+            let url = retry_url_after_first_event(server.url(), "temp").await;
+            assert_eq!(url, server.url().join("temp").unwrap(), "{}", status);
+            assert_eq!(server.requests()[1].path, "/new");
+        }
+    }
+
+    #[tokio::test]
+    async fn too_many_redirects_is_fatal() {
+        let server = MockServer::start(std::iter::repeat(redirect_response(302, "/loop"))).await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url().join("loop").unwrap())
+                .max_redirects(3)
+                .build()
+                .unwrap(),
+        );
+        match es.next().await {
+            Some(Err(e @ EventSourceError::TooManyRedirects(_))) => assert!(!e.is_retryable()),
+            other => panic!("expected TooManyRedirects, got {:?}", other),
+        }
+        assert!(es.next().await.is_none());
+    }
+}
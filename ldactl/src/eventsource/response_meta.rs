@@ -0,0 +1,43 @@
+use reqwest::{header::HeaderMap, Response, StatusCode, Url, Version};
+
+/// Details about the HTTP response that opened the current connection.
+///
+/// Captured right before the response body is handed to the SSE decoder so
+/// consumers can log things like `X-Request-Id` or rate-limit headers.
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    url: Url,
+}
+
+impl ResponseMeta {
+    /// Status code of the response
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+    /// HTTP version negotiated for the connection
+    pub fn version(&self) -> Version {
+        self.version
+    }
+    /// Headers sent by the server
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+    /// Final url after following redirects
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+}
+
+impl From<&Response> for ResponseMeta {
+    fn from(response: &Response) -> Self {
+        Self {
+            status: response.status(),
+            version: response.version(),
+            headers: response.headers().clone(),
+            url: response.url().clone(),
+        }
+    }
+}
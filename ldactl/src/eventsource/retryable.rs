@@ -1,12 +1,25 @@
-use super::EventSourceError;
+use std::sync::Arc;
+
+use super::{authenticator::is_auth_failure, EventSourceError};
 use reqwest::StatusCode;
 
+/// Decides whether a connection attempt that failed with the given HTTP status should be retried
+pub type RetryStatusPredicate = Arc<dyn Fn(StatusCode) -> bool + Send + Sync>;
+
+/// Retries server errors, request timeouts and rate limiting
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
 pub trait Retryable {
     fn is_retryable(&self) -> bool;
 }
 
-impl Retryable for EventSourceError {
-    fn is_retryable(&self) -> bool {
+impl EventSourceError {
+    /// Whether the event source retries this error on its own with the default retry policy
+    pub fn is_retryable(&self) -> bool {
         match self {
             EventSourceError::RequestCloneError => false,
             EventSourceError::RequestError(e) => e.is_retryable(),
@@ -14,32 +27,59 @@ impl Retryable for EventSourceError {
             EventSourceError::DecodeError(_) => true,
             EventSourceError::ReadTimeoutElapsed(..) => true,
             EventSourceError::TooManyRedirects(..) => false,
+            EventSourceError::StreamEnded => false,
+            EventSourceError::InitializationTimeout(_) => false,
+            // graceful drains and resets are expected to recover on a new connection
+            EventSourceError::ConnectionClosed(..) => true,
+            EventSourceError::InvalidContentType { .. } => false,
+            // token endpoints can be temporarily unavailable too
+            EventSourceError::AuthorizationError(_) => true,
+            // timeouts, load shedding and retry budgets are expected to recover
+            EventSourceError::Middleware(_) => true,
             // we will treat all i/o errors as retryable here
             EventSourceError::Io(_) => true,
         }
     }
+
+    /// Whether the error is caused by configuration or the server rejecting the client, so
+    /// starting over won't help until something changes.
+    /// Exhausted retries are only fatal if the last error was
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            EventSourceError::RequestCloneError => true,
+            EventSourceError::RequestError(_) => match self.status() {
+                Some(status) => {
+                    is_auth_failure(status)
+                        || (status.is_client_error() && !is_retryable_status(status))
+                }
+                None => false,
+            },
+            EventSourceError::MaxRetriesExceeded(_, last) => {
+                last.as_ref().is_some_and(|e| e.is_fatal())
+            }
+            EventSourceError::TooManyRedirects(_) => true,
+            EventSourceError::StreamEnded => true,
+            EventSourceError::InvalidContentType { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl Retryable for EventSourceError {
+    fn is_retryable(&self) -> bool {
+        EventSourceError::is_retryable(self)
+    }
 }
 
 impl Retryable for reqwest::Error {
     fn is_retryable(&self) -> bool {
         match self.status() {
-            Some(status) => match status {
-                _ if status.is_server_error() => true,
-                StatusCode::BAD_REQUEST
-                | StatusCode::REQUEST_TIMEOUT
-                | StatusCode::TOO_MANY_REQUESTS => true,
-                _ => false,
-            },
+            Some(status) => is_retryable_status(status),
             None => {
-                if self.is_connect()
+                self.is_connect()
                     || self.is_timeout()
                     || self.is_decode()
                     || (!self.is_request() && self.is_body())
-                {
-                    true
-                } else {
-                    false
-                }
             }
         }
     }
@@ -47,16 +87,104 @@ impl Retryable for reqwest::Error {
 
 impl Retryable for std::io::Error {
     fn is_retryable(&self) -> bool {
-        match self.kind() {
+        matches!(
+            self.kind(),
             std::io::ErrorKind::ConnectionRefused
-            | std::io::ErrorKind::ConnectionReset
-            | std::io::ErrorKind::ConnectionAborted
-            | std::io::ErrorKind::AddrInUse
-            | std::io::ErrorKind::WouldBlock
-            | std::io::ErrorKind::TimedOut
-            | std::io::ErrorKind::Interrupted
-            | std::io::ErrorKind::UnexpectedEof => true,
-            _ => false,
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::AddrInUse
+                | std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted
+                | std::io::ErrorKind::UnexpectedEof
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        test_util::{MockResponse, MockServer},
+        EventSourceBuilder, EventSourceErrorKind,
+    };
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn classifies_errors() {
+        let server =
+            MockServer::start([MockResponse::status(404), MockResponse::status(503)]).await;
+        let mut es = Box::pin(EventSourceBuilder::get(server.url()).build().unwrap());
+        let e = es.next().await.unwrap().unwrap_err();
+        assert_eq!(e.kind(), EventSourceErrorKind::Status);
+        assert!(!e.is_retryable());
+        assert!(e.is_fatal());
+
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .max_retries(0)
+                .build()
+                .unwrap(),
+        );
+        let e = es.next().await.unwrap().unwrap_err();
+        assert_eq!(e.kind(), EventSourceErrorKind::RetriesExhausted);
+        assert!(!e.is_retryable());
+        assert!(!e.is_fatal());
+
+        let e = EventSourceError::StreamEnded;
+        assert_eq!(e.kind(), EventSourceErrorKind::StreamEnded);
+        assert!(e.is_fatal());
+    }
+
+    #[test]
+    fn retryable_statuses() {
+        for status in [408, 429, 500, 503] {
+            assert!(is_retryable_status(StatusCode::from_u16(status).unwrap()));
         }
+        for status in [400, 401, 404, 409] {
+            assert!(!is_retryable_status(StatusCode::from_u16(status).unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_only_configured_statuses() {
+        let server = MockServer::start([
+            MockResponse::status(429),
+            MockResponse::sse().event("hello").disconnect(),
+            MockResponse::status(503),
+        ])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(
+                    std::time::Duration::from_millis(1),
+                ))
+                .retry_statuses([StatusCode::TOO_MANY_REQUESTS])
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "hello");
+        let e = es.next().await.unwrap().unwrap_err();
+        assert_eq!(e.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(server.requests().len(), 3);
+
+        // client errors other than 408 and 429 aren't retried by default
+        let server = MockServer::start([
+            MockResponse::status(400),
+            MockResponse::sse().event("hello"),
+        ])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(
+                    std::time::Duration::from_millis(1),
+                ))
+                .build()
+                .unwrap(),
+        );
+        let e = es.next().await.unwrap().unwrap_err();
+        assert_eq!(e.status(), Some(StatusCode::BAD_REQUEST));
+        assert!(e.is_fatal());
+        assert_eq!(server.requests().len(), 1);
     }
 }
@@ -1,35 +1,36 @@
-use backoff::backoff::Backoff;
-use std::{ops::DerefMut, time::Duration};
-pub trait WithMinimumBackoff<B>
+use super::reconnect_policy::ReconnectPolicy;
+use rand::Rng;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+pub trait WithMinimumBackoff<P>
 where
-    B: std::ops::Deref<Target = dyn Backoff> + Sized,
+    P: ReconnectPolicy + Sized,
 {
-    fn with_minimum_duration(self, duration: Duration) -> MinimumBackoffDuration<B>;
+    fn with_minimum_duration(self, duration: Duration) -> MinimumBackoffDuration<P>;
 }
 
-impl<B> WithMinimumBackoff<B> for B
+impl<P> WithMinimumBackoff<P> for P
 where
-    B: std::ops::Deref<Target = dyn Backoff> + Sized,
+    P: ReconnectPolicy + Sized,
 {
     fn with_minimum_duration(self, duration: Duration) -> MinimumBackoffDuration<Self> {
         MinimumBackoffDuration::new(self, duration)
     }
 }
 
+/// Never waits less than the last `retry:` value sent by the server
 #[derive(Debug)]
-pub struct MinimumBackoffDuration<B>
-where
-    B: std::ops::Deref<Target = dyn Backoff> + Sized,
-{
-    backoff: B,
+pub struct MinimumBackoffDuration<P> {
+    backoff: P,
     minimum_duration: Duration,
 }
 
-impl<B> MinimumBackoffDuration<B>
-where
-    B: std::ops::Deref<Target = dyn Backoff>,
-{
-    pub fn new(backoff: B, minimum_duration: Duration) -> Self {
+impl<P> MinimumBackoffDuration<P> {
+    pub fn new(backoff: P, minimum_duration: Duration) -> Self {
         Self {
             backoff,
             minimum_duration,
@@ -40,18 +41,492 @@ where
     }
 }
 
-impl<B> Backoff for MinimumBackoffDuration<B>
+impl<P> ReconnectPolicy for MinimumBackoffDuration<P>
 where
-    B: std::ops::DerefMut<Target = dyn Backoff> + Sized,
+    P: ReconnectPolicy,
 {
-    fn next_backoff(&mut self) -> Option<Duration> {
+    fn next_delay(&mut self) -> Option<Duration> {
         self.backoff
-            .deref_mut()
-            .next_backoff()
+            .next_delay()
             .map(|duration| duration.max(self.minimum_duration))
     }
 
+    fn on_success(&mut self) {
+        self.backoff.on_success();
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        self.set_minimum_duration(retry);
+        self.backoff.on_retry_field(retry);
+    }
+
+    fn reset(&mut self) {
+        self.backoff.reset();
+    }
+}
+
+/// How the server's `retry:` field affects reconnect delays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetryFieldMode {
+    /// Never wait less than the server's `retry:` value, longer delays from the reconnect policy are kept
+    #[default]
+    Minimum,
+    /// Wait exactly the server's `retry:` value, as described by the SSE spec.
+    /// The reconnect policy is still consulted so limits such as max retries apply
+    Exact,
+}
+
+/// Waits exactly the last `retry:` value sent by the server, falling back to the wrapped policy until one is received
+#[derive(Debug)]
+pub struct ExactBackoffDuration<P> {
+    backoff: P,
+    duration: Option<Duration>,
+}
+
+impl<P> ExactBackoffDuration<P> {
+    pub fn new(backoff: P) -> Self {
+        Self {
+            backoff,
+            duration: None,
+        }
+    }
+}
+
+impl<P> ReconnectPolicy for ExactBackoffDuration<P>
+where
+    P: ReconnectPolicy,
+{
+    fn next_delay(&mut self) -> Option<Duration> {
+        let next = self.backoff.next_delay()?;
+        Some(self.duration.unwrap_or(next))
+    }
+
+    fn on_success(&mut self) {
+        self.backoff.on_success();
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        self.duration = Some(retry);
+        self.backoff.on_retry_field(retry);
+    }
+
+    fn reset(&mut self) {
+        self.backoff.reset();
+    }
+}
+
+/// Stops any reconnect policy after a number of retries or once enough time has elapsed since the last reset
+#[derive(Debug)]
+pub struct LimitedBackoff<B> {
+    backoff: B,
+    max_retries: Option<usize>,
+    max_elapsed: Option<Duration>,
+    retries: usize,
+    started: Instant,
+}
+
+impl<B> LimitedBackoff<B> {
+    pub fn new(backoff: B, max_retries: Option<usize>, max_elapsed: Option<Duration>) -> Self {
+        Self {
+            backoff,
+            max_retries,
+            max_elapsed,
+            retries: 0,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl<P> ReconnectPolicy for LimitedBackoff<P>
+where
+    P: ReconnectPolicy,
+{
+    fn next_delay(&mut self) -> Option<Duration> {
+        if matches!(self.max_retries, Some(max) if self.retries >= max) {
+            return None;
+        }
+        if matches!(self.max_elapsed, Some(max) if self.started.elapsed() >= max) {
+            return None;
+        }
+        self.retries += 1;
+        self.backoff.next_delay()
+    }
+
+    fn on_success(&mut self) {
+        self.retries = 0;
+        self.started = Instant::now();
+        self.backoff.on_success();
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        self.backoff.on_retry_field(retry);
+    }
+
+    fn reset(&mut self) {
+        self.retries = 0;
+        self.started = Instant::now();
+        self.backoff.reset();
+    }
+}
+
+/// Randomizes the delays produced by a reconnect policy so many clients don't reconnect in lockstep
+///
+/// See <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Jitter {
+    /// Use the delay from the backoff strategy as-is
+    #[default]
+    None,
+    /// A random delay between zero and the backoff delay
+    Full,
+    /// Half of the backoff delay plus a random delay up to the other half
+    Equal,
+    /// A random delay between the first delay and three times the previous delay, capped at the backoff delay
+    Decorrelated,
+}
+
+#[derive(Debug)]
+pub struct JitteredBackoff<B> {
+    backoff: B,
+    jitter: Jitter,
+    base: Option<Duration>,
+    previous: Option<Duration>,
+}
+
+impl<B> JitteredBackoff<B> {
+    pub fn new(backoff: B, jitter: Jitter) -> Self {
+        Self {
+            backoff,
+            jitter,
+            base: None,
+            previous: None,
+        }
+    }
+}
+
+impl<P> ReconnectPolicy for JitteredBackoff<P>
+where
+    P: ReconnectPolicy,
+{
+    fn next_delay(&mut self) -> Option<Duration> {
+        let duration = self.backoff.next_delay()?;
+        let mut rng = rand::thread_rng();
+        let next = match self.jitter {
+            Jitter::None => duration,
+            Jitter::Full => duration.mul_f64(rng.gen_range(0.0..=1.0)),
+            Jitter::Equal => duration / 2 + (duration / 2).mul_f64(rng.gen_range(0.0..=1.0)),
+            Jitter::Decorrelated => {
+                let base = *self.base.get_or_insert(duration);
+                let upper = self.previous.unwrap_or(base).saturating_mul(3).max(base);
+                rng.gen_range(base..=upper).min(duration)
+            }
+        };
+        self.previous = Some(next);
+        Some(next)
+    }
+
+    fn on_success(&mut self) {
+        self.base = None;
+        self.previous = None;
+        self.backoff.on_success();
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        self.backoff.on_retry_field(retry);
+    }
+
+    fn reset(&mut self) {
+        self.base = None;
+        self.previous = None;
+        self.backoff.reset();
+    }
+}
+
+/// Settings for [`CircuitBreakerBackoff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Failures within `window` that open the circuit
+    pub failure_threshold: usize,
+    /// How far back failures are counted
+    pub window: Duration,
+    /// How long to wait before a single probe attempt once the circuit is open
+    pub open_for: Duration,
+    /// Give up after the circuit opened this many times without a successful connection
+    pub max_trips: Option<usize>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            window: Duration::from_secs(60),
+            open_for: Duration::from_secs(5 * 60),
+            max_trips: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CircuitState {
+    Closed,
+    // the next attempt is a probe, failing it opens the circuit again
+    HalfOpen,
+}
+
+/// Stops a reconnect policy from hammering an endpoint that keeps failing
+///
+/// Once `failure_threshold` failures happen within `window` the circuit opens and the next
+/// attempt waits `open_for`. That attempt is a probe: if it fails the circuit opens again right
+/// away, if it succeeds failures are forgotten.
+#[derive(Debug)]
+pub struct CircuitBreakerBackoff<B> {
+    backoff: B,
+    config: CircuitBreaker,
+    state: CircuitState,
+    failures: VecDeque<Instant>,
+    trips: usize,
+}
+
+impl<B> CircuitBreakerBackoff<B> {
+    pub fn new(backoff: B, config: CircuitBreaker) -> Self {
+        Self {
+            backoff,
+            config,
+            state: CircuitState::Closed,
+            failures: VecDeque::new(),
+            trips: 0,
+        }
+    }
+
+    fn trip(&mut self, delay: Duration) -> Option<Duration> {
+        self.trips += 1;
+        if matches!(self.config.max_trips, Some(max) if self.trips > max) {
+            warn!(
+                trips = self.trips - 1,
+                "circuit breaker opened too many times, giving up"
+            );
+            return None;
+        }
+        warn!(open_for=?self.config.open_for, trips=self.trips, "too many failures, opening circuit breaker");
+        self.state = CircuitState::HalfOpen;
+        self.failures.clear();
+        Some(delay.max(self.config.open_for))
+    }
+}
+
+impl<P> ReconnectPolicy for CircuitBreakerBackoff<P>
+where
+    P: ReconnectPolicy,
+{
+    fn next_delay(&mut self) -> Option<Duration> {
+        let delay = self.backoff.next_delay()?;
+        if self.state == CircuitState::HalfOpen {
+            return self.trip(delay);
+        }
+        let now = Instant::now();
+        self.failures.push_back(now);
+        while matches!(self.failures.front(), Some(at) if now.duration_since(*at) > self.config.window)
+        {
+            self.failures.pop_front();
+        }
+        if self.failures.len() >= self.config.failure_threshold {
+            return self.trip(delay);
+        }
+        Some(delay)
+    }
+
+    fn on_success(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            info!("probe succeeded, closing circuit breaker");
+        }
+        self.state = CircuitState::Closed;
+        self.failures.clear();
+        self.trips = 0;
+        self.backoff.on_success();
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        self.backoff.on_retry_field(retry);
+    }
+
+    fn reset(&mut self) {
+        self.backoff.reset();
+    }
+}
+
+/// Token bucket shared by every event source that should reconnect at a combined rate
+///
+/// Wrap it in an [`Arc`] and pass it to [`super::EventSourceBuilder::reconnect_limiter`] for each
+/// event source. The bucket starts with `capacity` tokens and regains one every `refill_interval`,
+/// each reconnect takes one. When the bucket is empty the reconnect waits for the next token, so a
+/// process watching many streams doesn't stampede a backend that just recovered.
+#[derive(Debug)]
+pub struct ReconnectLimiter {
+    refill_interval: Duration,
+    burst: Duration,
+    // when the bucket will be full again, `None` until the first reservation
+    full_at: Mutex<Option<Instant>>,
+}
+
+impl ReconnectLimiter {
+    pub fn new(capacity: u32, refill_interval: Duration) -> Self {
+        Self {
+            refill_interval,
+            burst: refill_interval.saturating_mul(capacity.max(1) - 1),
+            full_at: Mutex::new(None),
+        }
+    }
+
+    /// Takes a token for a reconnect planned in `delay`, returning how long to actually wait
+    pub fn reserve(&self, delay: Duration) -> Duration {
+        let now = Instant::now();
+        let at = now + delay;
+        let mut full_at = self.full_at.lock().expect("failed to acquire limiter lock");
+        let next = full_at.map_or(at, |full_at| full_at.max(at));
+        let allowed = next
+            .checked_sub(self.burst)
+            .map_or(at, |allowed| allowed.max(at));
+        *full_at = Some(next + self.refill_interval);
+        allowed - now
+    }
+}
+
+/// Delays reconnects until a shared [`ReconnectLimiter`] has a token available
+#[derive(Debug)]
+pub struct RateLimitedBackoff<B> {
+    backoff: B,
+    limiter: Arc<ReconnectLimiter>,
+}
+
+impl<B> RateLimitedBackoff<B> {
+    pub fn new(backoff: B, limiter: Arc<ReconnectLimiter>) -> Self {
+        Self { backoff, limiter }
+    }
+}
+
+impl<P> ReconnectPolicy for RateLimitedBackoff<P>
+where
+    P: ReconnectPolicy,
+{
+    fn next_delay(&mut self) -> Option<Duration> {
+        let delay = self.backoff.next_delay()?;
+        let limited = self.limiter.reserve(delay);
+        if limited > delay {
+            debug!(?delay, ?limited, "reconnect delayed by shared rate limiter");
+        }
+        Some(limited)
+    }
+
+    fn on_success(&mut self) {
+        self.backoff.on_success();
+    }
+
+    fn on_retry_field(&mut self, retry: Duration) {
+        self.backoff.on_retry_field(retry);
+    }
+
     fn reset(&mut self) {
-        self.backoff.deref_mut().reset();
+        self.backoff.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backoff::backoff::Constant;
+
+    #[test]
+    fn limits_retries() {
+        let mut backoff =
+            LimitedBackoff::new(Constant::new(Duration::from_millis(1)), Some(2), None);
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_none());
+        ReconnectPolicy::on_success(&mut backoff);
+        assert!(backoff.next_delay().is_some());
+    }
+
+    #[test]
+    fn limits_elapsed() {
+        let mut backoff = LimitedBackoff::new(
+            Constant::new(Duration::from_millis(1)),
+            None,
+            Some(Duration::ZERO),
+        );
+        assert!(backoff.next_delay().is_none());
+    }
+
+    #[test]
+    fn exact_uses_retry_field() {
+        let mut backoff = ExactBackoffDuration::new(LimitedBackoff::new(
+            Constant::new(Duration::from_secs(10)),
+            Some(2),
+            None,
+        ));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(10)));
+        backoff.on_retry_field(Duration::from_millis(5));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(5)));
+        assert_eq!(backoff.next_delay(), None);
+    }
+
+    #[test]
+    fn jitter_stays_within_backoff() {
+        let delay = Duration::from_millis(100);
+        for jitter in [
+            Jitter::None,
+            Jitter::Full,
+            Jitter::Equal,
+            Jitter::Decorrelated,
+        ] {
+            let mut backoff = JitteredBackoff::new(Constant::new(delay), jitter);
+            for _ in 0..100 {
+                let next = backoff.next_delay().unwrap();
+                assert!(next <= delay, "{:?} produced {:?}", jitter, next);
+                if jitter == Jitter::Equal {
+                    assert!(next >= delay / 2, "{:?} produced {:?}", jitter, next);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circuit_breaker_opens_and_probes() {
+        let delay = Duration::from_millis(1);
+        let open_for = Duration::from_secs(30);
+        let mut backoff = CircuitBreakerBackoff::new(
+            Constant::new(delay),
+            CircuitBreaker {
+                failure_threshold: 3,
+                window: Duration::from_secs(60),
+                open_for,
+                max_trips: Some(2),
+            },
+        );
+        assert_eq!(backoff.next_delay(), Some(delay));
+        assert_eq!(backoff.next_delay(), Some(delay));
+        assert_eq!(backoff.next_delay(), Some(open_for));
+        // failed probe opens the circuit again
+        assert_eq!(backoff.next_delay(), Some(open_for));
+        // then gives up
+        assert_eq!(backoff.next_delay(), None);
+
+        ReconnectPolicy::on_success(&mut backoff);
+        assert_eq!(backoff.next_delay(), Some(delay));
+    }
+
+    #[test]
+    fn limiter_is_shared_between_policies() {
+        let refill = Duration::from_secs(10);
+        let limiter = Arc::new(ReconnectLimiter::new(2, refill));
+        let mut first = RateLimitedBackoff::new(Constant::new(Duration::ZERO), limiter.clone());
+        let mut second = RateLimitedBackoff::new(Constant::new(Duration::ZERO), limiter);
+        // the burst goes through right away
+        assert_eq!(first.next_delay(), Some(Duration::ZERO));
+        assert_eq!(second.next_delay(), Some(Duration::ZERO));
+        // then each reconnect waits for the next token
+        let delay = first.next_delay().unwrap();
+        assert!(delay > refill - Duration::from_secs(1) && delay <= refill);
+        let delay = second.next_delay().unwrap();
+        assert!(delay > refill * 2 - Duration::from_secs(1) && delay <= refill * 2);
     }
 }
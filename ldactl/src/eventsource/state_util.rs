@@ -1,29 +1,37 @@
 use std::pin::Pin;
 
-use super::EventSourceError;
-use futures::{Future, Stream};
+use super::{authenticator::AuthorizeError, EventSourceError, ReconnectReason};
+use bytes::BytesMut;
+use futures::Future;
 use pin_project::pin_project;
-use reqwest::Response;
-use tokio_sse_codec::{BytesStr, Event as CodecEvent, Frame as CodecFrame};
+use reqwest::{Client, Request, Response};
+use tokio::io::AsyncRead;
+use tokio_sse_codec::{BytesStr, Frame as CodecFrame, SseDecoder};
+use tokio_util::codec::FramedRead;
 type Frame = CodecFrame<BytesStr>;
-type Event = CodecEvent<BytesStr>;
+
+pub(crate) type FrameReader = FramedRead<Pin<Box<dyn AsyncRead + Send>>, SseDecoder<BytesStr>>;
 
 pub(crate) type NextState = Option<EventSourceState>;
 
 #[pin_project(project = StateProj)]
 pub(crate) enum EventSourceState {
     Initial,
-    ForceReconnect(tracing::span::EnteredSpan),
-    New(tracing::span::EnteredSpan),
-    Connect(
-        Pin<Box<dyn Future<Output = Result<Response, reqwest::Error>> + Send>>,
-        tracing::span::EnteredSpan,
+    ForceReconnect(ReconnectReason, tracing::Span),
+    New(tracing::Span),
+    Authorize(
+        Pin<Box<dyn Future<Output = Result<Request, AuthorizeError>> + Send>>,
+        Client,
+        tracing::Span,
     ),
-    Connected(
-        Pin<Box<dyn Stream<Item = Result<Frame, EventSourceError>> + Send>>,
-        tracing::span::EnteredSpan,
+    Connect(
+        Pin<Box<dyn Future<Output = Result<Response, EventSourceError>> + Send>>,
+        tracing::Span,
     ),
-    WaitingForRetry(#[pin] tokio::time::Sleep, tracing::span::EnteredSpan),
+    Connected(FrameReader, tracing::Span),
+    WaitingForRetry(#[pin] tokio::time::Sleep, tracing::Span),
+    // closed by the consumer, yields frames left in the read buffer
+    Closing(SseDecoder<BytesStr>, BytesMut),
     Closed,
 }
 
@@ -33,9 +41,11 @@ impl std::fmt::Display for EventSourceState {
             EventSourceState::Initial => write!(f, "Initial"),
             EventSourceState::ForceReconnect(..) => write!(f, "ForcedReconnect"),
             EventSourceState::New(_) => write!(f, "New"),
+            EventSourceState::Authorize(..) => write!(f, "Authorize"),
             EventSourceState::Connect(_, _) => write!(f, "Connect"),
             EventSourceState::Connected(_, _) => write!(f, "Connected"),
             EventSourceState::WaitingForRetry(..) => write!(f, "WaitingForRetry"),
+            EventSourceState::Closing(..) => write!(f, "Closing"),
             EventSourceState::Closed => write!(f, "Closed"),
         }
     }
@@ -47,7 +57,7 @@ impl std::fmt::Debug for EventSourceState {
 }
 
 pub(crate) enum StateAction {
-    Break(std::task::Poll<Option<Result<Event, EventSourceError>>>),
+    Break(std::task::Poll<Option<Result<Frame, EventSourceError>>>),
     Continue,
 }
 
@@ -59,7 +69,7 @@ pub(crate) mod macros {
         let (action, next_state) = { $target.as_mut().$action($($args,)*) };
 
         if let Some(next_state) = next_state {
-            $target.as_mut().project().state.set(next_state.into());
+            $target.as_mut().set_state(next_state.into());
         };
         match action {
             StateAction::Break(v) => break v,
@@ -0,0 +1,163 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::EventSourceError;
+
+/// Names of the metrics emitted when the `metrics` feature is enabled
+#[cfg(feature = "metrics")]
+pub(crate) mod names {
+    pub(crate) const CONNECTION_ATTEMPTS: &str = "eventsource_connection_attempts_total";
+    pub(crate) const CONNECTIONS: &str = "eventsource_connections_total";
+    pub(crate) const FRAMES: &str = "eventsource_frames_total";
+    pub(crate) const BYTES: &str = "eventsource_bytes_total";
+    pub(crate) const ERRORS: &str = "eventsource_errors_total";
+    pub(crate) const DECODE_ERRORS: &str = "eventsource_decode_errors_total";
+    pub(crate) const RECONNECT_DELAY: &str = "eventsource_reconnect_delay_seconds";
+}
+
+/// Snapshot of counters kept by [`super::EventSource`], see [`super::EventSource::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EventSourceStats {
+    /// Requests sent to the server, including retries
+    pub connection_attempts: u64,
+    /// Connection attempts that resulted in an open stream
+    pub connections: u64,
+    /// Frames decoded from the stream, including comments and retry fields
+    pub frames_received: u64,
+    /// Bytes read from response bodies
+    pub bytes_received: u64,
+    /// How long the current connection has been open, `None` while disconnected
+    pub uptime: Option<Duration>,
+    /// Most recent error with its sources, whether or not it was retried
+    pub last_error: Option<String>,
+}
+
+/// Counts bytes read from response bodies, shared with the body stream
+#[derive(Debug, Default, Clone)]
+pub(crate) struct BytesCounter(Arc<AtomicU64>);
+
+impl BytesCounter {
+    pub(crate) fn add(&self, bytes: usize) {
+        self.0.fetch_add(bytes as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::counter!(names::BYTES, bytes as u64);
+    }
+}
+
+/// Keeps the counters behind [`EventSourceStats`] and, with the `metrics` feature, emits them
+/// through the `metrics` facade as well
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder {
+    connection_attempts: u64,
+    connections: u64,
+    frames_received: u64,
+    bytes_received: BytesCounter,
+    connected_at: Option<Instant>,
+    last_frame_at: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl StatsRecorder {
+    pub(crate) fn record_attempt(&mut self) {
+        self.connection_attempts += 1;
+        self.connected_at = None;
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter!(names::CONNECTION_ATTEMPTS);
+    }
+
+    pub(crate) fn record_connected(&mut self) {
+        self.connections += 1;
+        self.connected_at = Some(Instant::now());
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter!(names::CONNECTIONS);
+    }
+
+    pub(crate) fn record_frame(&mut self) {
+        self.frames_received += 1;
+        self.last_frame_at = Some(Instant::now());
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter!(names::FRAMES);
+    }
+
+    pub(crate) fn record_retry_delay(&self, delay: Duration) {
+        #[cfg(feature = "metrics")]
+        metrics::histogram!(names::RECONNECT_DELAY, delay);
+        #[cfg(not(feature = "metrics"))]
+        let _ = delay;
+    }
+
+    pub(crate) fn record_error(&mut self, error: &EventSourceError) {
+        #[cfg(feature = "metrics")]
+        {
+            metrics::increment_counter!(names::ERRORS);
+            if matches!(error, EventSourceError::DecodeError(_)) {
+                metrics::increment_counter!(names::DECODE_ERRORS);
+            }
+        }
+        self.connected_at = None;
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(error);
+        while let Some(e) = source {
+            message.push_str(": ");
+            message.push_str(&e.to_string());
+            source = e.source();
+        }
+        self.last_error = Some(message);
+    }
+
+    pub(crate) fn connection_id(&self) -> u64 {
+        self.connections
+    }
+
+    pub(crate) fn last_frame_at(&self) -> Option<Instant> {
+        self.last_frame_at
+    }
+
+    pub(crate) fn bytes_counter(&self) -> BytesCounter {
+        self.bytes_received.clone()
+    }
+
+    pub(crate) fn snapshot(&self) -> EventSourceStats {
+        EventSourceStats {
+            connection_attempts: self.connection_attempts,
+            connections: self.connections,
+            frames_received: self.frames_received,
+            bytes_received: self.bytes_received.0.load(Ordering::Relaxed),
+            uptime: self.connected_at.map(|at| at.elapsed()),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_error_includes_sources() {
+        let mut stats = StatsRecorder::default();
+        stats.record_attempt();
+        stats.record_connected();
+        assert!(stats.snapshot().uptime.is_some());
+
+        let error = super::super::EventSourceError::Io(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "connection reset",
+        ));
+        stats.record_error(&error);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.connection_attempts, 1);
+        assert_eq!(snapshot.connections, 1);
+        assert_eq!(snapshot.uptime, None);
+        assert_eq!(
+            snapshot.last_error.as_deref(),
+            Some("io error: connection reset")
+        );
+    }
+}
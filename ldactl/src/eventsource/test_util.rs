@@ -0,0 +1,302 @@
+//! Scripted SSE server for testing reconnect and backoff behavior
+//!
+//! Each connection accepted by [`MockServer`] is answered with the next [`MockResponse`] from
+//! its script, so a test can lay out exactly which attempts fail, which ones stream events and
+//! how each connection ends.
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::{JoinHandle, JoinSet},
+};
+use tokio_sse_codec::{Frame, SseEncoder};
+use tokio_util::codec::Encoder;
+
+use crate::messages::Message;
+
+#[derive(Debug, Clone)]
+enum Step {
+    Write(String),
+    Delay(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ending {
+    // sends the final chunk, the event source sees the stream end
+    Close,
+    // drops the connection mid-body, the event source sees an error and reconnects
+    Disconnect,
+    // keeps the connection open without sending anything else
+    Hold,
+}
+
+/// Response to a single connection, see [`MockServer`]
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    steps: Vec<Step>,
+    ending: Ending,
+}
+
+impl MockResponse {
+    /// `200 OK` with a `text/event-stream` body made of the frames added afterwards
+    pub fn sse() -> Self {
+        Self::status(200).header("content-type", "text/event-stream")
+    }
+
+    /// Response with the given status and an empty body
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            steps: Vec::new(),
+            ending: Ending::Close,
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sends an unnamed event, multi-line data is split into several `data:` fields
+    pub fn event(self, data: &str) -> Self {
+        let frame = data
+            .lines()
+            .map(|line| format!("data: {}\n", line))
+            .collect::<String>();
+        self.frame(frame + "\n")
+    }
+
+    /// Sends an unnamed event with an id
+    pub fn event_with_id(self, id: &str, data: &str) -> Self {
+        self.frame(format!("id: {}\n", id)).event(data)
+    }
+
+    /// Sends an autoconfig message, encoded the way LaunchDarkly sends it
+    pub fn message(self, msg: &Message) -> Self {
+        let mut buf = BytesMut::new();
+        SseEncoder::new()
+            .encode(Frame::from(msg), &mut buf)
+            .expect("encoding into memory can't fail");
+        self.frame(String::from_utf8(buf.to_vec()).expect("messages are utf-8"))
+    }
+
+    /// Sends a comment, often used as a keep-alive
+    pub fn comment(self, comment: &str) -> Self {
+        self.frame(format!(": {}\n", comment))
+    }
+
+    /// Sends a `retry:` field
+    pub fn retry(self, retry: Duration) -> Self {
+        self.frame(format!("retry: {}\n\n", retry.as_millis()))
+    }
+
+    /// Sends raw sse text as-is, useful for malformed or partial frames
+    pub fn frame(mut self, frame: impl Into<String>) -> Self {
+        self.steps.push(Step::Write(frame.into()));
+        self
+    }
+
+    /// Waits before sending the next frame
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.steps.push(Step::Delay(delay));
+        self
+    }
+
+    /// Drops the connection after the last frame without finishing the body
+    pub fn disconnect(mut self) -> Self {
+        self.ending = Ending::Disconnect;
+        self
+    }
+
+    /// Keeps the connection open after the last frame until the server is dropped
+    pub fn hold(mut self) -> Self {
+        self.ending = Ending::Hold;
+        self
+    }
+
+    async fn write_to(self, mut socket: TcpStream) -> std::io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} Mock\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("transfer-encoding: chunked\r\nconnection: close\r\n\r\n");
+        socket.write_all(head.as_bytes()).await?;
+        for step in self.steps {
+            match step {
+                Step::Write(data) if !data.is_empty() => {
+                    let chunk = format!("{:x}\r\n{}\r\n", data.len(), data);
+                    socket.write_all(chunk.as_bytes()).await?;
+                }
+                Step::Write(_) => {}
+                Step::Delay(delay) => tokio::time::sleep(delay).await,
+            }
+        }
+        match self.ending {
+            Ending::Close => socket.write_all(b"0\r\n\r\n").await?,
+            Ending::Disconnect => {}
+            Ending::Hold => std::future::pending::<()>().await,
+        }
+        socket.shutdown().await
+    }
+}
+
+/// Request line and headers received by [`MockServer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl RecordedRequest {
+    /// First value of the header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn parse(head: &str) -> Self {
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let method = request_line.next().unwrap_or_default().to_owned();
+        let path = request_line.next().unwrap_or("/").to_owned();
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+            .collect();
+        Self {
+            method,
+            path,
+            headers,
+        }
+    }
+}
+
+/// Local SSE server answering each connection with the next response from a script
+///
+/// Once the script runs out, connections get `404 Not Found`. Dropping the server closes every
+/// connection, including held ones.
+pub struct MockServer {
+    url: Url,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start<I>(script: I) -> Self
+    where
+        I: IntoIterator<Item = MockResponse>,
+        I::IntoIter: Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let mut script = script.into_iter();
+        let task = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let head = match read_head(&mut socket).await {
+                    Ok(head) => head,
+                    Err(_) => continue,
+                };
+                recorded.lock().unwrap().push(RecordedRequest::parse(&head));
+                let response = script.next().unwrap_or_else(|| MockResponse::status(404));
+                connections.spawn(response.write_to(socket));
+            }
+        });
+        Self {
+            url,
+            requests,
+            task,
+        }
+    }
+
+    /// Base url of the server, every path is answered from the same script
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Requests received so far, one per connection
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn read_head(socket: &mut TcpStream) -> std::io::Result<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        match socket.read(&mut chunk).await? {
+            0 => break,
+            n => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{EventSourceBuilder, EventSourceError};
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn reconnects_through_script() {
+        let server = MockServer::start([
+            MockResponse::status(503),
+            MockResponse::sse()
+                .comment("keep-alive")
+                .event_with_id("1", "one")
+                .disconnect(),
+            MockResponse::sse()
+                .delay(Duration::from_millis(5))
+                .event("two\nlines"),
+        ])
+        .await;
+        let mut es = Box::pin(
+            EventSourceBuilder::get(server.url())
+                .with_backoff_strategy(backoff::backoff::Constant::new(Duration::from_millis(1)))
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "one");
+        assert_eq!(&*es.next().await.unwrap().unwrap().data, "two\nlines");
+        assert!(es.next().await.is_none());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].header("last-event-id"), None);
+        assert_eq!(requests[2].header("Last-Event-ID"), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn exhausted_script_returns_not_found() {
+        let server = MockServer::start([]).await;
+        let mut es = Box::pin(EventSourceBuilder::get(server.url()).build().unwrap());
+        match es.next().await {
+            Some(Err(e @ EventSourceError::RequestError(_))) => {
+                assert_eq!(e.status(), Some(reqwest::StatusCode::NOT_FOUND))
+            }
+            other => panic!("expected 404, got {:?}", other),
+        }
+    }
+}
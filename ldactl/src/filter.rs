@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::messages::{EnvironmentConfig, ProjectKey};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum EnvironmentFilterError {
+    #[error("invalid environment key glob {0:?}")]
+    #[diagnostic(help("globs support `*`, `?`, `[...]` and `{{a,b}}`"))]
+    InvalidGlob(String, #[source] globset::Error),
+}
+
+/// Restricts [`crate::AutoConfigClient`] to some of the environments in the stream
+///
+/// Environments that don't match are dropped before they're stored, so they never produce
+/// change events. An empty filter matches everything. Keys are matched ignoring ASCII case.
+#[derive(Debug, Clone, Default)]
+pub struct EnvironmentFilter {
+    // lowercase
+    project_keys: Option<HashSet<String>>,
+    env_keys: Option<GlobSet>,
+}
+
+impl EnvironmentFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep environments in one of the given projects
+    pub fn project_keys<I, S>(mut self, project_keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.project_keys = Some(
+            project_keys
+                .into_iter()
+                .map(|key| key.into().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Only keep environments whose key matches one of the given globs, such as `prod*`
+    pub fn env_key_globs<I, S>(mut self, globs: I) -> Result<Self, EnvironmentFilterError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            let glob = glob.as_ref();
+            builder.add(
+                GlobBuilder::new(glob)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| EnvironmentFilterError::InvalidGlob(glob.to_owned(), e))?,
+            );
+        }
+        self.env_keys = Some(
+            builder
+                .build()
+                .map_err(|e| EnvironmentFilterError::InvalidGlob(String::new(), e))?,
+        );
+        Ok(self)
+    }
+
+    pub fn matches(&self, env: &EnvironmentConfig) -> bool {
+        let project_matches = self.matches_project(&env.proj_key);
+        let env_matches = self
+            .env_keys
+            .as_ref()
+            .is_none_or(|globs| globs.is_match(env.env_key.as_ref()));
+        project_matches && env_matches
+    }
+
+    /// Whether environments in the project can match, used for payload filters
+    pub fn matches_project(&self, proj_key: &ProjectKey) -> bool {
+        self.project_keys.as_ref().is_none_or(|keys| {
+            keys.contains(&proj_key.as_ref().to_ascii_lowercase())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::EnvironmentFixture;
+
+    fn environment(proj_key: &str, env_key: &str) -> EnvironmentConfig {
+        EnvironmentFixture::new()
+            .proj_key(proj_key)
+            .env_key(env_key)
+            .build()
+    }
+
+    #[test]
+    fn matches_projects_and_env_key_globs() {
+        assert!(EnvironmentFilter::new().matches(&environment("default", "test")));
+
+        let filter = EnvironmentFilter::new()
+            .project_keys(["default", "mobile"])
+            .env_key_globs(["prod*", "staging"])
+            .unwrap();
+        assert!(filter.matches(&environment("default", "production")));
+        assert!(filter.matches(&environment("mobile", "staging")));
+        assert!(filter.matches(&environment("Mobile", "Production")));
+        assert!(!filter.matches(&environment("default", "test")));
+        assert!(!filter.matches(&environment("other", "production")));
+
+        assert!(matches!(
+            EnvironmentFilter::new().env_key_globs(["prod[*"]),
+            Err(EnvironmentFilterError::InvalidGlob(glob, _)) if glob == "prod[*"
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use launchdarkly_autoconfig::{AutoConfigClient, SequencedChange};
+use miette::{Context, IntoDiagnostic};
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// How often the connection state is refreshed between change events
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What `/healthz` and `/status` report, kept up to date by the watch loop
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    pub initialized: bool,
+    /// Always `true` for offline clients
+    pub connected: bool,
+    pub stale: bool,
+    pub environment_count: usize,
+    /// Id of the last stream message that changed something
+    pub last_event_id: Option<String>,
+    #[serde(serialize_with = "serialize_time")]
+    pub last_change_at: Option<SystemTime>,
+}
+
+impl Health {
+    /// Liveness and readiness: the initial put was processed and the stream is up
+    pub fn is_healthy(&self) -> bool {
+        self.initialized && self.connected
+    }
+
+    /// The stream is connected and has been sending something, the watchdog's idea of alive
+    pub fn is_live(&self) -> bool {
+        self.connected && !self.stale
+    }
+
+    pub fn refresh(&mut self, client: &AutoConfigClient) {
+        self.initialized = client.is_initialized();
+        self.connected = match client.stats().event_source {
+            Some(stats) => stats.uptime.is_some(),
+            None => true,
+        };
+        self.stale = client.is_stale();
+        self.environment_count = client.environments().len();
+    }
+
+    pub fn changed(&mut self, client: &AutoConfigClient, change: &SequencedChange) {
+        self.refresh(client);
+        if let Some(id) = change.message_id.as_ref() {
+            self.last_event_id = Some(id.clone());
+        }
+        self.last_change_at = Some(SystemTime::now());
+    }
+}
+
+fn serialize_time<S: serde::Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.collect_str(&humantime::format_rfc3339_millis(*time)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status<'a> {
+    healthy: bool,
+    #[serde(flatten)]
+    health: &'a Health,
+    uptime_ms: u128,
+}
+
+/// Serves `/healthz` and `/status` on `addr` until the process exits
+///
+/// Binds before returning so a port that's in use fails startup instead of being logged.
+pub fn serve(addr: SocketAddr, health: watch::Receiver<Health>) -> Result<(), miette::Report> {
+    let started = Instant::now();
+    let make_service = make_service_fn(move |_| {
+        let health = health.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = respond(&request, &health.borrow(), started);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    let server = hyper::Server::try_bind(&addr)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {addr} for --health-listen"))?
+        .serve(make_service);
+    debug!(%addr, "serving health checks");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            warn!(error=%e, "health check server failed");
+        }
+    });
+    Ok(())
+}
+
+fn respond(request: &Request<Body>, health: &Health, started: Instant) -> Response<Body> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+    }
+    match request.uri().path() {
+        "/healthz" if health.is_healthy() => text(StatusCode::OK, "ok\n"),
+        "/healthz" if !health.initialized => {
+            text(StatusCode::SERVICE_UNAVAILABLE, "not initialized\n")
+        }
+        "/healthz" => text(StatusCode::SERVICE_UNAVAILABLE, "disconnected\n"),
+        "/status" => {
+            let status = Status {
+                healthy: health.is_healthy(),
+                health,
+                uptime_ms: started.elapsed().as_millis(),
+            };
+            let body = serde_json::to_vec(&status).expect("status serializes to json");
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .expect("valid response")
+        }
+        _ => text(StatusCode::NOT_FOUND, "not found\n"),
+    }
+}
+
+fn text(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_health() {
+        let started = Instant::now();
+        let get = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+        let mut health = Health::default();
+        assert_eq!(
+            respond(&get("/healthz"), &health, started).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        health.initialized = true;
+        health.connected = true;
+        health.environment_count = 2;
+        assert_eq!(
+            respond(&get("/healthz"), &health, started).status(),
+            StatusCode::OK
+        );
+        let status = respond(&get("/status"), &health, started);
+        assert_eq!(status.status(), StatusCode::OK);
+        assert_eq!(
+            respond(&get("/metrics"), &health, started).status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let status = Status {
+            healthy: true,
+            health: &health,
+            uptime_ms: 5,
+        };
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["environmentCount"], 2);
+        assert_eq!(json["lastChangeAt"], serde_json::Value::Null);
+        assert_eq!(json["uptimeMs"], 5);
+    }
+}
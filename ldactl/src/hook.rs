@@ -0,0 +1,694 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use backoff::backoff::Backoff;
+use launchdarkly_autoconfig::messages::PayloadFilter;
+use launchdarkly_autoconfig::{filter_key_vars, ConfigChangeEvent, SequencedChange};
+use miette::Diagnostic;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{debug, instrument, warn};
+
+/// Prefix of the variables describing the event, such as `LDAC_EVENT_KIND`
+pub const DEFAULT_ENV_PREFIX: &str = "LDAC";
+
+/// How long a hook gets to exit after SIGTERM before it's killed
+pub const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+/// Delay before the first retry of a failed hook, doubling up to [`MAX_RETRY_DELAY`]
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How hooks for changes that arrive while one is still running are executed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Concurrency {
+    /// One at a time in the order the changes happened
+    #[default]
+    Serialize,
+    /// Up to this many at once, in no particular order
+    Parallel(u32),
+    /// One at a time, each hook skipping to the latest change to an environment that arrived
+    /// while a hook ran
+    Coalesce,
+}
+
+impl FromStr for Concurrency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{s:?} isn't one of serialize, parallel(N) or coalesce");
+        match s {
+            "serialize" => Ok(Self::Serialize),
+            "coalesce" => Ok(Self::Coalesce),
+            _ => match s
+                .strip_prefix("parallel(")
+                .and_then(|s| s.strip_suffix(')'))
+                .map(str::parse)
+            {
+                Some(Ok(limit)) if limit > 0 => Ok(Self::Parallel(limit)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Concurrency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Serialize => f.write_str("serialize"),
+            Self::Parallel(limit) => write!(f, "parallel({limit})"),
+            Self::Coalesce => f.write_str("coalesce"),
+        }
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum HookError {
+    #[error("failed to start hook command {command:?}")]
+    Spawn {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to run hook command {command:?}")]
+    Io {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("hook command {command:?} exited with {status}")]
+    Exit { command: String, status: ExitStatus },
+    #[error("hook command {command:?} timed out after {}", humantime::format_duration(*.timeout))]
+    #[diagnostic(help(
+        "the command was sent SIGTERM and killed if it was still running {} later",
+        humantime::format_duration(KILL_GRACE_PERIOD)
+    ))]
+    TimedOut {
+        command: String,
+        timeout: Duration,
+        /// Set if the command exited on SIGTERM, otherwise it was killed
+        status: Option<ExitStatus>,
+    },
+    #[error("failed to write {} to the dead letter file {}", .0, .1.display())]
+    DeadLetter(String, PathBuf, #[source] std::io::Error),
+}
+
+impl HookError {
+    /// Failures that may go away on their own, such as a downstream service being down
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Exit { .. } | Self::TimedOut { .. })
+    }
+}
+
+/// Command run for change events, with the event as json on stdin
+///
+/// Also gets `LDAC_EVENT_KIND` and `LDAC_SEQUENCE` in its environment,
+/// `LDAC_ENVIRONMENT_COUNT` for `initialized` events and `LDAC_<PROJ>_FILTER_KEYS` for every
+/// project with payload filters, with `LDAC` replaced by [`Hook::env_prefix`].
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Also run for [`ConfigChangeEvent::Initialized`]
+    pub on_init: bool,
+    /// Only run for events of this [kind](ConfigChangeEvent::kind)
+    pub kind: Option<&'static str>,
+    /// Stop commands running longer than this, see [`HookError::TimedOut`]
+    pub timeout: Option<Duration>,
+    /// Times a [retryable](HookError::is_retryable) failure is retried with exponential backoff
+    pub retries: u32,
+    /// Events whose hook still failed after every retry are appended here as json lines
+    pub dead_letter: Option<PathBuf>,
+    /// Run `command` with `<shell> -c`, passing `args` as `$1` and onwards
+    pub shell: Option<String>,
+    pub env_prefix: String,
+    /// Working directory, ldactl's own if unset
+    pub current_dir: Option<PathBuf>,
+    /// Pass ldactl's environment on to the command, otherwise it only gets `PATH`
+    pub inherit_env: bool,
+    /// Set for every run, before the variables describing the event
+    pub env: Vec<(String, String)>,
+}
+
+impl Hook {
+    pub fn new(command: String, args: Vec<String>) -> Self {
+        Self {
+            command,
+            args,
+            on_init: false,
+            kind: None,
+            timeout: None,
+            retries: 0,
+            dead_letter: None,
+            shell: None,
+            env_prefix: DEFAULT_ENV_PREFIX.to_owned(),
+            current_dir: None,
+            inherit_env: true,
+            env: Vec::new(),
+        }
+    }
+
+    pub fn on_init(mut self, on_init: bool) -> Self {
+        self.on_init = on_init;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn dead_letter(mut self, dead_letter: Option<PathBuf>) -> Self {
+        self.dead_letter = dead_letter;
+        self
+    }
+
+    pub fn shell(mut self, shell: Option<String>) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    pub fn env_prefix(mut self, env_prefix: String) -> Self {
+        self.env_prefix = env_prefix;
+        self
+    }
+
+    pub fn current_dir(mut self, current_dir: Option<PathBuf>) -> Self {
+        self.current_dir = current_dir;
+        self
+    }
+
+    pub fn inherit_env(mut self, inherit_env: bool) -> Self {
+        self.inherit_env = inherit_env;
+        self
+    }
+
+    pub fn env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn only(mut self, kind: &'static str) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn wants(&self, change: &ConfigChangeEvent) -> bool {
+        match self.kind {
+            Some(kind) => change.kind() == kind,
+            None => self.on_init || !matches!(change, ConfigChangeEvent::Initialized(_)),
+        }
+    }
+
+    /// Executes the hook, retrying failures and recording the event in the dead letter file if
+    /// it never succeeds
+    pub async fn run(
+        &self,
+        change_event: &SequencedChange,
+        filters: &[PayloadFilter],
+    ) -> Result<(), HookError> {
+        let mut backoff = backoff::ExponentialBackoffBuilder::new()
+            .with_initial_interval(INITIAL_RETRY_DELAY)
+            .with_max_interval(MAX_RETRY_DELAY)
+            .with_max_elapsed_time(None)
+            .build();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match self.execute(change_event, filters).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if attempts <= self.retries && error.is_retryable() {
+                let delay = backoff.next_backoff().unwrap_or(MAX_RETRY_DELAY);
+                warn!(error=%error, attempts, ?delay, sequence = change_event.sequence, "hook command failed, retrying");
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            if let Some(path) = self.dead_letter.as_ref() {
+                self.write_dead_letter(path, change_event, attempts, &error)
+                    .map_err(|e| HookError::DeadLetter(error.to_string(), path.clone(), e))?;
+            }
+            return Err(error);
+        }
+    }
+
+    fn write_dead_letter(
+        &self,
+        path: &Path,
+        change_event: &SequencedChange,
+        attempts: u32,
+        error: &HookError,
+    ) -> std::io::Result<()> {
+        let entry = serde_json::json!({
+            "failedAt": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            "command": self.command,
+            "attempts": attempts,
+            "error": error.to_string(),
+            "event": change_event,
+        });
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        // one write per line so concurrent appends don't interleave
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)
+    }
+
+    #[instrument(skip(self, change_event, filters), fields(cmd = %self.command, kind = change_event.change.kind(), sequence = change_event.sequence))]
+    pub async fn execute(
+        &self,
+        change_event: &SequencedChange,
+        filters: &[PayloadFilter],
+    ) -> Result<(), HookError> {
+        // TODO: wrap the output in tracing
+        let mut cmd = match self.shell.as_ref() {
+            Some(shell) => {
+                let mut cmd = Command::new(shell);
+                cmd.arg("-c").arg(&self.command).arg("ldactl");
+                cmd
+            }
+            None => Command::new(&self.command),
+        };
+        if !self.inherit_env {
+            cmd.env_clear();
+            if let Some(path) = std::env::var_os("PATH") {
+                cmd.env("PATH", path);
+            }
+        }
+        if let Some(dir) = self.current_dir.as_ref() {
+            cmd.current_dir(dir);
+        }
+        let var = |name: &str| format!("{}_{name}", self.env_prefix);
+        cmd.args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .env(var("EVENT_KIND"), change_event.change.kind())
+            .env(var("SEQUENCE"), change_event.sequence.to_string())
+            .envs(
+                filter_key_vars(filters)
+                    .into_iter()
+                    .map(|(name, keys)| (var(&name), keys)),
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        if let ConfigChangeEvent::Initialized(summary) = &change_event.change {
+            cmd.env(
+                var("ENVIRONMENT_COUNT"),
+                summary.environment_count.to_string(),
+            );
+        }
+        debug!("executing hook command");
+        let mut child = cmd.spawn().map_err(|source| HookError::Spawn {
+            command: self.command.clone(),
+            source,
+        })?;
+        let io = |source| HookError::Io {
+            command: self.command.clone(),
+            source,
+        };
+        let run = async {
+            // the payload is small enough that the write doesn't need to race the timeout, but
+            // the command may exit without reading it
+            if let Some(mut stdin) = child.stdin.take() {
+                let payload = serde_json::to_vec(change_event).map_err(std::io::Error::from)?;
+                match stdin.write_all(&payload).await {
+                    Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+                    result => result?,
+                }
+            }
+            child.wait().await
+        };
+        let Some(timeout) = self.timeout else {
+            return self.exited(run.await.map_err(io)?);
+        };
+        match tokio::time::timeout(timeout, run).await {
+            Ok(status) => self.exited(status.map_err(io)?),
+            Err(_) => {
+                warn!(?timeout, "hook command timed out, terminating");
+                let status = terminate(&mut child).await.map_err(io)?;
+                Err(HookError::TimedOut {
+                    command: self.command.clone(),
+                    timeout,
+                    status,
+                })
+            }
+        }
+    }
+
+    fn exited(&self, status: ExitStatus) -> Result<(), HookError> {
+        debug!(%status, "hook command exited");
+        if status.success() {
+            return Ok(());
+        }
+        Err(HookError::Exit {
+            command: self.command.clone(),
+            status,
+        })
+    }
+}
+
+/// Runs [`Hook`]s in the background according to a [`Concurrency`], so slow hooks don't hold
+/// up the stream
+///
+/// Every hook that [wants](Hook::wants) a change runs for it, one after the other.
+#[derive(Debug)]
+pub struct HookRunner {
+    hooks: Arc<[Hook]>,
+    tx: mpsc::UnboundedSender<Queued>,
+    task: JoinHandle<()>,
+}
+
+impl HookRunner {
+    pub fn spawn(hooks: Vec<Hook>, concurrency: Concurrency) -> Self {
+        let hooks: Arc<[Hook]> = hooks.into();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(dispatch(hooks.clone(), concurrency, rx));
+        Self { hooks, tx, task }
+    }
+
+    /// Queues `change` if any of the hooks wants it, along with the payload filters at the
+    /// time of the change
+    pub fn send(&self, change: &SequencedChange, filters: &[PayloadFilter]) {
+        if !self.hooks.iter().any(|hook| hook.wants(&change.change)) {
+            return;
+        }
+        // the receiver lives as long as the task, which only ends once `tx` is dropped
+        let _ = self.tx.send((change.clone(), filters.into()));
+    }
+
+    /// Waits for every queued hook to finish
+    pub async fn finish(self) {
+        drop(self.tx);
+        let _ = self.task.await;
+    }
+}
+
+/// A change and the payload filters known when it happened
+type Queued = (SequencedChange, Arc<[PayloadFilter]>);
+
+async fn dispatch(
+    hooks: Arc<[Hook]>,
+    concurrency: Concurrency,
+    mut rx: mpsc::UnboundedReceiver<Queued>,
+) {
+    match concurrency {
+        Concurrency::Serialize => {
+            while let Some((change, filters)) = rx.recv().await {
+                run_logged(&hooks, &change, &filters).await;
+            }
+        }
+        Concurrency::Coalesce => {
+            // changes each hook wants and hasn't run for yet, in sequence order
+            let mut pending = vec![VecDeque::new(); hooks.len()];
+            loop {
+                if pending.iter().all(VecDeque::is_empty) {
+                    let Some(queued) = rx.recv().await else {
+                        break;
+                    };
+                    coalesce(&hooks, &mut pending, queued);
+                }
+                while let Ok(queued) = rx.try_recv() {
+                    coalesce(&hooks, &mut pending, queued);
+                }
+                // oldest change first, so hooks still run in the order the changes happened
+                let next = pending
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(_, queue)| !queue.is_empty())
+                    .min_by_key(|(_, queue)| queue[0].0.sequence);
+                let Some((index, (change, filters))) =
+                    next.and_then(|(index, queue)| Some((index, queue.pop_front()?)))
+                else {
+                    continue;
+                };
+                run_logged(std::slice::from_ref(&hooks[index]), &change, &filters).await;
+            }
+        }
+        Concurrency::Parallel(limit) => {
+            let permits = Arc::new(Semaphore::new(limit as usize));
+            while let Some((change, filters)) = rx.recv().await {
+                let Ok(permit) = permits.clone().acquire_owned().await else {
+                    break;
+                };
+                let hooks = hooks.clone();
+                tokio::spawn(async move {
+                    run_logged(&hooks, &change, &filters).await;
+                    drop(permit);
+                });
+            }
+            // every permit is back once the last hook finished
+            let _ = permits.acquire_many(limit).await;
+        }
+    }
+}
+
+/// Queues `queued` for every hook that wants it, replacing a queued change to the same
+/// environment. Changes that aren't about an environment, such as `initialized`, all run
+fn coalesce(hooks: &[Hook], pending: &mut [VecDeque<Queued>], queued: Queued) {
+    let env_id = queued.0.change.env_id();
+    for (hook, queue) in hooks.iter().zip(pending.iter_mut()) {
+        if !hook.wants(&queued.0.change) {
+            continue;
+        }
+        let previous = env_id.and_then(|env_id| {
+            queue
+                .iter()
+                .position(|(change, _)| change.change.env_id() == Some(env_id))
+        });
+        if let Some((skipped, _)) = previous.and_then(|index| queue.remove(index)) {
+            debug!(skipped = skipped.sequence, cmd = %hook.command, "coalescing hook");
+        }
+        queue.push_back(queued.clone());
+    }
+}
+
+// a failing hook shouldn't stop the watch, the next change gets a fresh attempt
+async fn run_logged(hooks: &[Hook], change: &SequencedChange, filters: &[PayloadFilter]) {
+    for hook in hooks.iter().filter(|hook| hook.wants(&change.change)) {
+        if let Err(e) = hook.run(change, filters).await {
+            warn!(error=%e, cmd = %hook.command, sequence = change.sequence, "hook command failed");
+        }
+    }
+}
+
+/// `KEY=VALUE` for [`Hook::env`]
+pub fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("{value:?} isn't a KEY=VALUE pair")),
+    }
+}
+
+/// Sends SIGTERM, then SIGKILL if the command is still running after [`KILL_GRACE_PERIOD`]
+async fn terminate(child: &mut Child) -> std::io::Result<Option<ExitStatus>> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: kill has no memory safety requirements, and the pid can't have been reused
+        // since the child hasn't been waited on
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+            if let Ok(status) = tokio::time::timeout(KILL_GRACE_PERIOD, child.wait()).await {
+                return status.map(Some);
+            }
+        }
+    }
+    child.kill().await?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use launchdarkly_autoconfig::messages::{EnvironmentConfig, EnvironmentId};
+    use launchdarkly_autoconfig::test_util::EnvironmentFixture;
+    use launchdarkly_autoconfig::AutoConfigClient;
+    use std::collections::HashMap;
+
+    async fn initialized() -> SequencedChange {
+        let client = AutoConfigClient::offline(Default::default());
+        futures::pin_mut!(client);
+        client.next_sequenced().await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn terminates_hung_commands() {
+        let change = initialized().await;
+        let hook = Hook::new("sleep".to_owned(), vec!["30".to_owned()])
+            .timeout(Some(Duration::from_millis(50)));
+        let started = std::time::Instant::now();
+        let result = hook.execute(&change, &[]).await;
+        assert!(started.elapsed() < KILL_GRACE_PERIOD);
+        assert!(
+            matches!(result, Err(HookError::TimedOut { status: Some(status), .. }) if !status.success())
+        );
+
+        let hook = hook.timeout(Some(Duration::from_secs(30)));
+        let hook = Hook {
+            command: "true".to_owned(),
+            args: vec![],
+            ..hook
+        };
+        hook.execute(&change, &[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_and_records_dead_letters() {
+        let change = initialized().await;
+        let dir = tempfile::tempdir().unwrap();
+        let dead_letter = dir.path().join("failed.ndjson");
+        let hook = Hook::new("false".to_owned(), vec![])
+            .retries(1)
+            .dead_letter(Some(dead_letter.clone()));
+        let result = hook.run(&change, &[]).await;
+        assert!(matches!(result, Err(HookError::Exit { .. })));
+        let entry: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&dead_letter).unwrap()).unwrap();
+        assert_eq!(entry["attempts"], 2);
+        assert_eq!(entry["event"]["kind"], "initialized");
+    }
+
+    #[tokio::test]
+    async fn runs_through_a_shell() {
+        let change = initialized().await;
+        let hook = Hook::new(
+            r#"test "$1" = "$LDAC_EVENT_KIND""#.to_owned(),
+            vec!["initialized".to_owned()],
+        )
+        .shell(Some("sh".to_owned()));
+        hook.execute(&change, &[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sets_up_the_environment() {
+        let change = initialized().await;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("marker"), "").unwrap();
+        let hook = Hook::new(
+            r#"test "$TARGET" = "$LD_EVENT_KIND" -a -z "$HOME" -a -f marker"#.to_owned(),
+            vec![],
+        )
+        .shell(Some("sh".to_owned()))
+        .env_prefix("LD".to_owned())
+        .current_dir(Some(dir.path().to_owned()))
+        .inherit_env(false)
+        .env(vec![("TARGET".to_owned(), "initialized".to_owned())]);
+        hook.execute(&change, &[]).await.unwrap();
+        assert_eq!(
+            parse_env_var("A=b=c"),
+            Ok(("A".to_owned(), "b=c".to_owned()))
+        );
+        assert!(parse_env_var("=b").is_err());
+    }
+
+    #[tokio::test]
+    async fn coalesces_per_environment() {
+        let environments: HashMap<EnvironmentId, EnvironmentConfig> = [
+            ("62ea8c4afac9b011945f6791", "one"),
+            ("62ea8c4afac9b011945f6792", "two"),
+        ]
+        .into_iter()
+        .map(|(env_id, env_key)| {
+            let env = EnvironmentFixture::new()
+                .env_id(env_id)
+                .env_key(env_key)
+                .build();
+            (env.env_id.clone(), env)
+        })
+        .collect();
+        let client = AutoConfigClient::offline(environments);
+        futures::pin_mut!(client);
+        let mut changes = Vec::new();
+        for _ in 0..3 {
+            changes.push(client.as_mut().next_sequenced().await.unwrap().unwrap());
+        }
+        let [initialized, first, second] = &changes[..] else {
+            unreachable!()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("sequences");
+        let hook = Hook::new(
+            r#"echo "$LDAC_SEQUENCE" >> "$1" && sleep 0.2"#.to_owned(),
+            vec![log.to_str().unwrap().to_owned()],
+        )
+        .shell(Some("sh".to_owned()))
+        .on_init(true);
+        let runner = HookRunner::spawn(vec![hook.clone()], Concurrency::Coalesce);
+        let mut first_again = first.clone();
+        first_again.sequence = 4;
+        runner.send(initialized, &[]);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // the first environment changes twice while the hook runs for `initialized`, so the
+        // hook skips its older change. The sends don't yield, so this doesn't depend on timing
+        runner.send(first, &[]);
+        runner.send(second, &[]);
+        runner.send(&first_again, &[]);
+        runner.finish().await;
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "1\n3\n4\n");
+
+        // a hook only gets the changes it wants, and only the latest one per environment
+        let hooks = [hook.clone().only("delete"), hook];
+        let mut pending = vec![VecDeque::new(); hooks.len()];
+        for change in [first, second, first] {
+            coalesce(&hooks, &mut pending, (change.clone(), Arc::from([])));
+        }
+        assert!(pending[0].is_empty());
+        let sequences: Vec<_> = pending[1]
+            .iter()
+            .map(|(change, _)| change.sequence)
+            .collect();
+        assert_eq!(sequences, [second.sequence, first.sequence]);
+    }
+
+    #[tokio::test]
+    async fn exports_filter_keys() {
+        let change = initialized().await;
+        let filters: Vec<PayloadFilter> = serde_json::from_str(
+            r#"[
+                {"projKey":"default","key":"mobile","version":1},
+                {"projKey":"default","key":"backend","version":1}
+            ]"#,
+        )
+        .unwrap();
+        let hook = Hook::new(
+            r#"test "$LDAC_DEFAULT_FILTER_KEYS" = backend,mobile"#.to_owned(),
+            vec![],
+        )
+        .shell(Some("sh".to_owned()));
+        hook.execute(&change, &filters).await.unwrap();
+        assert!(hook.execute(&change, &[]).await.is_err());
+    }
+
+    #[test]
+    fn wants_kinds() {
+        let hook = Hook::new("true".to_owned(), vec![]);
+        assert!(hook.wants(&ConfigChangeEvent::Fresh));
+        assert!(hook.clone().only("fresh").wants(&ConfigChangeEvent::Fresh));
+        assert!(!hook.clone().only("insert").wants(&ConfigChangeEvent::Fresh));
+    }
+
+    #[test]
+    fn parses_concurrency() {
+        for policy in ["serialize", "parallel(4)", "coalesce"] {
+            assert_eq!(policy.parse::<Concurrency>().unwrap().to_string(), policy);
+        }
+        assert!("parallel(0)".parse::<Concurrency>().is_err());
+        assert!("parallel".parse::<Concurrency>().is_err());
+        assert!("parallel(4294967296)".parse::<Concurrency>().is_err());
+    }
+}
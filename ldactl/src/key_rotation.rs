@@ -0,0 +1,68 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use reqwest::{header::HeaderValue, Request, StatusCode};
+use tracing::warn;
+
+use crate::{
+    credential::{LaunchDarklyCredential, RelayAutoConfigKey},
+    eventsource::{Authenticator, AuthorizeError},
+};
+
+/// A primary and a secondary Relay AutoConfig key, for rotating keys without downtime
+///
+/// Connects with the primary key until LaunchDarkly rejects it with 401, then fails over to
+/// the secondary key for good. Clones share whether the failover happened, see
+/// [`crate::AutoConfigClient::key_rotation`].
+#[derive(Debug, Clone)]
+pub struct KeyRotation {
+    primary: RelayAutoConfigKey,
+    secondary: RelayAutoConfigKey,
+    rotated: Arc<AtomicBool>,
+}
+
+impl KeyRotation {
+    pub fn new(primary: RelayAutoConfigKey, secondary: RelayAutoConfigKey) -> Self {
+        Self {
+            primary,
+            secondary,
+            rotated: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the primary key was rejected and the secondary one is used instead
+    pub fn is_rotated(&self) -> bool {
+        self.rotated.load(Ordering::Acquire)
+    }
+
+    /// The key the next connection is made with
+    pub fn active_key(&self) -> &RelayAutoConfigKey {
+        if self.is_rotated() {
+            &self.secondary
+        } else {
+            &self.primary
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for KeyRotation {
+    async fn authorize(&mut self, request: &mut Request) -> Result<(), AuthorizeError> {
+        let mut value = HeaderValue::from_str(self.active_key().expose_secret())?;
+        value.set_sensitive(true);
+        request
+            .headers_mut()
+            .insert(reqwest::header::AUTHORIZATION, value);
+        Ok(())
+    }
+
+    fn on_auth_failure(&mut self, status: StatusCode) {
+        if status == StatusCode::UNAUTHORIZED && !self.is_rotated() {
+            warn!(secondary=%self.secondary, "primary key rejected, failing over to the secondary key");
+            self.rotated.store(true, Ordering::Release);
+        }
+    }
+}
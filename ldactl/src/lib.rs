@@ -1,5 +1,58 @@
+//! Consumer for the LaunchDarkly Relay AutoConfig stream
+//!
+//! [`AutoConfigClient`] keeps an in-memory copy of every environment the Relay AutoConfig key
+//! has access to and yields a [`ConfigChangeEvent`] whenever one is added, updated or removed.
+//! This is what the `ldactl` binary is built on, so it can be embedded in other services too.
+//!
+//! ```no_run
+//! use futures::TryStreamExt;
+//! use launchdarkly_autoconfig::{credential::RelayAutoConfigKey, AutoConfigClient};
+//!
+//! # async fn run(key: RelayAutoConfigKey) -> Result<(), Box<dyn std::error::Error>> {
+//! let client = AutoConfigClient::new(key)?;
+//! futures::pin_mut!(client);
+//! while let Some(change) = client.try_next().await? {
+//!     println!("{:?}", change);
+//! }
+//! # Ok(())
+//! # }
+//! ```
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
-mod credential;
-mod eventsource;
-mod messages;
+pub mod autoconfigclient;
+mod callbacks;
+mod coalesce;
+pub mod credential;
+mod endpoint;
+pub mod eventsource;
+mod filter;
+mod key_rotation;
+mod message_event_source;
+pub mod messages;
+mod output;
+mod snapshot;
+mod stats;
+mod store;
+mod template;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod tombstones;
+
+pub use autoconfigclient::{
+    AutoConfigClient, AutoConfigClientError, ConfigChangeEvent, InitializedSummary, SequencedChange,
+    VersionConflict,
+};
+pub use endpoint::{Endpoint, EndpointParseError};
+pub use filter::{EnvironmentFilter, EnvironmentFilterError};
+pub use key_rotation::KeyRotation;
+pub use message_event_source::{
+    MessageParseError, MessageParser, ParseErrorPolicy, ProtocolVersion, UnknownParseErrorPolicy,
+};
+pub use output::{
+    filter_key_vars, OutputFormat, OutputOptions, RelayConfigFormat, UnknownOutputFormat,
+    UnknownRelayConfigFormat,
+};
+pub use snapshot::{replace_file, Snapshot, SnapshotError};
+pub use stats::AutoConfigStats;
+pub use store::EnvironmentStore;
+pub use template::{Template, TemplateError};
@@ -1,62 +1,479 @@
-mod credential;
-mod messages;
-
-mod autoconfigclient;
-mod message_event_source;
-use autoconfigclient::ConfigChangeEvent;
-use clap::Parser;
-use credential::{ClientSideId, ServerSideKey};
-use futures::FutureExt;
-use futures::{pin_mut, TryStream};
-use messages::EnvironmentConfig;
-use miette::{miette, Context, Diagnostic, IntoDiagnostic};
-use reqwest::ClientBuilder;
+mod config_file;
+mod health;
+mod hook;
+mod permissions;
+mod systemd;
+mod webhook;
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use config_file::ConfigFile;
+use futures::pin_mut;
+use health::Health;
+use hook::{Concurrency, Hook, HookRunner, DEFAULT_ENV_PREFIX};
+use launchdarkly_autoconfig::messages::{
+    EnvironmentConfig, EnvironmentId, Fingerprint, PayloadFilter, ProjectKey, SortedEnvironments,
+};
+use launchdarkly_autoconfig::{
+    filter_key_vars, AutoConfigClient, ConfigChangeEvent, Endpoint, EnvironmentFilter,
+    InitializedSummary, KeyRotation, OutputFormat, OutputOptions, ParseErrorPolicy,
+    RelayConfigFormat, SequencedChange, Snapshot, Template,
+};
+use miette::{miette, Context, IntoDiagnostic};
+use permissions::FilePermissions;
 use std::collections::HashMap;
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::string::ParseError;
-use tempfile::tempfile;
-use tokio::sync::oneshot::error::TryRecvError;
-use tokio::task::JoinHandle;
-use tokio_stream::StreamExt;
-use tracing::{debug, instrument, trace, Instrument, Span};
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
-static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
-
-mod eventsource;
-use crate::credential::RelayAutoConfigKey;
-use crate::credential::{LaunchDarklyCredential, LaunchDarklyCredentialExt};
-use crate::eventsource::{EventSource, EventSourceError};
-use crate::messages::{Expirable, Expiring};
-use std::convert::TryFrom;
-use tokio_sse_codec::{Event, Frame, SseDecodeError, SseDecoder};
-
-type ExpirableSDKKey = Expirable<ServerSideKey>;
-type ExpiringSDKKey = Expiring<ServerSideKey>;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{debug, instrument, trace, warn};
+use tracing_subscriber::EnvFilter;
+use webhook::{Webhook, WebhookSender};
+
+use launchdarkly_autoconfig::credential::{CredentialValueParser, RelayAutoConfigKey};
+
+const STATE_FILE_NAME: &str = "state.json";
 
 #[derive(Parser, Debug)]
 #[command(name = "ldactl")]
 #[command(about = "LaunchDarkly Relay AutoConfig CLI", long_about = Some("LaunchDarkly Relay AutoConfig CLI\n\nThis utility is used to fetch and parse the LaunchDarkly Relay AutoConfig stream and write it to a file or execute a command when changes are detected."))]
-struct Args {
-    #[arg(short = 'k', long, env = "LD_RELAY_AUTO_CONFIG_KEY", value_parser=RelayAutoConfigKey::try_from_str)]
-    credential: RelayAutoConfigKey,
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Follow the stream, writing files and running a hook when environments change
+    Watch(WatchArgs),
+    /// Write the current environments to a file and exit
+    Snapshot(SnapshotArgs),
+    /// Run a command for every change, with the change as JSON on stdin
+    Exec(ExecArgs),
+    /// Check that the stream, or the --offline file, can be read and exit
+    Validate,
+}
+
+#[derive(clap::Args, Debug)]
+struct GlobalArgs {
+    #[arg(long = "config", global = true, value_name = "FILE", value_hint = clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_FILE")]
+    config: Option<std::path::PathBuf>,
+    #[arg(short = 'k', long, global = true, env = "LD_RELAY_AUTO_CONFIG_KEY", value_parser = CredentialValueParser::<RelayAutoConfigKey>::new())]
+    credential: Option<RelayAutoConfigKey>,
+    #[arg(long = "secondary-credential", global = true, value_name = "CREDENTIAL", env = "LD_RELAY_AUTO_CONFIG_SECONDARY_KEY", value_parser = CredentialValueParser::<RelayAutoConfigKey>::new())]
+    secondary_credential: Option<RelayAutoConfigKey>,
     #[arg(
         short = 'u',
         long = "stream-uri",
+        alias = "endpoint",
+        global = true,
+        value_name = "ENDPOINT",
         env = "LD_STREAM_URI",
-        default_value = "https://stream.launchdarkly.com/"
+        default_value = "commercial",
+        help = "commercial, eu, federal or the base url of the stream"
+    )]
+    endpoint: Endpoint,
+
+    #[arg(long = "offline", global = true, value_name="FILE", value_hint=clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_OFFLINE_FILE")]
+    offline: Option<std::path::PathBuf>,
+
+    #[arg(
+        long = "project",
+        global = true,
+        value_name = "PROJECT_KEY",
+        env = "LD_PROJECTS",
+        value_delimiter = ','
+    )]
+    projects: Vec<ProjectKey>,
+    #[arg(
+        long = "env-key",
+        global = true,
+        value_name = "GLOB",
+        env = "LD_ENV_KEYS",
+        value_delimiter = ','
+    )]
+    env_keys: Vec<String>,
+
+    #[arg(long = "reconnect-delay-window", global = true, value_name = "DURATION", env = "LD_RECONNECT_DELAY_WINDOW", value_parser = humantime::parse_duration)]
+    reconnect_delay_window: Option<std::time::Duration>,
+    #[arg(long = "min-reconnect-interval", global = true, value_name = "DURATION", env = "LD_MIN_RECONNECT_INTERVAL", value_parser = humantime::parse_duration)]
+    min_reconnect_interval: Option<std::time::Duration>,
+    #[arg(long = "stale-after", global = true, value_name = "DURATION", env = "LD_STALE_AFTER", value_parser = humantime::parse_duration)]
+    stale_after: Option<std::time::Duration>,
+    #[arg(long = "coalesce", global = true, value_name = "DURATION", env = "LD_COALESCE_WINDOW", value_parser = humantime::parse_duration)]
+    coalesce: Option<std::time::Duration>,
+    #[arg(long = "tombstone-retention", global = true, value_name = "DURATION", env = "LD_TOMBSTONE_RETENTION", value_parser = humantime::parse_duration)]
+    tombstone_retention: Option<std::time::Duration>,
+    #[arg(
+        long = "on-parse-error",
+        global = true,
+        value_name = "POLICY",
+        env = "LD_ON_PARSE_ERROR",
+        default_value = "fail"
     )]
-    uri: reqwest::Url,
+    on_parse_error: ParseErrorPolicy,
+}
+
+#[derive(clap::Args, Debug, Default)]
+struct StartArgs {
     #[arg(short = 'o', long = "once", default_value = "false")]
     once: bool,
+
+    #[arg(long = "bootstrap-file", value_name="FILE", value_hint=clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_BOOTSTRAP_FILE", conflicts_with_all = ["offline", "state_file", "state_dir"])]
+    bootstrap_file: Option<std::path::PathBuf>,
+
+    #[arg(short = 's', long = "state-file", value_name="STATE_FILE", value_hint=clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_STATE_FILE")]
+    state_file: Option<std::path::PathBuf>,
+    #[arg(long = "state-dir", value_name="DIR", value_hint=clap::ValueHint::DirPath, env = "LD_AUTO_CONFIG_STATE_DIR", conflicts_with = "state_file")]
+    state_dir: Option<std::path::PathBuf>,
+
+    /// Serve /healthz and /status on this address, such as 0.0.0.0:8080
+    #[arg(
+        long = "health-listen",
+        value_name = "ADDR",
+        env = "LD_AUTO_CONFIG_HEALTH_LISTEN"
+    )]
+    health_listen: Option<std::net::SocketAddr>,
+}
+
+impl StartArgs {
+    /// `--state-dir` is a `--state-file` in a directory that's created if needed
+    fn resolve_state_dir(&mut self) -> Result<(), miette::Report> {
+        let Some(dir) = self.state_dir.take() else {
+            return Ok(());
+        };
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder
+            .create(&dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to create state directory {}", dir.display()))?;
+        self.state_file = Some(dir.join(STATE_FILE_NAME));
+        Ok(())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct HookArgs {
+    #[arg(long = "exec-on-init", env = "LD_AUTO_CONFIG_EXEC_ON_INIT")]
+    on_init: bool,
+    #[arg(long = "exec-timeout", value_name = "DURATION", env = "LD_AUTO_CONFIG_EXEC_TIMEOUT", value_parser = humantime::parse_duration)]
+    timeout: Option<std::time::Duration>,
+    #[arg(
+        long = "exec-retries",
+        value_name = "N",
+        env = "LD_AUTO_CONFIG_EXEC_RETRIES",
+        default_value_t = 0
+    )]
+    retries: u32,
+    /// Append events whose hook failed every attempt to this file as json lines
+    #[arg(
+        long = "exec-dead-letter",
+        value_name = "FILE",
+        env = "LD_AUTO_CONFIG_EXEC_DEAD_LETTER"
+    )]
+    dead_letter: Option<PathBuf>,
+    /// serialize, parallel(N) or coalesce
+    #[arg(
+        long = "exec-concurrency",
+        value_name = "POLICY",
+        env = "LD_AUTO_CONFIG_EXEC_CONCURRENCY",
+        default_value_t = Concurrency::Serialize
+    )]
+    concurrency: Concurrency,
+    /// Run the command with `sh -c`, or another shell with --exec-shell=bash
+    #[arg(
+        long = "exec-shell",
+        value_name = "SHELL",
+        env = "LD_AUTO_CONFIG_EXEC_SHELL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "sh"
+    )]
+    shell: Option<String>,
+
+    /// Command run only for insert events, alongside the one for every change
+    #[arg(
+        long = "on-insert",
+        value_name = "COMMAND",
+        env = "LD_AUTO_CONFIG_ON_INSERT"
+    )]
+    on_insert: Option<String>,
+    #[arg(
+        long = "on-update",
+        value_name = "COMMAND",
+        env = "LD_AUTO_CONFIG_ON_UPDATE"
+    )]
+    on_update: Option<String>,
+    #[arg(
+        long = "on-delete",
+        value_name = "COMMAND",
+        env = "LD_AUTO_CONFIG_ON_DELETE"
+    )]
+    on_delete: Option<String>,
+
+    /// Prefix of the variables describing the event, such as LDAC_EVENT_KIND
+    #[arg(
+        long = "env-prefix",
+        value_name = "PREFIX",
+        env = "LD_AUTO_CONFIG_ENV_PREFIX",
+        default_value = DEFAULT_ENV_PREFIX
+    )]
+    env_prefix: String,
+    #[arg(long = "exec-cwd", value_name = "DIR", env = "LD_AUTO_CONFIG_EXEC_CWD")]
+    cwd: Option<PathBuf>,
+    /// Only pass PATH and --exec-env variables to hooks, not ldactl's whole environment
+    #[arg(long = "exec-clear-env", env = "LD_AUTO_CONFIG_EXEC_CLEAR_ENV")]
+    clear_env: bool,
+    #[arg(
+        long = "exec-env",
+        value_name = "KEY=VALUE",
+        env = "LD_AUTO_CONFIG_EXEC_ENV",
+        value_delimiter = ',',
+        value_parser = hook::parse_env_var
+    )]
+    env: Vec<(String, String)>,
+}
+
+impl HookArgs {
+    fn hook(&self, command: String, args: Vec<String>) -> Hook {
+        Hook::new(command, args)
+            .on_init(self.on_init)
+            .timeout(self.timeout)
+            .retries(self.retries)
+            .dead_letter(self.dead_letter.clone())
+            .shell(self.shell.clone())
+            .env_prefix(self.env_prefix.clone())
+            .current_dir(self.cwd.clone())
+            .inherit_env(!self.clear_env)
+            .env(self.env.clone())
+    }
+
+    /// The hook for every change, if any, followed by the per-kind ones
+    fn hooks(&self, exec: Option<(String, Vec<String>)>) -> Vec<Hook> {
+        let per_kind = [
+            ("insert", &self.on_insert),
+            ("update", &self.on_update),
+            ("delete", &self.on_delete),
+        ]
+        .into_iter()
+        .filter_map(|(kind, command)| {
+            let command = command.clone()?;
+            Some(self.hook(command, Vec::new()).only(kind))
+        });
+        exec.map(|(command, args)| self.hook(command, args))
+            .into_iter()
+            .chain(per_kind)
+            .collect()
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct WatchArgs {
+    #[command(flatten)]
+    start: StartArgs,
+    #[command(flatten)]
+    hook: HookArgs,
+
     #[arg(short = 'f', long = "output-file", value_name="OUT_FILE", value_hint=clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_OUTPUT_FILE")]
     output_file: Option<std::path::PathBuf>,
+    #[arg(
+        long = "output-format",
+        value_name = "FORMAT",
+        env = "LD_AUTO_CONFIG_OUTPUT_FORMAT",
+        default_value = "json",
+        help = "json, yaml, toml or dotenv"
+    )]
+    output_format: OutputFormat,
+    #[arg(
+        long = "redact",
+        env = "LD_AUTO_CONFIG_REDACT",
+        requires = "output_file"
+    )]
+    redact: bool,
+    #[arg(
+        long = "compact",
+        env = "LD_AUTO_CONFIG_COMPACT",
+        requires = "output_file"
+    )]
+    compact: bool,
+    #[command(flatten)]
+    permissions: PermissionArgs,
+
+    #[arg(long = "template", value_name = "FILE", value_hint = clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_TEMPLATE", requires = "template_output")]
+    template: Option<std::path::PathBuf>,
+    #[arg(long = "template-output", value_name = "FILE", value_hint = clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_TEMPLATE_OUTPUT", requires = "template")]
+    template_output: Option<std::path::PathBuf>,
+
+    #[arg(long = "events-ndjson", env = "LD_AUTO_CONFIG_EVENTS_NDJSON")]
+    events_ndjson: bool,
 
-    #[arg(short = 'e', long = "exec")]
+    #[arg(long = "relay-config", value_name = "FILE", value_hint = clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_RELAY_CONFIG")]
+    relay_config: Option<std::path::PathBuf>,
+    #[arg(
+        long = "relay-config-format",
+        value_name = "FORMAT",
+        env = "LD_AUTO_CONFIG_RELAY_CONFIG_FORMAT",
+        default_value = "file",
+        help = "file for ld-relay --config or env for ld-relay --from-env"
+    )]
+    relay_config_format: RelayConfigFormat,
+
+    #[arg(short = 'e', long = "exec", env = "LD_AUTO_CONFIG_EXEC")]
     exec: Option<String>,
     #[arg(last = true)]
     exec_args: Option<Vec<String>>,
+
+    #[command(flatten)]
+    webhook: WebhookArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct WebhookArgs {
+    #[arg(
+        long = "webhook-url",
+        value_name = "URL",
+        env = "LD_AUTO_CONFIG_WEBHOOK_URL"
+    )]
+    webhook_url: Option<reqwest::Url>,
+    /// `Name: value`, may be repeated
+    #[arg(
+        long = "webhook-header",
+        value_name = "HEADER",
+        env = "LD_AUTO_CONFIG_WEBHOOK_HEADERS",
+        value_delimiter = ',',
+        value_parser = webhook::parse_header
+    )]
+    webhook_headers: Vec<(reqwest::header::HeaderName, reqwest::header::HeaderValue)>,
+    /// Signs bodies with HMAC-SHA256, also accepts file: and env: references
+    #[arg(
+        long = "webhook-secret",
+        value_name = "SECRET",
+        env = "LD_AUTO_CONFIG_WEBHOOK_SECRET",
+        hide_env_values = true
+    )]
+    webhook_secret: Option<String>,
+    #[arg(
+        long = "webhook-retries",
+        value_name = "N",
+        env = "LD_AUTO_CONFIG_WEBHOOK_RETRIES",
+        default_value_t = 3
+    )]
+    webhook_retries: u32,
+    #[arg(long = "webhook-timeout", value_name = "DURATION", env = "LD_AUTO_CONFIG_WEBHOOK_TIMEOUT", value_parser = humantime::parse_duration, default_value = "10s")]
+    webhook_timeout: std::time::Duration,
+}
+
+impl WebhookArgs {
+    fn webhook(self) -> Result<Option<Webhook>, miette::Report> {
+        let Some(url) = self.webhook_url else {
+            return Ok(None);
+        };
+        let secret = self
+            .webhook_secret
+            .map(|secret| launchdarkly_autoconfig::credential::resolve(&secret))
+            .transpose()
+            .into_diagnostic()
+            .wrap_err("failed to read --webhook-secret")?;
+        Ok(Some(
+            Webhook::new(url)
+                .headers(self.webhook_headers)
+                .secret(secret)
+                .retries(self.webhook_retries)
+                .timeout(self.webhook_timeout),
+        ))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct PermissionArgs {
+    #[arg(long = "output-mode", value_name = "MODE", env = "LD_AUTO_CONFIG_OUTPUT_MODE", value_parser = permissions::parse_mode)]
+    mode: Option<u32>,
+    #[arg(long = "output-owner", value_name = "USER", env = "LD_AUTO_CONFIG_OUTPUT_OWNER", value_parser = permissions::parse_user)]
+    owner: Option<u32>,
+    #[arg(long = "output-group", value_name = "GROUP", env = "LD_AUTO_CONFIG_OUTPUT_GROUP", value_parser = permissions::parse_group)]
+    group: Option<u32>,
+}
+
+impl PermissionArgs {
+    fn file_permissions(&self) -> FilePermissions {
+        FilePermissions {
+            mode: self.mode,
+            uid: self.owner,
+            gid: self.group,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SnapshotArgs {
+    #[arg(short = 'f', long = "output-file", value_name="OUT_FILE", value_hint=clap::ValueHint::FilePath, env = "LD_AUTO_CONFIG_OUTPUT_FILE")]
+    output_file: std::path::PathBuf,
+    #[arg(
+        long = "output-format",
+        value_name = "FORMAT",
+        env = "LD_AUTO_CONFIG_OUTPUT_FORMAT",
+        default_value = "json",
+        help = "json, yaml, toml or dotenv"
+    )]
+    output_format: OutputFormat,
+    #[arg(long = "redact", env = "LD_AUTO_CONFIG_REDACT")]
+    redact: bool,
+    #[arg(long = "compact", env = "LD_AUTO_CONFIG_COMPACT")]
+    compact: bool,
+    #[command(flatten)]
+    permissions: PermissionArgs,
 }
+
+#[derive(clap::Args, Debug)]
+struct ExecArgs {
+    #[command(flatten)]
+    start: StartArgs,
+    #[command(flatten)]
+    hook: HookArgs,
+
+    #[arg(
+        value_name = "COMMAND",
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    command: Vec<String>,
+}
+
+/// Where `watch` and `exec` send changes
+#[derive(Debug, Default)]
+struct Sinks {
+    output_file: Option<PathBuf>,
+    output_format: OutputFormat,
+    output_options: OutputOptions,
+    file_permissions: FilePermissions,
+    // fingerprint of the environments and the filter keys the output files were last written with
+    written: Option<(Fingerprint, Vec<(String, String)>)>,
+    template: Option<(Template, PathBuf)>,
+    relay_config: Option<(RelayConfigFormat, PathBuf)>,
+    events_ndjson: bool,
+    state_file: Option<PathBuf>,
+    hooks: Vec<Hook>,
+    hook_concurrency: Concurrency,
+    webhook: Option<Webhook>,
+    health_listen: Option<std::net::SocketAddr>,
+}
+
+impl Sinks {
+    fn writes_files(&self) -> bool {
+        self.output_file.is_some()
+            || self.template.is_some()
+            || self.relay_config.is_some()
+            || self.state_file.is_some()
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), miette::Report> {
     miette::set_hook(Box::new(|_| {
@@ -70,49 +487,303 @@ async fn main() -> Result<(), miette::Report> {
         )
     }))
     .unwrap();
+    let command = Cli::command();
+    // the config file's settings become defaults for the other flags, so --config has to be
+    // known before the command line is parsed for real
+    let config_path = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches()
+        .ok()
+        .and_then(|matches| matches.get_one::<PathBuf>("config").cloned());
+    let config = ConfigFile::find(config_path.as_deref())?;
+    let command = match config.as_ref() {
+        Some(config) => config.apply(command)?,
+        None => command,
+    };
+    let filter = match config.as_ref().and_then(ConfigFile::log) {
+        Some(log) if std::env::var_os(EnvFilter::DEFAULT_ENV).is_none() => EnvFilter::new(log),
+        _ => EnvFilter::from_default_env(),
+    };
     tracing_subscriber::fmt()
         .pretty()
-        .with_env_filter(EnvFilter::from_default_env())
+        .with_env_filter(filter)
+        // keep stdout for --events-ndjson and friends
+        .with_writer(std::io::stderr)
         .init();
-    let args = Args::parse();
-    let key = args.credential;
-    let client = ClientBuilder::new().build().map_err(|e| miette!(e))?;
-    let mut url = args.uri;
-    url.path_segments_mut().unwrap().push("relay_auto_config");
+    if let Some(config) = config {
+        debug!(path=?config.path, "loaded config file");
+    }
+    let cli = Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit());
+    match cli.command {
+        Command::Watch(mut args) => {
+            if args.events_ndjson && args.output_file.as_deref() == Some(Path::new("-")) {
+                return Err(miette!(
+                    "--events-ndjson and --output-file - can't both write to stdout"
+                ));
+            }
+            args.start.resolve_state_dir()?;
+            let client = build_client(&cli.global, &args.start)?;
+            let sinks = Sinks {
+                output_file: args.output_file,
+                output_format: args.output_format,
+                output_options: OutputOptions {
+                    redact: args.redact,
+                    compact: args.compact,
+                },
+                file_permissions: args.permissions.file_permissions(),
+                written: None,
+                template: match (args.template, args.template_output) {
+                    (Some(template), Some(output)) => Some((load_template(&template)?, output)),
+                    _ => None,
+                },
+                relay_config: args
+                    .relay_config
+                    .map(|path| (args.relay_config_format, path)),
+                events_ndjson: args.events_ndjson,
+                state_file: args.start.state_file,
+                hooks: args.hook.hooks(
+                    args.exec
+                        .map(|cmd| (cmd, args.exec_args.unwrap_or_default())),
+                ),
+                hook_concurrency: args.hook.concurrency,
+                webhook: args.webhook.webhook()?,
+                health_listen: args.start.health_listen,
+            };
+            watch(client, args.start.once, sinks).await
+        }
+        Command::Exec(mut args) => {
+            args.start.resolve_state_dir()?;
+            let client = build_client(&cli.global, &args.start)?;
+            let mut command = args.command.into_iter();
+            let sinks = Sinks {
+                state_file: args.start.state_file,
+                hooks: args
+                    .hook
+                    .hooks(command.next().map(|cmd| (cmd, command.collect()))),
+                hook_concurrency: args.hook.concurrency,
+                health_listen: args.start.health_listen,
+                ..Default::default()
+            };
+            watch(client, args.start.once, sinks).await
+        }
+        Command::Snapshot(args) => {
+            let client = build_client(&cli.global, &StartArgs::default())?;
+            pin_mut!(client);
+            initialize(client.as_mut()).await?;
+            let options = OutputOptions {
+                redact: args.redact,
+                compact: args.compact,
+            };
+            write_outfile(
+                args.output_file.clone(),
+                client.environments(),
+                client.payload_filters().cloned().collect(),
+                args.output_format,
+                options,
+                args.permissions.file_permissions(),
+            )
+            .await?;
+            debug!(path=?args.output_file, "wrote environments to file");
+            Ok(())
+        }
+        Command::Validate => {
+            let client = build_client(&cli.global, &StartArgs::default())?;
+            pin_mut!(client);
+            let summary = initialize(client.as_mut()).await?;
+            println!(
+                "ok: {} environments in {} projects",
+                summary.environment_count,
+                summary.project_keys.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+fn build_client(
+    global: &GlobalArgs,
+    start: &StartArgs,
+) -> Result<AutoConfigClient, miette::Report> {
+    let client = match (global.credential.as_ref(), global.offline.as_ref()) {
+        (_, Some(path)) => AutoConfigClient::from_file(path)?,
+        (Some(key), None) => {
+            let rotation = global
+                .secondary_credential
+                .clone()
+                .map(|secondary| KeyRotation::new(key.clone(), secondary));
+            let builder = match rotation.as_ref() {
+                Some(rotation) => {
+                    AutoConfigClient::rotating_event_source_builder(rotation, &global.endpoint)
+                }
+                None => AutoConfigClient::event_source_builder(key, &global.endpoint),
+            };
+            let client = match start
+                .state_file
+                .as_deref()
+                .map(Snapshot::load)
+                .transpose()?
+                .flatten()
+            {
+                Some(snapshot) => AutoConfigClient::restore_from_builder(builder, snapshot)
+                    .wrap_err("failed to build the event source")?,
+                None => AutoConfigClient::from_event_source(
+                    builder
+                        .build()
+                        .wrap_err("failed to build the event source")?,
+                ),
+            };
+            match rotation {
+                Some(rotation) => client.key_rotation(rotation),
+                None => client,
+            }
+        }
+        (None, None) => return Err(miette!("--credential is required unless running --offline")),
+    };
+    let client = match start.bootstrap_file.as_ref() {
+        Some(path) => client.bootstrap_from_file(path)?,
+        None => client,
+    };
+    let mut filter = EnvironmentFilter::new();
+    if !global.projects.is_empty() {
+        filter = filter.project_keys(global.projects.iter().cloned());
+    }
+    if !global.env_keys.is_empty() {
+        filter = filter.env_key_globs(&global.env_keys)?;
+    }
+    let mut client = client.filter(filter);
+    if let Some(window) = global.reconnect_delay_window {
+        client = client.reconnect_delay_window(window);
+    }
+    if let Some(interval) = global.min_reconnect_interval {
+        client = client.min_reconnect_interval(interval);
+    }
+    if let Some(threshold) = global.stale_after {
+        client = client.stale_after(threshold);
+    }
+    if let Some(retention) = global.tombstone_retention {
+        client = client.tombstone_retention(retention);
+    }
+    client = client.on_parse_error(global.on_parse_error);
+    if let Some(window) = global.coalesce {
+        client = client.coalesce(window);
+    }
+    Ok(client)
+}
+
+/// Reads the stream until the initial set of environments is known
+async fn initialize(
+    mut client: Pin<&mut AutoConfigClient>,
+) -> Result<InitializedSummary, miette::Report> {
+    while let Some(change) = client.as_mut().next_sequenced().await {
+        if let ConfigChangeEvent::Initialized(summary) = change?.change {
+            debug!(environment_count=summary.environment_count, project_keys=?summary.project_keys, connect_latency=?summary.connect_latency, stats=?client.stats(), "initialized");
+            return Ok(summary);
+        }
+    }
+    Err(miette!("autoconfig stream ended before it was initialized"))
+}
 
-    let client = autoconfigclient::AutoConfigClient::new(key);
+async fn watch(
+    client: AutoConfigClient,
+    once: bool,
+    mut sinks: Sinks,
+) -> Result<(), miette::Report> {
     pin_mut!(client);
 
     let (debounce_tx, debounce_rx) = tokio::sync::mpsc::channel(1);
     let (flush_tx, mut flush_rx) = tokio::sync::mpsc::channel(1);
-    let file = tokio::spawn(file_write_debouncer(debounce_rx, flush_tx));
+    tokio::spawn(file_write_debouncer(debounce_rx, flush_tx));
+    let webhook = sinks
+        .webhook
+        .take()
+        .map(|webhook| WebhookSender::spawn(webhook, reqwest::Client::new()));
+    let hooks = (!sinks.hooks.is_empty())
+        .then(|| HookRunner::spawn(std::mem::take(&mut sinks.hooks), sinks.hook_concurrency));
+    let health = match sinks.health_listen {
+        Some(addr) => {
+            let (tx, rx) = tokio::sync::watch::channel(Health::default());
+            health::serve(addr, rx)?;
+            Some(tx)
+        }
+        None => None,
+    };
+    let mut refresh_health = tokio::time::interval(health::REFRESH_INTERVAL);
+    let systemd = systemd::Notifier::from_env();
+    let watchdog_interval = systemd
+        .as_ref()
+        .and_then(systemd::Notifier::watchdog_interval);
+    let mut watchdog = tokio::time::interval(watchdog_interval.unwrap_or(health::REFRESH_INTERVAL));
 
     loop {
         tokio::select! {
 
             _ = flush_rx.recv() => {
-                if let Some(path) = args.output_file.as_ref() {
-                    write_outfile(path.clone(), client.environments().clone()).await?;
-                    debug!(?path, "wrote environments to file");
+                write_files(&client, &mut sinks).await?;
+            }
+            _ = watchdog.tick(), if watchdog_interval.is_some() => {
+                let mut liveness = Health::default();
+                liveness.refresh(&client);
+                // a wedged stream stops the keep-alives so systemd restarts us
+                if let Some(systemd) = systemd.as_ref().filter(|_| liveness.is_live()) {
+                    systemd.watchdog();
                 }
             }
-            result = client.try_next() => {
+            _ = refresh_health.tick(), if health.is_some() => {
+                if let Some(health) = health.as_ref() {
+                    health.send_modify(|health| health.refresh(&client));
+                }
+            }
+            result = client.as_mut().next_sequenced() => {
+                let result = result.transpose();
+                if let Err(e) = &result {
+                    warn!(error=%e, stats=?client.stats(), "autoconfig stream failed");
+                }
                 if let Some(change) = result? {
-                    if args.output_file.is_some() {
+                    if let Some(health) = health.as_ref() {
+                        health.send_modify(|health| health.changed(&client, &change));
+                    }
+                    if let Some(systemd) = systemd.as_ref() {
+                        let status = systemd_status(&client);
+                        match change.change {
+                            ConfigChangeEvent::Initialized(_) => systemd.ready(&status),
+                            _ => systemd.status(&status),
+                        }
+                    }
+                    if sinks.writes_files() {
                         debounce_tx.send(()).await.into_diagnostic()?;
                     }
-                    match change {
-                        ConfigChangeEvent::Initialized => {
-                            debug!(environment_count=client.environments().len(), "initialized");
-                            if args.once {
+                    if sinks.events_ndjson {
+                        print_ndjson(&change)?;
+                    }
+                    if let Some(webhook) = webhook.as_ref() {
+                        webhook.send(&change);
+                    }
+                    match &change.change {
+                        ConfigChangeEvent::Initialized(summary) => {
+                            debug!(environment_count=summary.environment_count, project_keys=?summary.project_keys, connect_latency=?summary.connect_latency, stats=?client.stats(), "initialized");
+                            if let Some(hooks) = hooks.as_ref() {
+                                let filters: Vec<PayloadFilter> = client.payload_filters().cloned().collect();
+                                hooks.send(&change, &filters);
+                            }
+                            if once {
+                                // the debouncer won't get to it
+                                if sinks.writes_files() {
+                                    write_files(&client, &mut sinks).await?;
+                                }
                                 break;
                             }
 
                         },
                         _ => {
-                            if let Some(cmd) = args.exec.as_ref() {
-                                let args = args.exec_args.clone().unwrap_or_default();
-                                let _ = execute_hook(cmd.clone(), args, change).await;
+                            if let ConfigChangeEvent::Update { current, .. } = &change.change {
+                                if let Some(expiring) = current.sdk_key.expiring().filter(|expiring| !expiring.is_expired(SystemTime::now())) {
+                                    debug!(proj_key=%current.proj_key, env_key=%current.env_key, expires_at=%humantime::format_rfc3339_seconds(expiring.expires_at()), "sdk key rotation pending");
+                                }
+                            }
+                            if let Some(hooks) = hooks.as_ref() {
+                                let filters: Vec<PayloadFilter> = client.payload_filters().cloned().collect();
+                                hooks.send(&change, &filters);
                             }
                         }
                     }
@@ -121,57 +792,158 @@ async fn main() -> Result<(), miette::Report> {
             }
         }
     }
+    if let Some(hooks) = hooks {
+        hooks.finish().await;
+    }
+    if let Some(webhook) = webhook {
+        webhook.finish().await;
+    }
+    if let Some(systemd) = systemd {
+        systemd.stopping();
+    }
+    Ok(())
+}
+
+fn systemd_status(client: &AutoConfigClient) -> String {
+    let count = client.environments().len();
+    if client.is_stale() {
+        format!("stream is stale, {count} environments may be out of date")
+    } else {
+        format!("watching {count} environments")
+    }
+}
+
+async fn write_files(client: &AutoConfigClient, sinks: &mut Sinks) -> Result<(), miette::Report> {
+    if let Some(path) = sinks.state_file.as_ref() {
+        client.snapshot().save(path)?;
+    }
+    // reconnects and version bumps don't change what's written, skip touching the files so
+    // anything watching them isn't woken up for nothing
+    let fingerprint = SortedEnvironments(&client.environments()).fingerprint();
+    let filters: Vec<PayloadFilter> = client.payload_filters().cloned().collect();
+    let written = Some((fingerprint, filter_key_vars(&filters)));
+    if sinks.written == written {
+        trace!(%fingerprint, "environments unchanged, skipping output files");
+        return Ok(());
+    }
+    if let Some(path) = sinks.output_file.as_ref() {
+        write_outfile(
+            path.clone(),
+            client.environments(),
+            filters,
+            sinks.output_format,
+            sinks.output_options,
+            sinks.file_permissions,
+        )
+        .await?;
+        debug!(?path, "wrote environments to file");
+    }
+    if let Some((template, path)) = sinks.template.as_ref() {
+        write_template(
+            path.clone(),
+            template,
+            client.environments(),
+            sinks.file_permissions,
+        )
+        .await?;
+        debug!(?path, "rendered template");
+    }
+    if let Some((format, path)) = sinks.relay_config.as_ref() {
+        write_relay_config(
+            path.clone(),
+            *format,
+            client.environments(),
+            sinks.file_permissions,
+        )
+        .await?;
+        debug!(?path, "wrote relay config");
+    }
+    sinks.written = written;
     Ok(())
 }
 
-#[instrument]
-fn execute_hook(
-    cmd: String,
-    args: Vec<String>,
-    change_event: ConfigChangeEvent,
-) -> JoinHandle<Result<(), miette::Report>> {
-    // TODO: Use tokio to spawn instead
-    // we should also wrap the output in tracing
-    let span = Span::current();
-    tokio::task::spawn_blocking(move || -> Result<(), miette::Report> {
-        let _span = span.enter();
-        let mut cmd = std::process::Command::new(cmd);
-        cmd.args(args);
-        cmd.stdin(std::process::Stdio::piped());
-        cmd.stdout(std::process::Stdio::inherit());
-        cmd.stderr(std::process::Stdio::inherit());
-        debug!("executing hook command");
-        let mut child = cmd.spawn().into_diagnostic()?;
-        {
-            let stdin = child
-                .stdin
-                .as_mut()
-                .ok_or_else(|| miette!("failed to write to hook command stdin"))?;
-            let mut writer = BufWriter::new(stdin);
-            serde_json::to_writer(&mut writer, &change_event).into_diagnostic()?;
-            writer.flush().into_diagnostic()?;
+/// One change per line, flushed right away so consumers see changes as they happen
+fn print_ndjson(change: &SequencedChange) -> Result<(), miette::Report> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, change).into_diagnostic()?;
+    writeln!(stdout).into_diagnostic()?;
+    stdout.flush().into_diagnostic()
+}
+
+#[instrument(target="file_output", skip(environments, filters), fields(environment_count = environments.len()))]
+async fn write_outfile(
+    path: PathBuf,
+    environments: Arc<HashMap<EnvironmentId, EnvironmentConfig>>,
+    filters: Vec<PayloadFilter>,
+    format: OutputFormat,
+    options: OutputOptions,
+    permissions: FilePermissions,
+) -> Result<(), miette::Report> {
+    if path == Path::new("-") {
+        let mut stdout = BufWriter::new(std::io::stdout().lock());
+        format
+            .write(&mut stdout, &environments, &filters, options)
+            .map_err(|e| miette!(e))?;
+        if format == OutputFormat::Json && !options.compact {
+            writeln!(stdout).map_err(|e| miette!(e))?;
         }
-        child
-            .wait()
-            .into_diagnostic()
-            .context("hook command failed")?;
-        Ok(())
+        return stdout.flush().map_err(|e| miette!(e));
+    }
+    replace_file(&path, permissions, |writer| {
+        format.write(writer, &environments, &filters, options)
     })
 }
 
-#[instrument(target="file_output", skip(environments), fields(environment_count = environments.len()))]
-async fn write_outfile(
+/// [`launchdarkly_autoconfig::replace_file`] with `permissions` applied to the new file
+fn replace_file(
+    path: &Path,
+    permissions: FilePermissions,
+    write: impl FnOnce(&mut BufWriter<&mut std::fs::File>) -> std::io::Result<()>,
+) -> Result<(), miette::Report> {
+    launchdarkly_autoconfig::replace_file(path, |file| {
+        let mut writer = BufWriter::new(&mut *file);
+        write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        permissions.apply(file)
+    })
+    .into_diagnostic()
+    .wrap_err_with(|| format!("failed to write {}", path.display()))
+}
+fn load_template(path: &Path) -> Result<Template, miette::Report> {
+    let source = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to read template {}", path.display()))?;
+    Template::parse(&source)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to parse template {}", path.display()))
+}
+
+#[instrument(target="file_output", skip(template, environments), fields(environment_count = environments.len()))]
+async fn write_template(
     path: PathBuf,
-    environments: HashMap<ClientSideId, EnvironmentConfig>,
+    template: &Template,
+    environments: Arc<HashMap<EnvironmentId, EnvironmentConfig>>,
+    permissions: FilePermissions,
 ) -> Result<(), miette::Report> {
-    let mut tmp = tempfile::NamedTempFile::new().map_err(|e| miette!(e))?;
-    let writer = BufWriter::new(tmp.as_file_mut());
-    serde_json::to_writer_pretty(writer, &environments).map_err(|e| miette!(e))?;
-    tmp.flush().map_err(|e| miette!(e))?;
+    let rendered = template.render(&environments).into_diagnostic()?;
+    replace_file(&path, permissions, |writer| {
+        writer.write_all(rendered.as_bytes())
+    })
+}
 
-    std::fs::rename(tmp.path(), path).map_err(|e| miette!(e))?;
-    Ok(())
+#[instrument(target="file_output", skip(environments), fields(environment_count = environments.len()))]
+async fn write_relay_config(
+    path: PathBuf,
+    format: RelayConfigFormat,
+    environments: Arc<HashMap<EnvironmentId, EnvironmentConfig>>,
+    permissions: FilePermissions,
+) -> Result<(), miette::Report> {
+    replace_file(&path, permissions, |writer| {
+        format.write(writer, &environments)
+    })
 }
+
 #[instrument(target = "file_output", skip(rx, tx))]
 async fn file_write_debouncer(
     mut rx: tokio::sync::mpsc::Receiver<()>,
@@ -190,17 +962,32 @@ async fn file_write_debouncer(
             Ok(None) => {
                 if needs_flush {
                     trace!("file output flush requested");
-                    tx.send(()).await.unwrap();
+                    if tx.send(()).await.is_err() {
+                        return;
+                    }
                     needs_flush = false;
                 }
             }
             Err(_) => {
                 if needs_flush {
                     trace!("file output flush requested");
-                    tx.send(()).await.unwrap();
+                    if tx.send(()).await.is_err() {
+                        return;
+                    }
                     needs_flush = false;
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_is_valid() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
@@ -1,10 +1,14 @@
-use std::ops::Deref;
+use std::{borrow::Cow, fmt, str::FromStr};
 
-use crate::messages::{DeleteEvent, Message, PatchEvent, PutEvent};
+use crate::messages::{
+    DeleteEvent, EnvironmentConfig, Message, PatchEvent, PayloadFilter, PutEvent,
+    FILTERS_PATH_PREFIX,
+};
 use miette::Diagnostic;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
-use tokio_sse_codec::{BytesStr, Event};
-use tracing::{error_span, instrument, Instrument};
+use tokio_sse_codec::{BytesStr, Event, Frame};
+use tracing::instrument;
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum MessageParseError {
@@ -12,32 +16,375 @@ pub enum MessageParseError {
     UnknownEventType(Event<BytesStr>),
     #[error("error parsing {0} event: {1}")]
     JSONError(&'static str, #[source] serde_json::Error),
+    /// The event is valid JSON, but `location`, such as `data.environments.<env id>`, isn't
+    /// what was expected
+    #[error("error parsing {event} event at {location}: {source}")]
+    InvalidPayload {
+        event: &'static str,
+        location: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("{event} event uses protocol version {version}, only {min} to {max} are supported")]
+    #[diagnostic(help("upgrade ldactl or widen the accepted protocol versions"))]
+    UnsupportedProtocolVersion {
+        event: &'static str,
+        version: ProtocolVersion,
+        min: ProtocolVersion,
+        max: ProtocolVersion,
+    },
 }
 
-const PUT_EVENT: &'static str = "put";
-const PATCH_EVENT: &'static str = "patch";
-const DELETE_EVENT: &'static str = "delete";
-const RECONNECT_EVENT: &'static str = "reconnect";
+/// Version of the autoconfig event schema, read from the `protocolVersion` field of an event
+///
+/// The stream doesn't send one yet, events without it are [`ProtocolVersion::V1`]. See
+/// [`MessageParser`] for limiting which versions are accepted.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct ProtocolVersion(u32);
+
+impl ProtocolVersion {
+    /// The schema as of the first release, which every event without a version uses
+    pub const V1: ProtocolVersion = ProtocolVersion(1);
+    /// Newest version this release understands
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V1;
+
+    pub const fn new(version: u32) -> Self {
+        Self(version)
+    }
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses stream events into [`Message`]s, rejecting protocol versions outside a range
+///
+/// The default accepts [`ProtocolVersion::V1`] to [`ProtocolVersion::LATEST`], which is what
+/// `Message::try_from` uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageParser {
+    min_protocol_version: ProtocolVersion,
+    max_protocol_version: ProtocolVersion,
+}
+
+impl Default for MessageParser {
+    fn default() -> Self {
+        Self {
+            min_protocol_version: ProtocolVersion::V1,
+            max_protocol_version: ProtocolVersion::LATEST,
+        }
+    }
+}
+
+impl MessageParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Oldest protocol version to accept
+    pub fn min_protocol_version(mut self, version: ProtocolVersion) -> Self {
+        self.min_protocol_version = version;
+        self
+    }
+
+    /// Newest protocol version to accept, raise it to try out a schema this release doesn't
+    /// know about yet
+    pub fn max_protocol_version(mut self, version: ProtocolVersion) -> Self {
+        self.max_protocol_version = version;
+        self
+    }
+
+    #[instrument(level = "debug", skip(self), fields(event_name=%event.name))]
+    pub fn parse(&self, event: Event<BytesStr>) -> Result<Message, MessageParseError> {
+        // names are matched ignoring case and surrounding whitespace
+        let name = match [PUT_EVENT, PATCH_EVENT, DELETE_EVENT, RECONNECT_EVENT]
+            .into_iter()
+            .find(|name| event.name.trim().eq_ignore_ascii_case(name))
+        {
+            Some(RECONNECT_EVENT) => return Ok(Message::Reconnect),
+            Some(name) => name,
+            None => return Err(MessageParseError::UnknownEventType(event)),
+        };
+        let envelope = serde_json::from_str::<Envelope>(&event.data)
+            .map_err(|e| MessageParseError::JSONError(name, e))?;
+        let version = envelope.protocol_version.unwrap_or(ProtocolVersion::V1);
+        if version < self.min_protocol_version || version > self.max_protocol_version {
+            return Err(MessageParseError::UnsupportedProtocolVersion {
+                event: name,
+                version,
+                min: self.min_protocol_version,
+                max: self.max_protocol_version,
+            });
+        }
+        let is_filter = envelope
+            .path
+            .is_some_and(|path| path.starts_with(FILTERS_PATH_PREFIX));
+        let data = &event.data;
+        match (name, is_filter) {
+            (PUT_EVENT, _) => parse(name, data).map(Message::Put),
+            (PATCH_EVENT, true) => parse(name, data).map(Message::PatchFilter),
+            (PATCH_EVENT, false) => parse(name, data).map(Message::Patch),
+            (DELETE_EVENT, true) => parse(name, data).map(Message::DeleteFilter),
+            _ => parse(name, data).map(Message::Delete),
+        }
+    }
+}
+
+/// The fields every event but `reconnect` has in common
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    path: Option<String>,
+    protocol_version: Option<ProtocolVersion>,
+}
+
+/// Parses `data`, pointing at the environment or filter that didn't parse if there is one
+fn parse<T: DeserializeOwned>(event: &'static str, data: &str) -> Result<T, MessageParseError> {
+    serde_json::from_str(data).map_err(|e| {
+        let value: serde_json::Value = match serde_json::from_str(data) {
+            Ok(value) => value,
+            Err(_) => return MessageParseError::JSONError(event, e),
+        };
+        let data = &value["data"];
+        let located = match event {
+            PUT_EVENT => {
+                find_invalid::<EnvironmentConfig>("data.environments", &data["environments"])
+                    .or_else(|| find_invalid::<PayloadFilter>("data.filters", &data["filters"]))
+            }
+            PATCH_EVENT if data.get("key").is_some() => {
+                invalid::<PayloadFilter>("data".to_owned(), data)
+            }
+            PATCH_EVENT => invalid::<EnvironmentConfig>("data".to_owned(), data),
+            _ => None,
+        };
+        match located {
+            Some((location, source)) => MessageParseError::InvalidPayload {
+                event,
+                location,
+                source,
+            },
+            None => MessageParseError::JSONError(event, e),
+        }
+    })
+}
+
+fn invalid<T: DeserializeOwned>(
+    location: String,
+    value: &serde_json::Value,
+) -> Option<(String, serde_json::Error)> {
+    T::deserialize(value).err().map(|e| (location, e))
+}
+
+/// First entry of the `entries` map that doesn't parse as a `T`
+fn find_invalid<T: DeserializeOwned>(
+    location: &str,
+    entries: &serde_json::Value,
+) -> Option<(String, serde_json::Error)> {
+    entries
+        .as_object()?
+        .iter()
+        .find_map(|(id, value)| invalid::<T>(format!("{}.{}", location, id), value))
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("unknown parse error policy {0:?}")]
+#[diagnostic(help("use fail, skip-and-log or skip"))]
+pub struct UnknownParseErrorPolicy(String);
+
+/// What [`crate::AutoConfigClient`] does with events it can't parse, see
+/// [`crate::AutoConfigClient::on_parse_error`]
+///
+/// Every policy counts the event in [`crate::AutoConfigStats::parse_errors`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseErrorPolicy {
+    /// Yield [`crate::AutoConfigClientError::EventParseError`]
+    #[default]
+    Fail,
+    /// Log a warning and carry on with the next event
+    SkipAndLog,
+    /// Carry on with the next event, the error is only visible in the stats
+    Skip,
+}
+
+impl FromStr for ParseErrorPolicy {
+    type Err = UnknownParseErrorPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fail" => Ok(ParseErrorPolicy::Fail),
+            "skip-and-log" => Ok(ParseErrorPolicy::SkipAndLog),
+            "skip" => Ok(ParseErrorPolicy::Skip),
+            _ => Err(UnknownParseErrorPolicy(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseErrorPolicy::Fail => "fail",
+            ParseErrorPolicy::SkipAndLog => "skip-and-log",
+            ParseErrorPolicy::Skip => "skip",
+        })
+    }
+}
+
+const PUT_EVENT: &str = "put";
+const PATCH_EVENT: &str = "patch";
+const DELETE_EVENT: &str = "delete";
+const RECONNECT_EVENT: &str = "reconnect";
 
 impl TryFrom<Event<BytesStr>> for Message {
     type Error = MessageParseError;
-    #[instrument(level = "debug", fields(event_name=%event.name))]
     fn try_from(event: Event<BytesStr>) -> Result<Self, Self::Error> {
-        match event.name.deref() {
-            "put" => Ok(Message::Put(
-                serde_json::from_str(&event.data)
-                    .map_err(|e| MessageParseError::JSONError(PUT_EVENT, e))?,
-            )),
-            "patch" => Ok(Message::Patch(
-                serde_json::from_str(&event.data)
-                    .map_err(|e| MessageParseError::JSONError(PATCH_EVENT, e))?,
-            )),
-            "delete" => Ok(Message::Delete(
-                serde_json::from_str(&event.data)
-                    .map_err(|e| MessageParseError::JSONError(DELETE_EVENT, e))?,
-            )),
-            "reconnect" => Ok(Message::Reconnect),
-            _ => Err(MessageParseError::UnknownEventType(event)),
+        MessageParser::default().parse(event)
+    }
+}
+
+/// Event named `name` with `data` serialized to JSON
+fn json_frame(name: &'static str, data: &impl serde::Serialize) -> Frame<String> {
+    Frame::Event(Event {
+        id: None,
+        name: Cow::Borrowed(name),
+        // the messages only have string keys, serializing them can't fail
+        data: serde_json::to_string(data).expect("messages serialize to json"),
+    })
+}
+
+impl From<&PutEvent> for Frame<String> {
+    fn from(put: &PutEvent) -> Self {
+        json_frame(PUT_EVENT, put)
+    }
+}
+
+impl From<&PatchEvent> for Frame<String> {
+    fn from(patch: &PatchEvent) -> Self {
+        json_frame(PATCH_EVENT, patch)
+    }
+}
+
+impl From<&DeleteEvent> for Frame<String> {
+    fn from(delete: &DeleteEvent) -> Self {
+        json_frame(DELETE_EVENT, delete)
+    }
+}
+
+/// Encodes a message the way the stream sends it, so it parses back into the same message
+impl From<&Message> for Frame<String> {
+    fn from(msg: &Message) -> Self {
+        match msg {
+            Message::Put(put) => put.into(),
+            Message::Patch(patch) => patch.into(),
+            Message::Delete(delete) => delete.into(),
+            Message::PatchFilter(patch) => json_frame(PATCH_EVENT, patch),
+            Message::DeleteFilter(delete) => json_frame(DELETE_EVENT, delete),
+            // events with empty data are never dispatched
+            Message::Reconnect => json_frame(RECONNECT_EVENT, &serde_json::json!({})),
+        }
+    }
+}
+
+impl From<Message> for Frame<String> {
+    fn from(msg: Message) -> Self {
+        (&msg).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::EnvironmentFixture;
+    use bytes::BytesMut;
+    use tokio_sse_codec::{SseDecoder, SseEncoder};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    #[test]
+    fn encoded_messages_parse_back() {
+        let messages: Vec<Message> = vec![
+            Message::Put(
+                serde_json::from_str(&format!(
+                    r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+                    EnvironmentFixture::new().version(6).to_json()
+                ))
+                .unwrap(),
+            ),
+            Message::Delete(
+                serde_json::from_str(
+                    r#"{"path":"/environments/62ea8c4afac9b011945f6791","version":7}"#,
+                )
+                .unwrap(),
+            ),
+            Message::DeleteFilter(
+                serde_json::from_str(r#"{"path":"/filters/64a447c454eaac132a068d80","version":2}"#)
+                    .unwrap(),
+            ),
+            Message::Reconnect,
+        ];
+        let mut buf = BytesMut::new();
+        for msg in &messages {
+            SseEncoder::new()
+                .encode(Frame::from(msg), &mut buf)
+                .unwrap();
+        }
+        let mut decoder = SseDecoder::<BytesStr>::new();
+        let mut decoded = Vec::new();
+        while let Some(frame) = decoder.decode(&mut buf).unwrap() {
+            if let Frame::Event(event) = frame {
+                decoded.push(Message::try_from(event).unwrap());
+            }
+        }
+        assert_eq!(decoded, messages);
+    }
+
+    fn event(name: &'static str, data: &str) -> Event<BytesStr> {
+        Event {
+            id: None,
+            name: name.into(),
+            data: BytesStr::from(data.to_owned()),
+        }
+    }
+
+    #[test]
+    fn gates_protocol_versions_and_locates_errors() {
+        let v2 = event(
+            "delete",
+            r#"{"path":"/environments/62ea8c4afac9b011945f6791","version":7,"protocolVersion":2}"#,
+        );
+        assert!(matches!(
+            Message::try_from(v2.clone()),
+            Err(MessageParseError::UnsupportedProtocolVersion { version, .. })
+                if version == ProtocolVersion::new(2)
+        ));
+        let parser = MessageParser::new().max_protocol_version(ProtocolVersion::new(2));
+        assert!(matches!(parser.parse(v2), Ok(Message::Delete(_))));
+        assert!(matches!(
+            Message::try_from(event("Reconnect ", "{}")),
+            Ok(Message::Reconnect)
+        ));
+
+        let put = event(
+            "put",
+            &format!(
+                r#"{{"path":"/","data":{{"environments":{{"62ea8c4afac9b011945f6791":{}}}}}}}"#,
+                EnvironmentFixture::new().sdk_key("sdk-3d560391").to_json()
+            ),
+        );
+        match Message::try_from(put) {
+            Err(MessageParseError::InvalidPayload {
+                event, location, ..
+            }) => {
+                assert_eq!(event, "put");
+                assert_eq!(location, "data.environments.62ea8c4afac9b011945f6791");
+            }
+            other => panic!("expected an invalid payload error, got {:?}", other),
         }
     }
 }
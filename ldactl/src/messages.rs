@@ -1,14 +1,121 @@
-use crate::credential::{ClientSideId as EnvironmentId, MobileKey, ServerSideKey};
+use crate::credential::{
+    error::CredentialError, ClientSideId, Credential, LaunchDarklyCredential, MobileKey,
+    ServerSideKey,
+};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 
 use std::{
     collections::HashMap,
     fmt::{self, Display, Formatter},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+/// Id of an environment, which doubles as its client-side id
+///
+/// Keeps the two apart in the API, convert with `From` when the client-side id is what's
+/// needed, such as for a JavaScript SDK.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EnvironmentId(ClientSideId);
+
+impl EnvironmentId {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn client_side_id(&self) -> &ClientSideId {
+        &self.0
+    }
+}
+impl Display for EnvironmentId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl AsRef<str> for EnvironmentId {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+impl From<ClientSideId> for EnvironmentId {
+    fn from(id: ClientSideId) -> Self {
+        Self(id)
+    }
+}
+impl From<EnvironmentId> for ClientSideId {
+    fn from(id: EnvironmentId) -> Self {
+        id.0
+    }
+}
+impl TryFrom<String> for EnvironmentId {
+    type Error = CredentialError;
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        ClientSideId::try_from(id).map(Self)
+    }
+}
+impl TryFrom<&str> for EnvironmentId {
+    type Error = CredentialError;
+    fn try_from(id: &str) -> Result<Self, Self::Error> {
+        ClientSideId::try_from(id).map(Self)
+    }
+}
+impl FromStr for EnvironmentId {
+    type Err = CredentialError;
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Self::try_from(id)
+    }
+}
+
+/// Longest project or environment key accepted, see [`ProjectKey`]
+pub const MAX_KEY_LEN: usize = 256;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum InvalidKey {
+    #[error("key is empty")]
+    Empty,
+    #[error("key is {0} characters long, the limit is {MAX_KEY_LEN}")]
+    TooLong(usize),
+    #[error("invalid character {1:?} in key {0:?}")]
+    #[diagnostic(help("keys only contain letters, numbers, '.', '_' and '-'"))]
+    InvalidChar(String, char),
+    #[error("key {0:?} doesn't start with a letter or number")]
+    InvalidStart(String),
+}
+
+/// Checks a project or environment key against LaunchDarkly's key format
+fn validate_key(key: &str) -> Result<(), InvalidKey> {
+    let first = key.chars().next().ok_or(InvalidKey::Empty)?;
+    if !first.is_ascii_alphanumeric() {
+        return Err(InvalidKey::InvalidStart(key.to_owned()));
+    }
+    if let Some(c) = key
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+    {
+        return Err(InvalidKey::InvalidChar(key.to_owned(), c));
+    }
+    // only ascii is left, so the length in bytes is the number of characters
+    if key.len() > MAX_KEY_LEN {
+        return Err(InvalidKey::TooLong(key.len()));
+    }
+    Ok(())
+}
+
+/// Key of a project, such as `default`
+///
+/// Parsing validates the format, deserializing doesn't since the stream is the authority on
+/// which keys exist. Keys are compared as-is, use [`ProjectKey::eq_ignore_case`] for input
+/// typed by people.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ProjectKey(String);
 
+impl ProjectKey {
+    /// Whether `other` is the same key ignoring ASCII case
+    pub fn eq_ignore_case(&self, other: impl AsRef<str>) -> bool {
+        self.0.eq_ignore_ascii_case(other.as_ref())
+    }
+}
 impl Display for ProjectKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -19,10 +126,35 @@ impl AsRef<str> for ProjectKey {
         self.0.as_ref()
     }
 }
+impl TryFrom<String> for ProjectKey {
+    type Error = InvalidKey;
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        validate_key(&key)?;
+        Ok(Self(key))
+    }
+}
+impl FromStr for ProjectKey {
+    type Err = InvalidKey;
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        Self::try_from(key.to_owned())
+    }
+}
+impl From<ProjectKey> for String {
+    fn from(key: ProjectKey) -> Self {
+        key.0
+    }
+}
 
+/// Key of an environment within its project, such as `production`, see [`ProjectKey`]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct EnvironmentKey(String);
 
+impl EnvironmentKey {
+    /// Whether `other` is the same key ignoring ASCII case
+    pub fn eq_ignore_case(&self, other: impl AsRef<str>) -> bool {
+        self.0.eq_ignore_ascii_case(other.as_ref())
+    }
+}
 impl AsRef<str> for EnvironmentKey {
     fn as_ref(&self) -> &str {
         self.0.as_ref()
@@ -33,9 +165,160 @@ impl Display for EnvironmentKey {
         write!(f, "{}", self.0)
     }
 }
+impl TryFrom<String> for EnvironmentKey {
+    type Error = InvalidKey;
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        validate_key(&key)?;
+        Ok(Self(key))
+    }
+}
+impl FromStr for EnvironmentKey {
+    type Err = InvalidKey;
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        Self::try_from(key.to_owned())
+    }
+}
+impl From<EnvironmentKey> for String {
+    fn from(key: EnvironmentKey) -> Self {
+        key.0
+    }
+}
 
-type Version = u64;
-type UnixTimestamp = u64;
+/// Key of a payload filter, which limits the flags relay and SDKs receive for a project
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FilterKey(String);
+
+impl AsRef<str> for FilterKey {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+impl Display for FilterKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Id the stream uses for a payload filter, patches and deletes refer to filters by it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FilterId(String);
+
+impl AsRef<str> for FilterId {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+impl Display for FilterId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Version of an environment or payload filter, a message only replaces what's stored when its
+/// version is newer
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Version(u64);
+
+impl Version {
+    pub const fn new(version: u64) -> Self {
+        Self(version)
+    }
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+    /// Whether a message with this version replaces what's stored at `stored`
+    pub fn is_newer_than(self, stored: Version) -> bool {
+        self > stored
+    }
+}
+
+impl From<u64> for Version {
+    fn from(version: u64) -> Self {
+        Self(version)
+    }
+}
+impl From<Version> for u64 {
+    fn from(version: Version) -> Self {
+        version.0
+    }
+}
+impl PartialEq<u64> for Version {
+    fn eq(&self, other: &u64) -> bool {
+        self.0 == *other
+    }
+}
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+/// Point in time as the stream sends it, milliseconds since the unix epoch
+///
+/// Displays as RFC 3339, converts to and from [`SystemTime`] and, with the `time` feature, to
+/// and from `time::OffsetDateTime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_unix_millis(millis: u64) -> Self {
+        Self(millis)
+    }
+    pub const fn as_unix_millis(self) -> u64 {
+        self.0
+    }
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+    pub fn to_system_time(self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.0)
+    }
+    /// Time from `earlier` until this timestamp, zero if `earlier` is later
+    pub fn saturating_duration_since(self, earlier: Timestamp) -> Duration {
+        Duration::from_millis(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    /// Times before the unix epoch become the epoch
+    fn from(time: SystemTime) -> Self {
+        Self(
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        )
+    }
+}
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.to_system_time()
+    }
+}
+#[cfg(feature = "time")]
+impl From<Timestamp> for time::OffsetDateTime {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.to_system_time().into()
+    }
+}
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for Timestamp {
+    fn from(time: time::OffsetDateTime) -> Self {
+        SystemTime::from(time).into()
+    }
+}
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            humantime::format_rfc3339_millis(self.to_system_time())
+        )
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -53,11 +336,288 @@ pub struct EnvironmentConfig {
     pub version: Version,
 }
 
+/// A payload filter defined in a project, every environment in the project can be served
+/// filtered by its key
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadFilter {
+    pub proj_key: ProjectKey,
+    pub key: FilterKey,
+    pub version: Version,
+}
+
+/// Field of [`EnvironmentConfig`] that changed between two versions, see [`EnvironmentConfig::diff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EnvironmentField {
+    EnvKey,
+    EnvName,
+    MobKey,
+    ProjKey,
+    ProjName,
+    /// The current SDK key was rotated
+    SdkKey,
+    /// The previous SDK key started or stopped expiring, or its expiry changed
+    SdkKeyExpiring,
+    DefaultTtl,
+    SecureMode,
+    Version,
+}
+
+impl EnvironmentConfig {
+    /// Fields that differ from `previous`, a version-only bump returns just
+    /// [`EnvironmentField::Version`]
+    pub fn diff(&self, previous: &EnvironmentConfig) -> Vec<EnvironmentField> {
+        let mut changed = Vec::new();
+        let mut check = |differs: bool, field| {
+            if differs {
+                changed.push(field)
+            }
+        };
+        check(self.env_key != previous.env_key, EnvironmentField::EnvKey);
+        check(
+            self.env_name != previous.env_name,
+            EnvironmentField::EnvName,
+        );
+        check(self.mob_key != previous.mob_key, EnvironmentField::MobKey);
+        check(
+            self.proj_key != previous.proj_key,
+            EnvironmentField::ProjKey,
+        );
+        check(
+            self.proj_name != previous.proj_name,
+            EnvironmentField::ProjName,
+        );
+        check(
+            self.sdk_key.current != previous.sdk_key.current,
+            EnvironmentField::SdkKey,
+        );
+        check(
+            self.sdk_key.expiring != previous.sdk_key.expiring,
+            EnvironmentField::SdkKeyExpiring,
+        );
+        check(
+            self.default_ttl != previous.default_ttl,
+            EnvironmentField::DefaultTtl,
+        );
+        check(
+            self.secure_mode != previous.secure_mode,
+            EnvironmentField::SecureMode,
+        );
+        check(self.version != previous.version, EnvironmentField::Version);
+        changed
+    }
+
+    /// Hash of everything but the version, equal fingerprints mean nothing effectively changed
+    ///
+    /// Stable across runs and releases, so it can be stored and compared later.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.write(self.env_id.as_str());
+        fingerprint.write(self.env_key.as_ref());
+        fingerprint.write(&self.env_name);
+        fingerprint.write(self.mob_key.expose_secret());
+        fingerprint.write(self.proj_key.as_ref());
+        fingerprint.write(&self.proj_name);
+        fingerprint.write(self.sdk_key.current().expose_secret());
+        match self.sdk_key.expiring() {
+            Some(expiring) => {
+                fingerprint.write(expiring.value().expose_secret());
+                fingerprint.write(&expiring.expires_at_timestamp().as_unix_millis().to_string());
+            }
+            None => fingerprint.write(""),
+        }
+        fingerprint.write(&self.default_ttl.to_string());
+        fingerprint.write(if self.secure_mode { "1" } else { "0" });
+        fingerprint
+    }
+
+    /// Every credential of the environment: the sdk key, the expiring sdk key if there is one,
+    /// the mobile key and the client-side id
+    pub fn credentials(&self) -> impl Iterator<Item = Credential> + '_ {
+        std::iter::once(Credential::Server(self.sdk_key.current().clone()))
+            .chain(
+                self.sdk_key
+                    .expiring()
+                    .map(|expiring| Credential::Server(expiring.value().clone())),
+            )
+            .chain([
+                Credential::Mobile(self.mob_key.clone()),
+                Credential::Client(self.env_id.client_side_id().clone()),
+            ])
+    }
+}
+
+/// Hash of an environment's contents, see [`EnvironmentConfig::fingerprint`]
+///
+/// 64-bit FNV-1a, displayed and serialized as 16 hex digits. Not meant to resist tampering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    /// Hashes `field` with its length first, so moving bytes between fields changes the hash
+    fn write(&mut self, field: &str) {
+        for byte in (field.len() as u64)
+            .to_le_bytes()
+            .iter()
+            .chain(field.as_bytes())
+        {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Serializes like the wrapped value, but with sdk and mobile keys masked
+///
+/// For writing an inventory of environments that's safe to share, such as with
+/// `ldactl --redact`. Masked keys keep their prefix and last six characters so they can still
+/// be told apart, which also means they don't deserialize back.
+#[derive(Debug, Clone, Copy)]
+pub struct Redacted<'a, T: ?Sized>(pub &'a T);
+
+fn serialize_masked<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    T: Display,
+{
+    // Display masks secret credentials
+    serializer.collect_str(value)
+}
+
+// mirrors EnvironmentConfig, keep the fields in sync
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RedactedEnvironment<'a> {
+    env_id: &'a EnvironmentId,
+    env_key: &'a EnvironmentKey,
+    env_name: &'a str,
+    #[serde(serialize_with = "serialize_masked")]
+    mob_key: &'a MobileKey,
+    proj_key: &'a ProjectKey,
+    proj_name: &'a str,
+    sdk_key: RedactedSdkKey<'a>,
+    default_ttl: u64,
+    secure_mode: bool,
+    version: Version,
+}
+
+#[derive(Serialize)]
+struct RedactedSdkKey<'a> {
+    #[serde(serialize_with = "serialize_masked")]
+    value: &'a ServerSideKey,
+    expiring: Option<RedactedExpiring<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RedactedExpiring<'a> {
+    #[serde(serialize_with = "serialize_masked")]
+    value: &'a ServerSideKey,
+    expires_at: Timestamp,
+}
+
+impl Serialize for Redacted<'_, EnvironmentConfig> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let env = self.0;
+        RedactedEnvironment {
+            env_id: &env.env_id,
+            env_key: &env.env_key,
+            env_name: &env.env_name,
+            mob_key: &env.mob_key,
+            proj_key: &env.proj_key,
+            proj_name: &env.proj_name,
+            sdk_key: RedactedSdkKey {
+                value: env.sdk_key.current(),
+                expiring: env.sdk_key.expiring().map(|expiring| RedactedExpiring {
+                    value: expiring.value(),
+                    expires_at: expiring.expires_at_timestamp(),
+                }),
+            },
+            default_ttl: env.default_ttl,
+            secure_mode: env.secure_mode,
+            version: env.version,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for Redacted<'_, HashMap<EnvironmentId, EnvironmentConfig>> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            sorted_by_id(self.0)
+                .into_iter()
+                .map(|(env_id, env)| (env_id, Redacted(env))),
+        )
+    }
+}
+
+/// Serializes environments ordered by id instead of in hash order, so files written from them
+/// are the same from run to run and diff cleanly
+#[derive(Debug, Clone, Copy)]
+pub struct SortedEnvironments<'a>(pub &'a HashMap<EnvironmentId, EnvironmentConfig>);
+
+impl SortedEnvironments<'_> {
+    /// Combined [`EnvironmentConfig::fingerprint`] of every environment, so it also ignores
+    /// version bumps but changes when environments are added or removed
+    pub fn fingerprint(&self) -> Fingerprint {
+        let mut fingerprint = Fingerprint::new();
+        for (_, env) in sorted_by_id(self.0) {
+            fingerprint.write(&env.fingerprint().to_string());
+        }
+        fingerprint
+    }
+}
+
+impl Serialize for SortedEnvironments<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(sorted_by_id(self.0))
+    }
+}
+
+/// For `#[serde(serialize_with)]`, see [`SortedEnvironments`]
+pub(crate) fn serialize_sorted<S: serde::Serializer>(
+    environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    SortedEnvironments(environments).serialize(serializer)
+}
+
+fn sorted_by_id(
+    environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+) -> Vec<(&EnvironmentId, &EnvironmentConfig)> {
+    let mut sorted: Vec<_> = environments.iter().collect();
+    sorted.sort_unstable_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    sorted
+}
+
 fn deserialize_env_id_from_path<'de, D>(deserializer: D) -> Result<EnvironmentId, D::Error>
 where
     D: Deserializer<'de>,
 {
-    const PATH_PREFIX: &'static str = "/environments/";
+    const PATH_PREFIX: &str = "/environments/";
     let buf = String::deserialize(deserializer)?;
     let s = buf
         .strip_prefix(PATH_PREFIX)
@@ -71,6 +631,25 @@ where
     serializer.collect_str(&format_args!("/environments/{}", env_id))
 }
 
+pub(crate) const FILTERS_PATH_PREFIX: &str = "/filters/";
+
+fn deserialize_filter_id_from_path<'de, D>(deserializer: D) -> Result<FilterId, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buf = String::deserialize(deserializer)?;
+    match buf.strip_prefix(FILTERS_PATH_PREFIX) {
+        Some(id) if !id.is_empty() && !id.contains('/') => Ok(FilterId(id.to_owned())),
+        _ => Err(D::Error::custom("invalid path")),
+    }
+}
+fn serialize_filter_id_path<S>(filter_id: &FilterId, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_str(&format_args!("{}{}", FILTERS_PATH_PREFIX, filter_id))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PatchEvent {
     #[serde(
@@ -85,6 +664,9 @@ pub struct PatchEvent {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PutData {
     pub environments: HashMap<EnvironmentId, EnvironmentConfig>,
+    /// Missing unless the account uses payload filters
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub filters: HashMap<FilterId, PayloadFilter>,
 }
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PutEvent {
@@ -104,12 +686,39 @@ pub struct DeleteEvent {
     pub version: Version,
 }
 
+/// A `patch` event for a path under `/filters/`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FilterPatchEvent {
+    #[serde(
+        deserialize_with = "deserialize_filter_id_from_path",
+        serialize_with = "serialize_filter_id_path",
+        rename = "path"
+    )]
+    pub filter_id: FilterId,
+    #[serde(rename = "data")]
+    pub filter: PayloadFilter,
+}
+
+/// A `delete` event for a path under `/filters/`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterDeleteEvent {
+    #[serde(
+        deserialize_with = "deserialize_filter_id_from_path",
+        serialize_with = "serialize_filter_id_path",
+        rename = "path"
+    )]
+    pub filter_id: FilterId,
+    pub version: Version,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Message {
     Put(PutEvent),
     Patch(PatchEvent),
     Delete(DeleteEvent),
+    PatchFilter(FilterPatchEvent),
+    DeleteFilter(FilterDeleteEvent),
     Reconnect,
 }
 
@@ -125,13 +734,71 @@ pub struct Expirable<T> {
 #[serde(rename_all = "camelCase")]
 pub struct Expiring<T> {
     value: T,
-    expires_at: UnixTimestamp,
+    expires_at: Timestamp,
+}
+
+impl<T> Expirable<T> {
+    /// The value to hand out, it stays valid until it's rotated
+    ///
+    /// This is the one to use at any time: it's accepted as soon as it's issued and outlives
+    /// the one it replaced. Use [`Expirable::valid_at`] to also accept the previous value.
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+    /// The value that was rotated out, until the stream says it expired
+    pub fn expiring(&self) -> Option<&Expiring<T>> {
+        self.expiring.as_ref()
+    }
+    /// Whether the previous value is still accepted at `now`, so clients using it have yet to
+    /// switch to [`Expirable::current`]
+    pub fn is_rotation_pending(&self, now: SystemTime) -> bool {
+        self.expiring()
+            .is_some_and(|expiring| !expiring.is_expired(now))
+    }
+    /// Values accepted at `now`, the current one first
+    pub fn valid_at(&self, now: SystemTime) -> impl Iterator<Item = &T> {
+        std::iter::once(&self.current).chain(
+            self.expiring()
+                .filter(|expiring| !expiring.is_expired(now))
+                .map(Expiring::value),
+        )
+    }
+    /// When the previous value expires
+    pub(crate) fn expiring_at(&self) -> Option<Timestamp> {
+        self.expiring.as_ref().map(|expiring| expiring.expires_at)
+    }
+    /// Drops the previous value if it expired at or before `now`, returns whether it did
+    pub(crate) fn prune_expired(&mut self, now: Timestamp) -> bool {
+        match self.expiring_at() {
+            Some(expires_at) if expires_at <= now => {
+                self.expiring = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T> Expiring<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+    pub fn expires_at(&self) -> SystemTime {
+        self.expires_at.to_system_time()
+    }
+    pub fn expires_at_timestamp(&self) -> Timestamp {
+        self.expires_at
+    }
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at() <= now
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::credential::ClientSideId;
+    use crate::credential::CredentialKind;
+    use crate::test_util::EnvironmentFixture;
     #[test]
     fn derialize_put() {
         let _ev = "{\"path\":\"/\",\"data\":{\"environments\":{\"62ea8c4afac9b011945f6791\":{\"envId\":\"62ea8c4afac9b011945f6791\",\"envKey\":\"test\",\"envName\":\"Test\",\"mobKey\":\"mob-b5734766-5a3d-4b41-b63f-2669a4fb6497\",\"projName\":\"Default\",\"projKey\":\"default\",\"sdkKey\":{\"value\":\"sdk-3d560391-904c-4afd-8075-faad7652ed1d\"},\"defaultTtl\":0,\"secureMode\":false,\"version\":6},\"62ea8c4afac9b011945f6792\":{\"envId\":\"62ea8c4afac9b011945f6792\",\"envKey\":\"production\",\"envName\":\"Production\",\"mobKey\":\"mob-6a161a22-6395-4c29-a9cd-88d4b5bf74d6\",\"projName\":\"Default\",\"projKey\":\"default\",\"sdkKey\":{\"value\":\"sdk-011511cd-335b-47af-9e01-05a0daf1d71e\"},\"defaultTtl\":0,\"secureMode\":false,\"version\":14},\"64a447c454eaac132a068d75\":{\"envId\":\"64a447c454eaac132a068d75\",\"envKey\":\"production\",\"envName\":\"Production\",\"mobKey\":\"mob-aa46ddd0-5d78-44d5-9337-c6bbd9965feb\",\"projName\":\"Example project\",\"projKey\":\"example-project\",\"sdkKey\":{\"value\":\"sdk-6c596994-34d0-4137-84c6-bef64a1732d0\"},\"defaultTtl\":0,\"secureMode\":false,\"version\":20},\"64a447c454eaac132a068d76\":{\"envId\":\"64a447c454eaac132a068d76\",\"envKey\":\"test\",\"envName\":\"Test\",\"mobKey\":\"mob-ca268c40-7c6b-4b36-a30c-0f9e93b68751\",\"projName\":\"Example project\",\"projKey\":\"example-project\",\"sdkKey\":{\"value\":\"sdk-35cbaa92-d78a-4c5e-aecf-52de2933e289\"},\"defaultTtl\":0,\"secureMode\":false,\"version\":20}}}}";
@@ -159,10 +826,239 @@ mod tests {
         let ret = serde_json::from_str::<PutEvent>(s);
         assert!(ret.is_ok(), "{:?}", ret);
     }
+    #[test]
+    fn diffs_changed_fields() {
+        let previous = EnvironmentFixture::new().version(6).build();
+        let mut current = previous.clone();
+        current.version = Version::new(7);
+        assert_eq!(current.diff(&previous), [EnvironmentField::Version]);
+
+        current.sdk_key = serde_json::from_str(
+            r#"{"value":"sdk-011511cd-335b-47af-9e01-05a0daf1d71e","expiring":{"value":"sdk-3d560391-904c-4afd-8075-faad7652ed1d","expiresAt":1700000000000}}"#,
+        )
+        .unwrap();
+        current.env_name = "Testing".to_owned();
+        assert_eq!(
+            current.diff(&previous),
+            [
+                EnvironmentField::EnvName,
+                EnvironmentField::SdkKey,
+                EnvironmentField::SdkKeyExpiring,
+                EnvironmentField::Version
+            ]
+        );
+    }
+    #[test]
+    fn lists_credentials() {
+        let env = EnvironmentFixture::new()
+            .sdk_key("sdk-011511cd-335b-47af-9e01-05a0daf1d71e")
+            .expiring_sdk_key(
+                "sdk-3d560391-904c-4afd-8075-faad7652ed1d",
+                1_700_000_000_000,
+            )
+            .build();
+        let credentials: Vec<Credential> = env.credentials().collect();
+        let kinds: Vec<_> = credentials.iter().map(|c| c.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                CredentialKind::ServerSide,
+                CredentialKind::ServerSide,
+                CredentialKind::MobileKey,
+                CredentialKind::ClientSide
+            ]
+        );
+        assert_eq!(
+            credentials[1].expose_secret(),
+            "sdk-3d560391-904c-4afd-8075-faad7652ed1d"
+        );
+    }
+
+    #[test]
+    fn fingerprints_ignore_version() {
+        let env = EnvironmentFixture::new()
+            .sdk_key("sdk-011511cd-335b-47af-9e01-05a0daf1d71e")
+            .version(6)
+            .build();
+        let mut bumped = env.clone();
+        bumped.version = Version::new(7);
+        assert_eq!(env.fingerprint(), bumped.fingerprint());
+
+        let mut renamed = env.clone();
+        renamed.env_name = "Testing".to_owned();
+        assert_ne!(env.fingerprint(), renamed.fingerprint());
+        // must never change, fingerprints are stored
+        assert_eq!(env.fingerprint().to_string(), "29e58eb248a13afb");
+
+        let environments = HashMap::from([(env.env_id.clone(), env)]);
+        let bumped = HashMap::from([(bumped.env_id.clone(), bumped)]);
+        assert_eq!(
+            SortedEnvironments(&environments).fingerprint(),
+            SortedEnvironments(&bumped).fingerprint()
+        );
+        assert_ne!(
+            SortedEnvironments(&environments).fingerprint(),
+            SortedEnvironments(&HashMap::new()).fingerprint()
+        );
+    }
+
+    #[test]
+    fn serializes_environments_sorted() {
+        let environment = |env_id: &str| EnvironmentFixture::new().env_id(env_id).build();
+        let ids = [
+            "64a447c454eaac132a068d76",
+            "62ea8c4afac9b011945f6791",
+            "64a447c454eaac132a068d75",
+            "62ea8c4afac9b011945f6792",
+        ];
+        let environments: HashMap<EnvironmentId, EnvironmentConfig> = ids
+            .iter()
+            .map(|id| (id.parse().unwrap(), environment(id)))
+            .collect();
+        let json = serde_json::to_string(&SortedEnvironments(&environments)).unwrap();
+        let positions: Vec<usize> = ids.iter().map(|id| json.find(id).unwrap()).collect();
+        assert!(positions[1] < positions[3]);
+        assert!(positions[3] < positions[2]);
+        assert!(positions[2] < positions[0]);
+    }
+
+    #[test]
+    fn redacts_secret_keys() {
+        let env = EnvironmentFixture::new()
+            .sdk_key("sdk-011511cd-335b-47af-9e01-05a0daf1d71e")
+            .expiring_sdk_key(
+                "sdk-3d560391-904c-4afd-8075-faad7652ed1d",
+                1_700_000_000_000,
+            )
+            .build();
+        let redacted = serde_json::to_value(Redacted(&env)).unwrap();
+        let mut expected = serde_json::to_value(&env).unwrap();
+        expected["mobKey"] = "mob-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxfb6497".into();
+        expected["sdkKey"]["value"] = "sdk-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxf1d71e".into();
+        expected["sdkKey"]["expiring"]["value"] = "sdk-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxx52ed1d".into();
+        assert_eq!(redacted, expected);
+    }
+
+    #[test]
+    fn tracks_key_rotation() {
+        let sdk_key: Expirable<ServerSideKey> = serde_json::from_str(
+            r#"{"value":"sdk-011511cd-335b-47af-9e01-05a0daf1d71e","expiring":{"value":"sdk-3d560391-904c-4afd-8075-faad7652ed1d","expiresAt":1700000000000}}"#,
+        )
+        .unwrap();
+        let expiring = sdk_key.expiring().unwrap();
+        let expires_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(expiring.expires_at(), expires_at);
+
+        let before = expires_at - Duration::from_secs(1);
+        assert!(sdk_key.is_rotation_pending(before));
+        assert_eq!(
+            sdk_key.valid_at(before).collect::<Vec<_>>(),
+            [sdk_key.current(), expiring.value()]
+        );
+        assert!(!sdk_key.is_rotation_pending(expires_at));
+        assert_eq!(sdk_key.valid_at(expires_at).count(), 1);
+    }
+    #[test]
+    fn rejects_invalid_credentials() {
+        let err = serde_json::from_str::<EnvironmentConfig>(
+            &EnvironmentFixture::new()
+                .mob_key("sdk-b5734766-5a3d-4b41-b63f-2669a4fb6497")
+                .to_json(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("invalid credential prefix"),
+            "{err}"
+        );
+
+        assert!(serde_json::from_str::<EnvironmentId>(r#""not-an-env-id""#).is_err());
+    }
+
+    #[test]
+    fn deserializes_payload_filters() {
+        let put: PutEvent = serde_json::from_str(
+            r#"{"path":"/","data":{"environments":{},"filters":{"64a447c454eaac132a068d80":{"projKey":"default","key":"mobile-flags","version":2}}}}"#,
+        )
+        .unwrap();
+        let filter_id = FilterId("64a447c454eaac132a068d80".to_owned());
+        assert_eq!(put.data.filters[&filter_id].key.as_ref(), "mobile-flags");
+
+        let patch: FilterPatchEvent = serde_json::from_str(
+            r#"{"path":"/filters/64a447c454eaac132a068d80","data":{"projKey":"default","key":"mobile-flags","version":3}}"#,
+        )
+        .unwrap();
+        assert_eq!(patch.filter_id, filter_id);
+        assert_eq!(
+            serde_json::to_value(&patch).unwrap()["path"],
+            "/filters/64a447c454eaac132a068d80"
+        );
+        assert!(serde_json::from_str::<FilterDeleteEvent>(
+            r#"{"path":"/environments/62ea8c4afac9b011945f6791","version":3}"#
+        )
+        .is_err());
+    }
+    #[test]
+    fn validates_keys() {
+        let key: ProjectKey = "example-project".parse().unwrap();
+        assert!(key.eq_ignore_case("Example-Project"));
+        assert!("prod.eu_1".parse::<EnvironmentKey>().is_ok());
+
+        assert!(matches!("".parse::<ProjectKey>(), Err(InvalidKey::Empty)));
+        assert!(matches!(
+            "-prod".parse::<EnvironmentKey>(),
+            Err(InvalidKey::InvalidStart(_))
+        ));
+        assert!(matches!(
+            "prod env".parse::<EnvironmentKey>(),
+            Err(InvalidKey::InvalidChar(_, ' '))
+        ));
+        assert!(matches!(
+            "a".repeat(MAX_KEY_LEN + 1).parse::<ProjectKey>(),
+            Err(InvalidKey::TooLong(len)) if len == MAX_KEY_LEN + 1
+        ));
+        assert!(matches!(
+            format!("a{}", "é".repeat(MAX_KEY_LEN)).parse::<ProjectKey>(),
+            Err(InvalidKey::InvalidChar(_, 'é'))
+        ));
+    }
+
+    #[test]
+    fn versions_and_timestamps() {
+        assert!(Version::new(7).is_newer_than(Version::new(6)));
+        assert!(!Version::new(6).is_newer_than(Version::new(6)));
+        assert_eq!(Version::new(7).to_string(), "v7");
+
+        let timestamp: Timestamp = serde_json::from_str("1700000000000").unwrap();
+        assert_eq!(timestamp.to_string(), "2023-11-14T22:13:20.000Z");
+        assert_eq!(
+            Timestamp::from(timestamp.to_system_time()),
+            timestamp,
+            "round-trips through SystemTime"
+        );
+        assert_eq!(
+            timestamp.saturating_duration_since(Timestamp::from_unix_millis(1_699_999_999_000)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            Timestamp::from_unix_millis(0).saturating_duration_since(timestamp),
+            Duration::ZERO
+        );
+    }
+    #[test]
+    fn converts_environment_ids() {
+        let env_id: EnvironmentId = "62ea8c4afac9b011945f6791".parse().unwrap();
+        let client_side_id = ClientSideId::from(env_id.clone());
+        assert_eq!(client_side_id.as_str(), "62ea8c4afac9b011945f6791");
+        assert_eq!(EnvironmentId::from(client_side_id), env_id);
+        assert_eq!(
+            serde_json::to_string(&env_id).unwrap(),
+            r#""62ea8c4afac9b011945f6791""#
+        );
+    }
+
     #[test]
     fn test_deserialize_env_id_from_path() {
         use super::deserialize_env_id_from_path;
-        use crate::credential::ClientSideId as EnvironmentId;
         let mut d =
             serde_json::Deserializer::from_str("\"/environments/62ea8c4afac9b011945f6792\"");
         assert_eq!(
@@ -188,7 +1084,7 @@ mod tests {
 
         let mut w = std::io::BufWriter::new(Vec::new());
         let mut se = serde_json::Serializer::new(&mut w);
-        let result = super::serialize_env_id_path(&ClientSideId::try_from(env).unwrap(), &mut se);
+        let result = super::serialize_env_id_path(&EnvironmentId::try_from(env).unwrap(), &mut se);
         assert!(result.is_ok());
         assert_eq!(String::from_utf8(w.into_inner().unwrap()).unwrap(), path);
     }
@@ -0,0 +1,422 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::Write,
+    str::FromStr,
+};
+
+use miette::Diagnostic;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    credential::LaunchDarklyCredential,
+    messages::{EnvironmentConfig, EnvironmentId, PayloadFilter, Redacted, SortedEnvironments},
+};
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("unknown output format {0:?}")]
+#[diagnostic(help("use json, yaml, toml or dotenv"))]
+pub struct UnknownOutputFormat(String);
+
+/// How environments are written to a file, see [`OutputFormat::write`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Environments by id, the format `--offline` and `--bootstrap-file` read
+    #[default]
+    Json,
+    /// Same structure as [`OutputFormat::Json`]
+    Yaml,
+    /// A table per environment id, `null` values are left out
+    Toml,
+    /// `LD_<PROJ>_<ENV>_SDK_KEY=...` lines for the SDK key, mobile key and client-side id of
+    /// every environment, and `LD_<PROJ>_FILTER_KEYS=...` for projects with payload filters,
+    /// for shell scripts that can't parse json
+    Dotenv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = UnknownOutputFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "toml" => Ok(OutputFormat::Toml),
+            "dotenv" | "env" => Ok(OutputFormat::Dotenv),
+            _ => Err(UnknownOutputFormat(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Dotenv => "dotenv",
+        })
+    }
+}
+
+/// Options for [`OutputFormat::write`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    /// Mask SDK and mobile keys, see [`Redacted`]
+    pub redact: bool,
+    /// Write json on a single line instead of pretty printed, other formats ignore it
+    pub compact: bool,
+}
+
+impl OutputFormat {
+    /// Writes `environments` ordered by id
+    ///
+    /// Only [`OutputFormat::Dotenv`] includes `filters`, the other formats are read back as
+    /// environments by `--offline` and `--bootstrap-file`.
+    pub fn write<W: Write>(
+        self,
+        mut writer: W,
+        environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+        filters: &[PayloadFilter],
+        options: OutputOptions,
+    ) -> std::io::Result<()> {
+        let redact = options.redact;
+        if self == OutputFormat::Dotenv {
+            return write_dotenv(writer, environments, filters, redact);
+        }
+        if self == OutputFormat::Json {
+            match (redact, options.compact) {
+                (true, true) => serde_json::to_writer(&mut writer, &Redacted(environments))?,
+                (true, false) => {
+                    serde_json::to_writer_pretty(&mut writer, &Redacted(environments))?
+                }
+                (false, true) => {
+                    serde_json::to_writer(&mut writer, &SortedEnvironments(environments))?
+                }
+                (false, false) => {
+                    serde_json::to_writer_pretty(&mut writer, &SortedEnvironments(environments))?
+                }
+            }
+            if options.compact {
+                writeln!(writer)?;
+            }
+            return writer.flush();
+        }
+        if self == OutputFormat::Yaml {
+            if redact {
+                serde_yaml::to_writer(&mut writer, &Redacted(environments))
+            } else {
+                serde_yaml::to_writer(&mut writer, &SortedEnvironments(environments))
+            }
+            .map_err(std::io::Error::other)?;
+            return writer.flush();
+        }
+        let mut value = if redact {
+            serde_json::to_value(Redacted(environments))?
+        } else {
+            serde_json::to_value(SortedEnvironments(environments))?
+        };
+        // toml has no null
+        strip_nulls(&mut value);
+        let out = toml::to_string(&value).map_err(std::io::Error::other)?;
+        writer.write_all(out.as_bytes())?;
+        writer.flush()
+    }
+}
+
+fn write_dotenv<W: Write>(
+    mut writer: W,
+    environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+    filters: &[PayloadFilter],
+    redact: bool,
+) -> std::io::Result<()> {
+    for env in sorted_by_key(environments) {
+        let prefix = format!(
+            "LD_{}_{}",
+            env_var_name(env.proj_key.as_ref()),
+            env_var_name(env.env_key.as_ref())
+        );
+        let (sdk_key, mob_key) = if redact {
+            (env.sdk_key.current().to_string(), env.mob_key.to_string())
+        } else {
+            (
+                env.sdk_key.current().expose_secret().to_owned(),
+                env.mob_key.expose_secret().to_owned(),
+            )
+        };
+        writeln!(writer, "{prefix}_SDK_KEY={sdk_key}")?;
+        writeln!(writer, "{prefix}_MOBILE_KEY={mob_key}")?;
+        writeln!(writer, "{prefix}_CLIENT_SIDE_ID={}", env.env_id)?;
+    }
+    for (name, keys) in filter_key_vars(filters) {
+        writeln!(writer, "LD_{name}={keys}")?;
+    }
+    writer.flush()
+}
+
+/// `<PROJ>_FILTER_KEYS` and the comma separated keys of the payload filters in that project,
+/// for every project with filters, ordered by project key
+///
+/// Prefixed with `LD_` in [`OutputFormat::Dotenv`] and with the env prefix for hooks.
+pub fn filter_key_vars(filters: &[PayloadFilter]) -> Vec<(String, String)> {
+    let mut by_project: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for filter in filters {
+        by_project
+            .entry(filter.proj_key.as_ref())
+            .or_default()
+            .push(filter.key.as_ref());
+    }
+    by_project
+        .into_iter()
+        .map(|(proj_key, mut keys)| {
+            keys.sort_unstable();
+            (
+                format!("{}_FILTER_KEYS", env_var_name(proj_key)),
+                keys.join(","),
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("unknown relay config format {0:?}")]
+#[diagnostic(help("use file or env"))]
+pub struct UnknownRelayConfigFormat(String);
+
+/// Configuration for the LaunchDarkly Relay Proxy, see [`RelayConfigFormat::write`]
+///
+/// Each environment is named `<projKey>-<envKey>` and gets its SDK key, mobile key,
+/// client-side id, secure mode and TTL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RelayConfigFormat {
+    /// `[Environment "default-production"]` sections for `ld-relay --config`
+    #[default]
+    File,
+    /// `LD_ENV_default_production=sdk-...` variables for `ld-relay --from-env`
+    Env,
+}
+
+impl FromStr for RelayConfigFormat {
+    type Err = UnknownRelayConfigFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "file" | "ini" => Ok(RelayConfigFormat::File),
+            "env" => Ok(RelayConfigFormat::Env),
+            _ => Err(UnknownRelayConfigFormat(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for RelayConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RelayConfigFormat::File => "file",
+            RelayConfigFormat::Env => "env",
+        })
+    }
+}
+
+impl RelayConfigFormat {
+    /// Writes an environment for each of `environments`, ordered by project and environment key
+    pub fn write<W: Write>(
+        self,
+        mut writer: W,
+        environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+    ) -> std::io::Result<()> {
+        for env in sorted_by_key(environments) {
+            let name = format!("{}-{}", env.proj_key.as_ref(), env.env_key.as_ref());
+            let sdk_key = env.sdk_key.current().expose_secret();
+            let mob_key = env.mob_key.expose_secret();
+            match self {
+                RelayConfigFormat::File => {
+                    writeln!(writer, "[Environment {}]", quote(&name))?;
+                    writeln!(writer, "sdkKey = {}", quote(sdk_key))?;
+                    writeln!(writer, "mobileKey = {}", quote(mob_key))?;
+                    writeln!(writer, "envId = {}", quote(env.env_id.as_str()))?;
+                    writeln!(writer, "secureMode = {}", env.secure_mode)?;
+                    if env.default_ttl > 0 {
+                        writeln!(writer, "ttl = \"{}m\"", env.default_ttl)?;
+                    }
+                    writeln!(writer)?;
+                }
+                RelayConfigFormat::Env => {
+                    let name = name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+                    writeln!(writer, "LD_ENV_{name}={sdk_key}")?;
+                    writeln!(writer, "LD_MOBILE_KEY_{name}={mob_key}")?;
+                    writeln!(writer, "LD_CLIENT_SIDE_ID_{name}={}", env.env_id)?;
+                    writeln!(writer, "LD_SECURE_MODE_{name}={}", env.secure_mode)?;
+                    if env.default_ttl > 0 {
+                        writeln!(writer, "LD_TTL_{name}={}m", env.default_ttl)?;
+                    }
+                }
+            }
+        }
+        writer.flush()
+    }
+}
+
+fn sorted_by_key(
+    environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+) -> Vec<&EnvironmentConfig> {
+    let mut sorted: Vec<_> = environments.values().collect();
+    sorted.sort_unstable_by(|a, b| {
+        (a.proj_key.as_ref(), a.env_key.as_ref()).cmp(&(b.proj_key.as_ref(), b.env_key.as_ref()))
+    });
+    sorted
+}
+
+fn env_var_name(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// gcfg, which ld-relay reads its config file with, only knows the \\, \", \n, \t and \b escapes
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{8}' => quoted.push_str("\\b"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => {
+            items.retain(|value| !value.is_null());
+            items.iter_mut().for_each(strip_nulls);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::EnvironmentFixture;
+
+    fn environments() -> HashMap<EnvironmentId, EnvironmentConfig> {
+        // toml needs U+007F escaped, json doesn't
+        let env = EnvironmentFixture::new()
+            .env_key("prod-us")
+            .env_name("Production \"us\"\n\u{7f}")
+            .build();
+        HashMap::from([(env.env_id.clone(), env)])
+    }
+
+    fn render(format: OutputFormat, redact: bool) -> String {
+        let options = OutputOptions {
+            redact,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        format
+            .write(&mut out, &environments(), &[], options)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_every_format() {
+        assert_eq!(
+            render(OutputFormat::Dotenv, false),
+            "LD_DEFAULT_PROD_US_SDK_KEY=sdk-3d560391-904c-4afd-8075-faad7652ed1d\n\
+             LD_DEFAULT_PROD_US_MOBILE_KEY=mob-b5734766-5a3d-4b41-b63f-2669a4fb6497\n\
+             LD_DEFAULT_PROD_US_CLIENT_SIDE_ID=62ea8c4afac9b011945f6791\n"
+        );
+        assert!(!render(OutputFormat::Dotenv, true).contains("3d560391"));
+
+        let json: Value = serde_json::from_str(&render(OutputFormat::Json, false)).unwrap();
+        assert_eq!(json["62ea8c4afac9b011945f6791"]["envKey"], "prod-us");
+
+        let toml = render(OutputFormat::Toml, false);
+        assert!(toml.starts_with("[62ea8c4afac9b011945f6791]\n"));
+        let mut without_nulls = json.clone();
+        strip_nulls(&mut without_nulls);
+        assert_eq!(toml::from_str::<Value>(&toml).unwrap(), without_nulls);
+
+        let yaml = render(OutputFormat::Yaml, false);
+        assert_eq!(serde_yaml::from_str::<Value>(&yaml).unwrap(), json);
+        assert!(!render(OutputFormat::Yaml, true).contains("3d560391"));
+
+        let mut relay = Vec::new();
+        RelayConfigFormat::File
+            .write(&mut relay, &environments())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(relay).unwrap(),
+            "[Environment \"default-prod-us\"]\n\
+             sdkKey = \"sdk-3d560391-904c-4afd-8075-faad7652ed1d\"\n\
+             mobileKey = \"mob-b5734766-5a3d-4b41-b63f-2669a4fb6497\"\n\
+             envId = \"62ea8c4afac9b011945f6791\"\n\
+             secureMode = false\n\n"
+        );
+        let mut relay = Vec::new();
+        RelayConfigFormat::Env
+            .write(&mut relay, &environments())
+            .unwrap();
+        assert!(String::from_utf8(relay)
+            .unwrap()
+            .starts_with("LD_ENV_default_prod_us=sdk-3d560391-904c-4afd-8075-faad7652ed1d\n"));
+
+        let mut compact = Vec::new();
+        let options = OutputOptions {
+            compact: true,
+            ..Default::default()
+        };
+        OutputFormat::Json
+            .write(&mut compact, &environments(), &[], options)
+            .unwrap();
+        let compact = String::from_utf8(compact).unwrap();
+        assert_eq!(compact.lines().count(), 1);
+        assert!(compact.starts_with("{\"62ea8c4afac9b011945f6791\":{"));
+    }
+
+    #[test]
+    fn dotenv_includes_filter_keys() {
+        let filters: Vec<PayloadFilter> = serde_json::from_str(
+            r#"[
+                {"projKey":"default","key":"mobile","version":1},
+                {"projKey":"web-app","key":"frontend","version":2},
+                {"projKey":"default","key":"backend","version":1}
+            ]"#,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        OutputFormat::Dotenv
+            .write(
+                &mut out,
+                &environments(),
+                &filters,
+                OutputOptions::default(),
+            )
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with(
+            "LD_DEFAULT_PROD_US_CLIENT_SIDE_ID=62ea8c4afac9b011945f6791\n\
+             LD_DEFAULT_FILTER_KEYS=backend,mobile\n\
+             LD_WEB_APP_FILTER_KEYS=frontend\n"
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs::File;
+
+/// Mode and ownership for the files ldactl writes, which contain SDK keys
+///
+/// Unset fields keep the defaults of the temporary file the output is written to, which is
+/// `0600` and owned by the user running ldactl.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilePermissions {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl FilePermissions {
+    /// Applies to `file` before it's moved into place, so the output is never readable by
+    /// anyone it shouldn't be
+    pub fn apply(&self, file: &File) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some(mode) = self.mode {
+                file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+            }
+            if self.uid.is_some() || self.gid.is_some() {
+                std::os::unix::fs::fchown(file, self.uid, self.gid)?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = file;
+        Ok(())
+    }
+}
+
+/// Octal permissions such as `0640` or `640`
+pub fn parse_mode(value: &str) -> Result<u32, String> {
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("{value:?} is not an octal file mode like 0640")),
+    }
+}
+
+/// A user name from `/etc/passwd` or a numeric uid
+pub fn parse_user(value: &str) -> Result<u32, String> {
+    lookup_id("/etc/passwd", value).ok_or_else(|| format!("unknown user {value:?}"))
+}
+
+/// A group name from `/etc/group` or a numeric gid
+pub fn parse_group(value: &str) -> Result<u32, String> {
+    lookup_id("/etc/group", value).ok_or_else(|| format!("unknown group {value:?}"))
+}
+
+fn lookup_id(database: &str, value: &str) -> Option<u32> {
+    if let Ok(id) = value.parse() {
+        return Some(id);
+    }
+    let contents = std::fs::read_to_string(database).ok()?;
+    find_id(&contents, value)
+}
+
+// `name:password:id:...` lines, the format of both /etc/passwd and /etc/group
+fn find_id(contents: &str, name: &str) -> Option<u32> {
+    contents.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next()? != name {
+            return None;
+        }
+        fields.nth(1)?.parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modes_and_ids() {
+        assert_eq!(parse_mode("0640"), Ok(0o640));
+        assert_eq!(parse_mode("600"), Ok(0o600));
+        assert!(parse_mode("0800").is_err());
+        assert!(parse_mode("17777").is_err());
+
+        let passwd = "root:x:0:0:root:/root:/bin/bash\nldactl:x:999:999::/var/lib/ldactl:/usr/sbin/nologin\n";
+        assert_eq!(find_id(passwd, "ldactl"), Some(999));
+        assert_eq!(find_id(passwd, "nobody"), None);
+        assert_eq!(parse_user("1000"), Ok(1000));
+    }
+}
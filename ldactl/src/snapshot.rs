@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use miette::Diagnostic;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, instrument};
+
+use crate::messages::{serialize_sorted, EnvironmentConfig, EnvironmentId, Redacted};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum SnapshotError {
+    #[error("failed to access {}", .0.display())]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("failed to parse {}", .0.display())]
+    #[diagnostic(help(
+        "expected a file written by ldactl, delete a corrupted state file to start over with a full sync"
+    ))]
+    Parse(PathBuf, #[source] serde_json::Error),
+}
+
+/// Reads a json file such as a snapshot or the environments written by `--output-file`
+pub(crate) fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, SnapshotError> {
+    let file = std::fs::File::open(path).map_err(|e| SnapshotError::Io(path.to_owned(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| SnapshotError::Parse(path.to_owned(), e))
+}
+
+/// Writes to a temporary file first so readers never see a partially written `path`
+///
+/// The temporary file is created next to `path`, renaming across filesystems fails with
+/// EXDEV, and synced before it replaces `path` so a crash can't leave an empty file behind.
+/// `write` gets the temporary file, set its permissions there before it's moved into place.
+pub fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&mut std::fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    write(tmp.as_file_mut())?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Everything needed to pick the stream back up after a restart, see
+/// [`crate::AutoConfigClient::snapshot`] and [`crate::AutoConfigClient::restore`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// Ordered by id when serialized
+    #[serde(serialize_with = "serialize_sorted")]
+    pub environments: HashMap<EnvironmentId, EnvironmentConfig>,
+    /// Sent as `Last-Event-ID` when reconnecting
+    pub last_event_id: Option<String>,
+}
+
+/// Same layout as the snapshot with the keys masked, it can't be restored from
+impl Serialize for Redacted<'_, Snapshot> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RedactedSnapshot<'a> {
+            environments: Redacted<'a, HashMap<EnvironmentId, EnvironmentConfig>>,
+            last_event_id: &'a Option<String>,
+        }
+        RedactedSnapshot {
+            environments: Redacted(&self.0.environments),
+            last_event_id: &self.0.last_event_id,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Snapshot {
+    /// Reads a snapshot written by [`Snapshot::save`], returns `None` if the file doesn't exist
+    #[instrument(skip_all, fields(path=%path.as_ref().display()))]
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, SnapshotError> {
+        let snapshot: Self = match read_json(path.as_ref()) {
+            Ok(snapshot) => snapshot,
+            Err(SnapshotError::Io(_, e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("no state file found");
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        debug!(
+            environment_count = snapshot.environments.len(),
+            last_event_id = snapshot.last_event_id.as_deref(),
+            "loaded state file"
+        );
+        Ok(Some(snapshot))
+    }
+
+    /// Atomically replaces the file at `path`, see [`replace_file`]
+    #[instrument(skip_all, fields(path=%path.as_ref().display(), environment_count=self.environments.len()))]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        replace_file(path, |file| {
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()
+        })
+        .map_err(|e| SnapshotError::Io(path.to_owned(), e))?;
+        debug!("saved state file");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        assert_eq!(Snapshot::load(&path).unwrap(), None);
+
+        let snapshot = Snapshot {
+            environments: HashMap::new(),
+            last_event_id: Some("42".to_owned()),
+        };
+        snapshot.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap(), Some(snapshot));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::Parse(..))
+        ));
+    }
+}
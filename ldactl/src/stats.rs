@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use crate::{eventsource::EventSourceStats, messages::Message};
+
+/// Names of the metrics emitted when the `metrics` feature is enabled
+#[cfg(feature = "metrics")]
+pub(crate) mod names {
+    pub(crate) const MESSAGES: &str = "autoconfig_messages_total";
+    pub(crate) const MESSAGE_PROCESSING: &str = "autoconfig_message_processing_seconds";
+    pub(crate) const IGNORED_UPDATES: &str = "autoconfig_ignored_updates_total";
+    pub(crate) const PARSE_ERRORS: &str = "autoconfig_parse_errors_total";
+}
+
+/// Snapshot of counters kept by [`crate::AutoConfigClient`], see
+/// [`crate::AutoConfigClient::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AutoConfigStats {
+    pub puts: u64,
+    pub patches: u64,
+    pub deletes: u64,
+    /// Reconnects requested by the server
+    pub reconnects: u64,
+    /// Patches and deletes dropped because their version wasn't newer than the stored one
+    pub ignored_updates: u64,
+    /// Events that couldn't be parsed into a message
+    pub parse_errors: u64,
+    /// Time spent applying messages to the in-memory environments
+    pub processing_time: Duration,
+    /// Time spent applying the most recent message
+    pub last_processing_time: Option<Duration>,
+    /// Counters of the underlying connection, `None` for offline clients
+    pub event_source: Option<EventSourceStats>,
+}
+
+/// Keeps the counters behind [`AutoConfigStats`] and, with the `metrics` feature, emits them
+/// through the `metrics` facade as well
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder {
+    puts: u64,
+    patches: u64,
+    deletes: u64,
+    reconnects: u64,
+    ignored_updates: u64,
+    parse_errors: u64,
+    processing_time: Duration,
+    last_processing_time: Option<Duration>,
+}
+
+impl StatsRecorder {
+    /// Counts `msg` and how long it took to process since `started`
+    pub(crate) fn record_message(&mut self, msg: &Message, started: Instant) {
+        let elapsed = started.elapsed();
+        let (counter, kind) = match msg {
+            Message::Put(_) => (&mut self.puts, "put"),
+            Message::Patch(_) | Message::PatchFilter(_) => (&mut self.patches, "patch"),
+            Message::Delete(_) | Message::DeleteFilter(_) => (&mut self.deletes, "delete"),
+            Message::Reconnect => (&mut self.reconnects, "reconnect"),
+        };
+        *counter += 1;
+        self.processing_time += elapsed;
+        self.last_processing_time = Some(elapsed);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::increment_counter!(names::MESSAGES, "kind" => kind);
+            metrics::histogram!(names::MESSAGE_PROCESSING, elapsed, "kind" => kind);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = kind;
+    }
+
+    pub(crate) fn record_ignored_update(&mut self) {
+        self.ignored_updates += 1;
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter!(names::IGNORED_UPDATES);
+    }
+
+    pub(crate) fn record_parse_error(&mut self) {
+        self.parse_errors += 1;
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter!(names::PARSE_ERRORS);
+    }
+
+    pub(crate) fn snapshot(&self, event_source: Option<EventSourceStats>) -> AutoConfigStats {
+        AutoConfigStats {
+            puts: self.puts,
+            patches: self.patches,
+            deletes: self.deletes,
+            reconnects: self.reconnects,
+            ignored_updates: self.ignored_updates,
+            parse_errors: self.parse_errors,
+            processing_time: self.processing_time,
+            last_processing_time: self.last_processing_time,
+            event_source,
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::messages::{EnvironmentConfig, EnvironmentId};
+
+/// Where [`crate::AutoConfigClient`] keeps its environments, see
+/// [`crate::AutoConfigClient::with_store`]
+///
+/// The default is an in-memory `HashMap`. Other backends, such as an embedded database or a
+/// map shared with the rest of the service, can implement this instead. Methods can't fail, a
+/// backend that can should log the error and keep serving what it has. Environments are
+/// returned by value so backends don't need to keep them in memory.
+pub trait EnvironmentStore: Send {
+    fn get(&self, env_id: &EnvironmentId) -> Option<EnvironmentConfig>;
+
+    /// Inserts or replaces an environment, returning the one it replaced
+    fn upsert(
+        &mut self,
+        env_id: EnvironmentId,
+        environment: EnvironmentConfig,
+    ) -> Option<EnvironmentConfig>;
+
+    fn remove(&mut self, env_id: &EnvironmentId) -> Option<EnvironmentConfig>;
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (EnvironmentId, EnvironmentConfig)> + '_>;
+
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self) {
+        let env_ids: Vec<EnvironmentId> = self.iter().map(|(env_id, _)| env_id).collect();
+        for env_id in env_ids {
+            self.remove(&env_id);
+        }
+    }
+
+    /// Removes the environments `f` returns false for
+    fn retain(&mut self, f: &mut dyn FnMut(&EnvironmentConfig) -> bool) {
+        let env_ids: Vec<EnvironmentId> = self
+            .iter()
+            .filter(|(_, environment)| !f(environment))
+            .map(|(env_id, _)| env_id)
+            .collect();
+        for env_id in env_ids {
+            self.remove(&env_id);
+        }
+    }
+}
+
+impl EnvironmentStore for HashMap<EnvironmentId, EnvironmentConfig> {
+    fn get(&self, env_id: &EnvironmentId) -> Option<EnvironmentConfig> {
+        HashMap::get(self, env_id).cloned()
+    }
+
+    fn upsert(
+        &mut self,
+        env_id: EnvironmentId,
+        environment: EnvironmentConfig,
+    ) -> Option<EnvironmentConfig> {
+        self.insert(env_id, environment)
+    }
+
+    fn remove(&mut self, env_id: &EnvironmentId) -> Option<EnvironmentConfig> {
+        HashMap::remove(self, env_id)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (EnvironmentId, EnvironmentConfig)> + '_> {
+        Box::new(
+            HashMap::iter(self).map(|(env_id, environment)| (env_id.clone(), environment.clone())),
+        )
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn clear(&mut self) {
+        HashMap::clear(self)
+    }
+
+    fn retain(&mut self, f: &mut dyn FnMut(&EnvironmentConfig) -> bool) {
+        HashMap::retain(self, |_, environment| f(environment))
+    }
+}
+
+/// Environments that changed in a [`TrackedStore`] since they were last taken
+#[derive(Debug)]
+pub(crate) enum Changed {
+    Some(HashSet<EnvironmentId>),
+    All,
+}
+
+impl Default for Changed {
+    fn default() -> Self {
+        Changed::Some(HashSet::new())
+    }
+}
+
+/// Store that remembers which environments changed, so the map published to
+/// [`crate::AutoConfigClient::subscribe`] can be patched instead of rebuilt
+pub(crate) struct TrackedStore {
+    store: Box<dyn EnvironmentStore>,
+    changed: Changed,
+}
+
+impl TrackedStore {
+    pub(crate) fn new(store: Box<dyn EnvironmentStore>) -> Self {
+        Self {
+            store,
+            changed: Changed::All,
+        }
+    }
+
+    pub(crate) fn take_changed(&mut self) -> Changed {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn mark(&mut self, env_id: &EnvironmentId) {
+        if let Changed::Some(env_ids) = &mut self.changed {
+            env_ids.insert(env_id.clone());
+        }
+    }
+}
+
+impl EnvironmentStore for TrackedStore {
+    fn get(&self, env_id: &EnvironmentId) -> Option<EnvironmentConfig> {
+        self.store.get(env_id)
+    }
+
+    fn upsert(
+        &mut self,
+        env_id: EnvironmentId,
+        environment: EnvironmentConfig,
+    ) -> Option<EnvironmentConfig> {
+        self.mark(&env_id);
+        self.store.upsert(env_id, environment)
+    }
+
+    fn remove(&mut self, env_id: &EnvironmentId) -> Option<EnvironmentConfig> {
+        self.mark(env_id);
+        self.store.remove(env_id)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (EnvironmentId, EnvironmentConfig)> + '_> {
+        self.store.iter()
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn clear(&mut self) {
+        self.changed = Changed::All;
+        self.store.clear()
+    }
+
+    fn retain(&mut self, f: &mut dyn FnMut(&EnvironmentConfig) -> bool) {
+        self.changed = Changed::All;
+        self.store.retain(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::EnvironmentFixture;
+
+    /// Only implements the required methods, like an external backend would
+    #[derive(Default)]
+    struct VecStore(Vec<EnvironmentConfig>);
+
+    impl EnvironmentStore for VecStore {
+        fn get(&self, env_id: &EnvironmentId) -> Option<EnvironmentConfig> {
+            self.0.iter().find(|env| &env.env_id == env_id).cloned()
+        }
+
+        fn upsert(
+            &mut self,
+            env_id: EnvironmentId,
+            environment: EnvironmentConfig,
+        ) -> Option<EnvironmentConfig> {
+            let previous = self.remove(&env_id);
+            self.0.push(environment);
+            previous
+        }
+
+        fn remove(&mut self, env_id: &EnvironmentId) -> Option<EnvironmentConfig> {
+            let index = self.0.iter().position(|env| &env.env_id == env_id)?;
+            Some(self.0.remove(index))
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = (EnvironmentId, EnvironmentConfig)> + '_> {
+            Box::new(self.0.iter().map(|env| (env.env_id.clone(), env.clone())))
+        }
+    }
+
+    fn environment(env_id: &str, env_key: &str) -> EnvironmentConfig {
+        EnvironmentFixture::new()
+            .env_id(env_id)
+            .env_key(env_key)
+            .build()
+    }
+
+    #[test]
+    fn default_methods_use_required_ones() {
+        let mut store = VecStore::default();
+        assert!(store.is_empty());
+        for (env_id, env_key) in [
+            ("62ea8c4afac9b011945f6791", "production"),
+            ("5f6e2e6b9c2d1a0c3b4a5d6e", "test"),
+        ] {
+            let env = environment(env_id, env_key);
+            assert_eq!(store.upsert(env.env_id.clone(), env), None);
+        }
+        assert_eq!(store.len(), 2);
+
+        store.retain(&mut |env| env.env_key.as_ref() == "production");
+        assert_eq!(store.len(), 1);
+        let env_id = EnvironmentId::try_from("62ea8c4afac9b011945f6791").unwrap();
+        assert!(store.get(&env_id).is_some());
+
+        store.clear();
+        assert!(store.is_empty());
+    }
+}
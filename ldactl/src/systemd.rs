@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+/// sd_notify(3) without libsystemd, for running as a `Type=notify` service
+///
+/// Does nothing unless systemd passed `NOTIFY_SOCKET`.
+#[derive(Debug)]
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    /// Half of `WatchdogSec=`, how often the service has to check in
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let watchdog = watchdog_interval(
+            std::env::var("WATCHDOG_USEC").ok().as_deref(),
+            std::env::var("WATCHDOG_PID").ok().as_deref(),
+        );
+        match Self::connect(Path::new(&path), watchdog) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                warn!(error=%e, path=?path, "failed to connect to NOTIFY_SOCKET");
+                None
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect(path: &Path, watchdog: Option<Duration>) -> std::io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.connect_addr(&addr)?;
+            }
+            _ => socket.connect(path)?,
+        }
+        Ok(Self { socket, watchdog })
+    }
+
+    #[cfg(not(unix))]
+    fn connect(_path: &Path, _watchdog: Option<Duration>) -> std::io::Result<Self> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// How often [`Notifier::watchdog`] has to be called, if systemd is watching
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Sends newline separated `KEY=value` assignments, failures are only logged
+    pub fn notify(&self, state: &str) {
+        debug!(state, "notifying systemd");
+        #[cfg(unix)]
+        if let Err(e) = self.socket.send(state.as_bytes()) {
+            warn!(error=%e, "failed to notify systemd");
+        }
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.notify(&format!("READY=1\nSTATUS={status}"));
+    }
+
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={status}"));
+    }
+
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+}
+
+// systemd wants a keep-alive within WatchdogSec, sending at half of it leaves room for delays
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    match usec?.parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec) / 2),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let systemd = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier::connect(&path, None).unwrap();
+        notifier.ready("watching 2 environments");
+        let mut buf = [0; 64];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=watching 2 environments");
+
+        assert_eq!(
+            watchdog_interval(Some("30000000"), None),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(watchdog_interval(Some("0"), None), None);
+        let pid = std::process::id().to_string();
+        assert!(watchdog_interval(Some("30000000"), Some(&pid)).is_some());
+        assert_eq!(watchdog_interval(Some("30000000"), Some("1")), None);
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use miette::Diagnostic;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::messages::{EnvironmentConfig, EnvironmentId, SortedEnvironments};
+
+const NAME: &str = "template";
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum TemplateError {
+    #[error(transparent)]
+    Template(#[from] minijinja::Error),
+    #[error("failed to serialize the environments for the template")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// A text file rendered from the environments, such as an ld-relay config or an nginx map
+///
+/// Templates are Jinja, rendered with [minijinja](https://docs.rs/minijinja): `environments`
+/// is a list of the environments ordered by id, with the same fields as the `--output-file`
+/// json. On top of the builtin filters, `json` prints a value as json.
+///
+/// ```
+/// use launchdarkly_autoconfig::Template;
+///
+/// let template = Template::parse(
+///     "{% for env in environments -%}\n{{ env.envKey | upper }}={{ env.sdkKey.value }}\n{% endfor %}",
+/// )
+/// .unwrap();
+/// assert_eq!(template.render(&Default::default()).unwrap(), "");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Template {
+    env: minijinja::Environment<'static>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut env = minijinja::Environment::new();
+        // the template is the whole file, which usually ends with a newline
+        env.set_keep_trailing_newline(true);
+        env.add_filter("json", |value: minijinja::Value| {
+            serde_json::to_string(&value).map_err(|e| {
+                minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
+            })
+        });
+        env.add_template_owned(NAME, source.to_owned())?;
+        Ok(Self { env })
+    }
+
+    pub fn render(
+        &self,
+        environments: &HashMap<EnvironmentId, EnvironmentConfig>,
+    ) -> Result<String, TemplateError> {
+        let Value::Object(environments) = serde_json::to_value(SortedEnvironments(environments))?
+        else {
+            unreachable!("environments serialize to a map keyed by id")
+        };
+        let environments: Vec<Value> = environments.into_values().collect();
+        let template = self.env.get_template(NAME)?;
+        Ok(template.render(minijinja::context! { environments })?)
+    }
+}
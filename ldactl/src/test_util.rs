@@ -0,0 +1,88 @@
+//! Fixtures for testing code that handles environments
+//!
+//! [`EnvironmentFixture`] starts from a valid environment as LaunchDarkly sends it, so tests only
+//! spell out the fields they care about. See [`crate::eventsource::test_util`] for a scripted
+//! stream to send them from.
+use serde_json::{json, Value};
+
+use crate::messages::EnvironmentConfig;
+
+/// Builds an [`EnvironmentConfig`] or its json, defaults to the `test` environment of the
+/// `default` project at version 1
+#[derive(Debug, Clone)]
+pub struct EnvironmentFixture(Value);
+
+impl Default for EnvironmentFixture {
+    fn default() -> Self {
+        Self(json!({
+            "envId": "62ea8c4afac9b011945f6791",
+            "envKey": "test",
+            "envName": "Test",
+            "mobKey": "mob-b5734766-5a3d-4b41-b63f-2669a4fb6497",
+            "projName": "Default",
+            "projKey": "default",
+            "sdkKey": {"value": "sdk-3d560391-904c-4afd-8075-faad7652ed1d"},
+            "defaultTtl": 0,
+            "secureMode": false,
+            "version": 1
+        }))
+    }
+}
+
+impl EnvironmentFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.0[field] = value.into();
+        self
+    }
+
+    pub fn env_id(self, env_id: &str) -> Self {
+        self.set("envId", env_id)
+    }
+
+    pub fn env_key(self, env_key: &str) -> Self {
+        self.set("envKey", env_key)
+    }
+
+    pub fn env_name(self, env_name: &str) -> Self {
+        self.set("envName", env_name)
+    }
+
+    pub fn proj_key(self, proj_key: &str) -> Self {
+        self.set("projKey", proj_key)
+    }
+
+    /// Isn't checked until [`EnvironmentFixture::build`], so invalid keys can be tested too
+    pub fn mob_key(self, mob_key: &str) -> Self {
+        self.set("mobKey", mob_key)
+    }
+
+    /// Isn't checked until [`EnvironmentFixture::build`], so invalid keys can be tested too
+    pub fn sdk_key(self, sdk_key: &str) -> Self {
+        self.set("sdkKey", json!({ "value": sdk_key }))
+    }
+
+    /// An sdk key that's being rotated out, `expires_at` is in milliseconds since the epoch
+    pub fn expiring_sdk_key(mut self, sdk_key: &str, expires_at: u64) -> Self {
+        self.0["sdkKey"]["expiring"] = json!({ "value": sdk_key, "expiresAt": expires_at });
+        self
+    }
+
+    pub fn version(self, version: u64) -> Self {
+        self.set("version", version)
+    }
+
+    /// The environment as it appears in stream messages
+    pub fn to_json(&self) -> String {
+        self.0.to_string()
+    }
+
+    /// Panics if a field was set to something invalid, use [`EnvironmentFixture::to_json`] to
+    /// test those
+    pub fn build(&self) -> EnvironmentConfig {
+        serde_json::from_value(self.0.clone()).expect("fixture is a valid environment")
+    }
+}
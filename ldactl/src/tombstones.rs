@@ -0,0 +1,76 @@
+use std::{collections::HashMap, time::Duration};
+
+use tracing::trace;
+
+use crate::messages::{EnvironmentId, Timestamp, Version};
+
+/// How long deleted environments are remembered unless
+/// [`crate::AutoConfigClient::tombstone_retention`] says otherwise
+pub(crate) const DEFAULT_TOMBSTONE_RETENTION: Duration = Duration::from_secs(5 * 60);
+
+/// An environment that was deleted, kept around so a patch that arrives late can't bring it
+/// back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Tombstone {
+    pub(crate) version: Version,
+    pub(crate) deleted_at: Timestamp,
+}
+
+pub(crate) struct Tombstones {
+    retention: Duration,
+    entries: HashMap<EnvironmentId, Tombstone>,
+}
+
+impl Tombstones {
+    pub(crate) fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: Duration) {
+        self.retention = retention;
+        self.prune(Timestamp::now());
+    }
+
+    pub(crate) fn insert(&mut self, env_id: EnvironmentId, version: Version) {
+        let now = Timestamp::now();
+        self.prune(now);
+        if self.retention.is_zero() {
+            return;
+        }
+        let tombstone = self.entries.entry(env_id).or_insert(Tombstone {
+            version,
+            deleted_at: now,
+        });
+        if version.is_newer_than(tombstone.version) {
+            *tombstone = Tombstone {
+                version,
+                deleted_at: now,
+            };
+        }
+    }
+
+    /// The tombstone for `env_id` if it's still within the retention window
+    pub(crate) fn get(&mut self, env_id: &EnvironmentId) -> Option<Tombstone> {
+        self.prune(Timestamp::now());
+        self.entries.get(env_id).copied()
+    }
+
+    /// Forgets `env_id`, for when it's known to exist again
+    pub(crate) fn remove(&mut self, env_id: &EnvironmentId) {
+        self.entries.remove(env_id);
+    }
+
+    fn prune(&mut self, now: Timestamp) {
+        let retention = self.retention;
+        self.entries.retain(|env_id, tombstone| {
+            let keep = now.saturating_duration_since(tombstone.deleted_at) < retention;
+            if !keep {
+                trace!(%env_id, "dropping tombstone");
+            }
+            keep
+        });
+    }
+}
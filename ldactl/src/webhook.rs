@@ -0,0 +1,367 @@
+use std::time::Duration;
+
+use backoff::backoff::Backoff;
+use hmac::{Hmac, Mac};
+use launchdarkly_autoconfig::eventsource::is_retryable_status;
+use launchdarkly_autoconfig::{ConfigChangeEvent, SequencedChange};
+use miette::Diagnostic;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{StatusCode, Url};
+use sha2::Sha256;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, instrument, warn};
+
+/// Hex HMAC-SHA256 of the body keyed with `--webhook-secret`, prefixed with `sha256=`
+pub const SIGNATURE_HEADER: &str = "x-ldactl-signature";
+pub const EVENT_KIND_HEADER: &str = "x-ldactl-event";
+pub const SEQUENCE_HEADER: &str = "x-ldactl-sequence";
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum WebhookError {
+    #[error("failed to send change {sequence} to {url}")]
+    Request {
+        url: Url,
+        sequence: u64,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{url} responded to change {sequence} with {status}")]
+    Status {
+        url: Url,
+        sequence: u64,
+        status: StatusCode,
+    },
+}
+
+impl WebhookError {
+    /// Connection failures, timeouts and the statuses the stream would retry as well
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Request { source, .. } => !source.is_builder(),
+            Self::Status { status, .. } => is_retryable_status(*status),
+        }
+    }
+}
+
+/// Endpoint that change events are POSTed to as json
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: Url,
+    pub headers: HeaderMap,
+    /// Signs bodies in the [`SIGNATURE_HEADER`] so receivers can check they came from ldactl
+    pub secret: Option<String>,
+    /// Times a [retryable](WebhookError::is_retryable) failure is retried with exponential
+    /// backoff
+    pub retries: u32,
+    pub timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            headers: HeaderMap::new(),
+            secret: None,
+            retries: 0,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn headers(mut self, headers: impl IntoIterator<Item = (HeaderName, HeaderValue)>) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    pub fn secret(mut self, secret: Option<String>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// `sha256=<hex>` for the [`SIGNATURE_HEADER`]
+    pub fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("hmac accepts keys of any length");
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// Delivers `change_event`, retrying failures
+    #[instrument(skip(self, client, change_event), fields(url = %self.url, sequence = change_event.sequence))]
+    pub async fn deliver(
+        &self,
+        client: &reqwest::Client,
+        change_event: &SequencedChange,
+    ) -> Result<(), WebhookError> {
+        let body = serde_json::to_vec(change_event).expect("change events serialize to json");
+        let mut headers = self.headers.clone();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(
+            EVENT_KIND_HEADER,
+            HeaderValue::from_static(change_event.change.kind()),
+        );
+        headers.insert(SEQUENCE_HEADER, change_event.sequence.into());
+        if let Some(secret) = self.secret.as_ref() {
+            let signature = Self::sign(secret, &body);
+            headers.insert(
+                SIGNATURE_HEADER,
+                HeaderValue::try_from(signature).expect("hex is a valid header value"),
+            );
+        }
+        let mut backoff = backoff::ExponentialBackoffBuilder::new()
+            .with_initial_interval(INITIAL_RETRY_DELAY)
+            .with_max_interval(MAX_RETRY_DELAY)
+            .with_max_elapsed_time(None)
+            .build();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match self
+                .post(client, headers.clone(), body.clone(), change_event)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if attempts > self.retries || !error.is_retryable() {
+                return Err(error);
+            }
+            let delay = backoff.next_backoff().unwrap_or(MAX_RETRY_DELAY);
+            warn!(error=%error, attempts, ?delay, "webhook delivery failed, retrying");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn post(
+        &self,
+        client: &reqwest::Client,
+        headers: HeaderMap,
+        body: Vec<u8>,
+        change_event: &SequencedChange,
+    ) -> Result<(), WebhookError> {
+        let response = client
+            .post(self.url.clone())
+            .headers(headers)
+            .timeout(self.timeout)
+            .body(body)
+            .send()
+            .await
+            .map_err(|source| WebhookError::Request {
+                url: self.url.clone(),
+                sequence: change_event.sequence,
+                source,
+            })?;
+        let status = response.status();
+        debug!(%status, "webhook delivered");
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(WebhookError::Status {
+            url: self.url.clone(),
+            sequence: change_event.sequence,
+            status,
+        })
+    }
+}
+
+/// Delivers change events to a [`Webhook`] in the background, one at a time and in order
+#[derive(Debug)]
+pub struct WebhookSender {
+    tx: mpsc::UnboundedSender<SequencedChange>,
+    task: JoinHandle<()>,
+}
+
+impl WebhookSender {
+    pub fn spawn(webhook: Webhook, client: reqwest::Client) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<SequencedChange>();
+        let task = tokio::spawn(async move {
+            while let Some(change) = rx.recv().await {
+                // like hooks, a failed delivery doesn't stop the watch
+                if let Err(e) = webhook.deliver(&client, &change).await {
+                    warn!(error=%e, sequence = change.sequence, "webhook delivery failed");
+                }
+            }
+        });
+        Self { tx, task }
+    }
+
+    /// Queues `change`, skipping [`ConfigChangeEvent::Initialized`] like hooks do
+    pub fn send(&self, change: &SequencedChange) {
+        if matches!(change.change, ConfigChangeEvent::Initialized(_)) {
+            return;
+        }
+        let _ = self.tx.send(change.clone());
+    }
+
+    /// Waits for every queued change to be delivered
+    pub async fn finish(self) {
+        drop(self.tx);
+        let _ = self.task.await;
+    }
+}
+
+/// `Name: value` for [`Webhook::headers`]
+pub fn parse_header(value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, header_value) = value
+        .split_once(':')
+        .ok_or_else(|| format!("{value:?} isn't a `Name: value` header"))?;
+    let name = HeaderName::try_from(name.trim()).map_err(|e| format!("{name:?}: {e}"))?;
+    let header_value =
+        HeaderValue::try_from(header_value.trim()).map_err(|e| format!("{value:?}: {e}"))?;
+    Ok((name, header_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use launchdarkly_autoconfig::eventsource::test_util::{MockResponse, MockServer};
+    use launchdarkly_autoconfig::test_util::EnvironmentFixture;
+    use launchdarkly_autoconfig::AutoConfigClient;
+
+    /// `initialized` followed by an insert for each of `count` environments
+    async fn changes(count: usize) -> Vec<SequencedChange> {
+        let environments = (0..count)
+            .map(|i| {
+                let env = EnvironmentFixture::new()
+                    .env_id(&format!("62ea8c4afac9b011945f679{i}"))
+                    .build();
+                (env.env_id.clone(), env)
+            })
+            .collect();
+        let client = AutoConfigClient::offline(environments);
+        futures::pin_mut!(client);
+        let mut changes = Vec::new();
+        for _ in 0..=count {
+            changes.push(client.as_mut().next_sequenced().await.unwrap().unwrap());
+        }
+        changes
+    }
+
+    #[tokio::test]
+    async fn sends_headers_and_signature() {
+        let change = changes(1).await.remove(1);
+        let server = MockServer::start([MockResponse::status(204)]).await;
+        let webhook = Webhook::new(server.url())
+            .headers([parse_header("X-Team: infra").unwrap()])
+            .secret(Some("s3cret".to_owned()));
+        webhook
+            .deliver(&reqwest::Client::new(), &change)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.header("x-team"), Some("infra"));
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(request.header(EVENT_KIND_HEADER), Some("insert"));
+        assert_eq!(request.header(SEQUENCE_HEADER), Some("2"));
+        let body = serde_json::to_vec(&change).unwrap();
+        assert_eq!(
+            request.header(SIGNATURE_HEADER),
+            Some(Webhook::sign("s3cret", &body).as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_failures() {
+        let change = changes(1).await.remove(1);
+        let client = reqwest::Client::new();
+
+        let server =
+            MockServer::start([MockResponse::status(503), MockResponse::status(200)]).await;
+        let webhook = Webhook::new(server.url()).retries(1);
+        webhook.deliver(&client, &change).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+
+        let server =
+            MockServer::start([MockResponse::status(429), MockResponse::status(503)]).await;
+        let webhook = Webhook::new(server.url()).retries(1);
+        let e = webhook.deliver(&client, &change).await.unwrap_err();
+        assert!(matches!(
+            e,
+            WebhookError::Status { status, .. } if status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert_eq!(server.requests().len(), 2);
+
+        let server =
+            MockServer::start([MockResponse::status(400), MockResponse::status(200)]).await;
+        let webhook = Webhook::new(server.url()).retries(3);
+        assert!(webhook.deliver(&client, &change).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn classifies_errors() {
+        let url: Url = "http://localhost/".parse().unwrap();
+        let status = |status| WebhookError::Status {
+            url: url.clone(),
+            sequence: 1,
+            status,
+        };
+        assert!(status(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(status(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+        assert!(!status(StatusCode::BAD_REQUEST).is_retryable());
+        assert!(!status(StatusCode::UNAUTHORIZED).is_retryable());
+
+        let request = |source| WebhookError::Request {
+            url: url.clone(),
+            sequence: 1,
+            source,
+        };
+        let client = reqwest::Client::new();
+        // nothing listens on the port once the listener is dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let refused = client.post(closed).send().await.unwrap_err();
+        assert!(request(refused).is_retryable());
+        let invalid = client.post("not a url").send().await.unwrap_err();
+        assert!(!request(invalid).is_retryable());
+    }
+
+    #[tokio::test]
+    async fn sender_delivers_in_order() {
+        let changes = changes(3).await;
+        let server = MockServer::start(std::iter::repeat(MockResponse::status(200))).await;
+        let sender = WebhookSender::spawn(Webhook::new(server.url()), reqwest::Client::new());
+        for change in &changes {
+            sender.send(change);
+        }
+        sender.finish().await;
+        // `initialized` isn't delivered
+        let sequences: Vec<_> = server
+            .requests()
+            .iter()
+            .map(|request| request.header(SEQUENCE_HEADER).unwrap().to_owned())
+            .collect();
+        assert_eq!(sequences, ["2", "3", "4"]);
+    }
+
+    #[test]
+    fn signs_bodies() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            Webhook::sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let (name, value) = parse_header("Authorization: Bearer abc").unwrap();
+        assert_eq!(name, "authorization");
+        assert_eq!(value, "Bearer abc");
+        assert!(parse_header("Authorization").is_err());
+    }
+}
@@ -1,13 +1,10 @@
 use super::errors::DecodeUtf8Error;
 use bytes::Buf;
-// We only support UTF-8 in this house
-const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
 
 pub(crate) trait BufExt: Buf {
     fn bump(&mut self);
     fn bump_if(&mut self, byte: u8);
     fn find_byte(&self, byte: u8) -> Option<usize>;
-    fn strip_utf8_bom(&mut self);
 }
 pub(crate) trait BufMutExt: Buf {
     fn rbump_if(&mut self, byte: u8);
@@ -42,14 +39,6 @@ where
         // TODO: make this optionally use burntsushi/memchr
         self.as_ref().iter().position(|b| *b == byte)
     }
-
-    /// Advances the buffer cursor past the UTF-8 BOM if it exists
-    #[inline]
-    fn strip_utf8_bom(&mut self) {
-        if self.as_ref().starts_with(UTF8_BOM) {
-            self.advance(UTF8_BOM.len());
-        }
-    }
 }
 
 impl BufMutExt for bytes::BytesMut {
@@ -139,19 +128,4 @@ mod tests {
         let pos = bytes.find_byte(b'X');
         assert_eq!(pos, None);
     }
-    #[test]
-    fn strip_utf8_bom() {
-        //0xEF, 0xBB, 0xBF
-        let mut bytes = Bytes::from_static(b"\xEF\xBB\xBFHello, world!");
-        bytes.strip_utf8_bom();
-        println!("BOM: {:?}", super::UTF8_BOM);
-        assert_eq!(bytes.as_ref(), b"Hello, world!");
-    }
-    #[test]
-    fn strip_utf8_bom_slice() {
-        //0xEF, 0xBB, 0xBF
-        let mut bytes = b"\xEF\xBB\xBFHello, world!".as_ref();
-        bytes.strip_utf8_bom();
-        assert_eq!(bytes, b"Hello, world!");
-    }
 }
@@ -42,6 +42,28 @@ impl BytesStr {
     }
 }
 
+impl From<String> for BytesStr {
+    fn from(value: String) -> Self {
+        Self {
+            inner: bytes::Bytes::from(value),
+        }
+    }
+}
+
+impl From<&'static str> for BytesStr {
+    fn from(value: &'static str) -> Self {
+        Self {
+            inner: bytes::Bytes::from_static(value.as_bytes()),
+        }
+    }
+}
+
+impl std::fmt::Display for BytesStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&**self, f)
+    }
+}
+
 impl Borrow<bytes::Bytes> for BytesStr {
     fn borrow(&self) -> &bytes::Bytes {
         &self.inner
@@ -163,6 +163,15 @@ impl<T> SseDecoder<T> {
     pub fn reset(&mut self) {
         self.inner.reset()
     }
+
+    /// Prepares the decoder to read a new connection to the same stream
+    ///
+    /// As required by the spec, a partially received event is discarded but the last event id
+    /// is kept, so events without an `id` field keep inheriting it after reconnecting.
+    /// Buffer capacity and the maximum buffer size are kept too.
+    pub fn reconnect(&mut self) {
+        self.inner.reconnect()
+    }
 }
 
 impl<T> Decoder for SseDecoder<T>
@@ -225,6 +234,26 @@ mod test {
         );
     }
     #[test]
+    fn test_reconnect_keeps_event_id() {
+        let mut bytes = BytesMut::from(b"id: 1\ndata: one\n\nevent: foo\ndata: partial\n".as_ref());
+        let mut decoder = SseDecoder::with_max_size(64);
+        let _ = decoder.decode(&mut bytes).unwrap().unwrap();
+        assert!(decoder.decode(&mut bytes).unwrap().is_none());
+
+        decoder.reconnect();
+        assert_eq!(decoder.current_event_type(), "message");
+        assert_eq!(decoder.max_buf_size(), 64);
+        let mut bytes = BytesMut::from(b"data: two\n\n".as_ref());
+        assert_eq!(
+            decoder.decode(&mut bytes).unwrap().unwrap(),
+            Frame::Event(Event {
+                id: Some("1".into()),
+                name: "message".into(),
+                data: "two".into()
+            })
+        );
+    }
+    #[test]
     fn test_current_event_type() {
         let mut bytes = BytesMut::from(b"event: foo\ndata: bar\nevent: baz\n".as_ref());
         let mut decoder = SseDecoder::default();
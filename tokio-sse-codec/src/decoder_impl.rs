@@ -143,6 +143,14 @@ impl SseDecoderImpl {
         self.is_closed = false;
     }
 
+    /// Discards the partially received event but keeps the event id and buffer capacity
+    pub fn reconnect(&mut self) {
+        self.data_buf.clear();
+        self.event_type = Cow::Borrowed(MESSAGE_EVENT);
+        self.field_decoder = FieldDecoder::with_max_buf_size(self.max_buf_len);
+        self.is_closed = false;
+    }
+
     /// Clear internal buffers after closing to allow re-use via [`SseDecoder::into_parts`]
     fn close(&mut self) {
         self.reset();
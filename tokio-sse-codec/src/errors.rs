@@ -44,9 +44,7 @@ impl From<SseDecodeError> for std::io::Error {
             }
 
             SseDecodeError::Utf8Error(_) => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
-            SseDecodeError::ExceededSizeLimit(..) => {
-                std::io::Error::new(std::io::ErrorKind::Other, e)
-            }
+            SseDecodeError::ExceededSizeLimit(..) => std::io::Error::other(e),
         }
     }
 }
@@ -126,7 +124,7 @@ impl SourceCode for DecodeUtf8ErrorInner {
     ) -> Result<Box<dyn miette::SpanContents<'a> + 'a>, miette::MietteError> {
         let buf = self
             .valid_str()
-            .ok_or_else(|| miette::MietteError::OutOfBounds)?;
+            .ok_or(miette::MietteError::OutOfBounds)?;
 
         <str as SourceCode>::read_span(buf, span, context_lines_before, context_lines_after)
     }
@@ -244,7 +242,6 @@ impl Diagnostic for DecodeUtf8Error {
     code(tokio_sse_codec::decoder::exceeded_size_limit),
     url(docsrs)
 )]
-
 /// Error indicating that the incoming data exceeded the set buffer size limit.
 pub struct ExceededSizeLimitError {
     limit: usize,